@@ -0,0 +1,491 @@
+use crate::replay::{self, Analyzer, Game};
+use crate::research::{ResearchEvent, ResearchTracker};
+use crate::units::UnitCatalog;
+use serde::Serialize;
+use uncage::model::{
+    ActionEntity, BuildingEntity, BuildingEntityFields, Entity, MissileEntity, WorldFields,
+    WorldNavigation,
+};
+use uncage_client::pb;
+use uncage_client::pb::command::Command;
+use uncage_model::patcher::PatcherSelectorMatch;
+use uncage_model::{ModelWithDocument, Selector};
+
+const CREATED_ENTITIES: usize = 0;
+const BUILT: usize = 1;
+const RESEARCH_PROGRESS: usize = 2;
+const RESEARCHED: usize = 3;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize)]
+pub enum BuildKind {
+    Building,
+    Unit,
+    Technology,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BuildStep {
+    pub player: i32,
+    pub kind: BuildKind,
+    /// Master id of buildings and units, technology id of technologies
+    pub id: i32,
+    pub name: Option<String>,
+    /// When the command was issued, if one was seen
+    pub ordered: Option<u32>,
+    /// When the foundation was placed or the research started
+    pub started: Option<u32>,
+    /// When the building was finished, the unit came out or the research was done
+    pub completed: Option<u32>,
+    pub entity_id: Option<i32>,
+}
+
+impl BuildStep {
+    /// The earliest time known of the step
+    pub fn time(&self) -> u32 {
+        self.ordered
+            .or(self.started)
+            .or(self.completed)
+            .unwrap_or(0)
+    }
+}
+
+/// Reconstructs the build order of every player from build, train and research commands, new
+/// buildings and units in `World.entities` and the progress of `Player.research_states`. Queued
+/// units that never come out stay without a completion time
+#[derive(Debug)]
+pub struct BuildOrder {
+    catalog: UnitCatalog,
+    research: ResearchTracker,
+    steps: Vec<BuildStep>,
+}
+
+impl BuildOrder {
+    /// Names of buildings and units are taken from `catalog`
+    pub fn new(catalog: UnitCatalog) -> BuildOrder {
+        BuildOrder {
+            catalog,
+            research: ResearchTracker::new(),
+            steps: vec![],
+        }
+    }
+
+    pub fn steps(&self) -> &[BuildStep] {
+        &self.steps
+    }
+
+    /// Steps of the player in the order they were first seen
+    pub fn player_steps(&self, player: i32) -> Vec<&BuildStep> {
+        let mut steps = self
+            .steps
+            .iter()
+            .filter(|x| x.player == player)
+            .collect::<Vec<_>>();
+        steps.sort_by_key(|x| x.time());
+        steps
+    }
+
+    fn order(&mut self, game: &Game, player: i32, kind: BuildKind, id: i32, time: u32) {
+        let name = self.name(game, kind, id);
+        self.steps.push(BuildStep {
+            player,
+            kind,
+            id,
+            name,
+            ordered: Some(time),
+            started: None,
+            completed: None,
+            entity_id: None,
+        });
+    }
+
+    fn name(&self, game: &Game, kind: BuildKind, id: i32) -> Option<String> {
+        match kind {
+            BuildKind::Technology => replay::world(game)?
+                .technologies()
+                .get(id)
+                .map(|x| x.name.clone()),
+            _ => self.catalog.name(id as i16).map(String::from),
+        }
+    }
+
+    /// The oldest step of the player that `pending` accepts, or a new one if there's none
+    fn pending<P: Fn(&BuildStep) -> bool>(
+        &mut self,
+        game: &Game,
+        player: i32,
+        kind: BuildKind,
+        id: i32,
+        pending: P,
+    ) -> &mut BuildStep {
+        let index = self
+            .steps
+            .iter()
+            .position(|x| x.player == player && x.kind == kind && x.id == id && pending(x));
+
+        let index = match index {
+            Some(index) => index,
+            None => {
+                let name = self.name(game, kind, id);
+                self.steps.push(BuildStep {
+                    player,
+                    kind,
+                    id,
+                    name,
+                    ordered: None,
+                    started: None,
+                    completed: None,
+                    entity_id: None,
+                });
+                self.steps.len() - 1
+            }
+        };
+
+        &mut self.steps[index]
+    }
+
+    fn entity_created(&mut self, game: &Game, time: u32, id: i32) {
+        let Some(entity) = replay::entity(game, id) else {
+            return;
+        };
+
+        // The selector matches every field assigned while the entity is created
+        let player = entity.owner_id as i32;
+        let master_id = entity.master_id as i32;
+        if player <= 0 || self.steps.iter().any(|x| x.entity_id == Some(id)) {
+            return;
+        }
+
+        if let Some(building) = entity.cast::<BuildingEntity>() {
+            let step = self.pending(game, player, BuildKind::Building, master_id, |x| {
+                x.entity_id.is_none()
+            });
+            step.started = Some(time);
+            step.entity_id = Some(id);
+            if building.built {
+                step.completed = Some(time);
+            }
+        } else if entity.cast::<ActionEntity>().is_some()
+            && entity.cast::<MissileEntity>().is_none()
+        {
+            let pending = self.steps.iter_mut().find(|x| {
+                x.player == player
+                    && x.kind == BuildKind::Unit
+                    && x.id == master_id
+                    && x.entity_id.is_none()
+            });
+
+            // Units that weren't queued, like the starting ones, aren't part of the build order
+            if let Some(step) = pending {
+                step.completed = Some(time);
+                step.entity_id = Some(id);
+            }
+        }
+    }
+
+    fn building_changed(&mut self, game: &Game, time: u32, object_id: usize) {
+        let Some(building) = game.by_id(object_id) else {
+            return;
+        };
+
+        let (Some(entity), Some(true)) = (
+            building.cast_ref::<Entity>(),
+            building.cast_ref::<BuildingEntity>().map(|x| x.built),
+        ) else {
+            return;
+        };
+
+        let step = self
+            .steps
+            .iter_mut()
+            .find(|x| x.entity_id == Some(entity.id) && x.kind == BuildKind::Building);
+        if let Some(step) = step.filter(|x| x.completed.is_none()) {
+            step.completed = Some(time);
+        }
+    }
+
+    fn research_changed(&mut self, game: &Game, time: u32, selected: &PatcherSelectorMatch) {
+        for event in self.research.changed(game, selected) {
+            match event {
+                ResearchEvent::Started { player, tech_id } => {
+                    let step = self.pending(game, player, BuildKind::Technology, tech_id, |x| {
+                        x.started.is_none() && x.completed.is_none()
+                    });
+                    step.started = Some(time);
+                }
+                ResearchEvent::Completed {
+                    player, tech_id, ..
+                } => {
+                    let step = self.pending(game, player, BuildKind::Technology, tech_id, |x| {
+                        x.completed.is_none()
+                    });
+                    step.completed = Some(time);
+                }
+            }
+        }
+    }
+}
+
+impl Analyzer for BuildOrder {
+    fn before_patch(&mut self, frame: &pb::Frame, game: &Game) {
+        for command in frame.command.iter().filter_map(|x| x.command.as_ref()) {
+            let time = frame.time;
+            match command {
+                Command::Build(x) => {
+                    let player = x.unit_player_id as i32;
+                    self.order(game, player, BuildKind::Building, x.obj_id, time);
+                }
+                Command::BuildWall(x) => {
+                    let player = x.unit_player_id as i32;
+                    self.order(game, player, BuildKind::Building, x.obj_id, time);
+                }
+                Command::Make(x) => {
+                    let player = x.unit_player_id as i32;
+                    self.order(game, player, BuildKind::Unit, x.obj_id, time);
+                }
+                Command::Queue(x) => {
+                    let Some(player) = replay::owner_of(game, x.building_id) else {
+                        continue;
+                    };
+
+                    for _ in 0..x.train_count {
+                        self.order(game, player, BuildKind::Unit, x.train_id, time);
+                    }
+                }
+                Command::MultiQueue(x) => {
+                    let player = x.player_id as i32;
+                    let count = usize::try_from(x.train_count).unwrap_or(0);
+                    for _ in 0..count * x.building_ids.len() {
+                        self.order(game, player, BuildKind::Unit, x.train_id, time);
+                    }
+                }
+                Command::Research(x) => {
+                    let player = x.unit_player_id as i32;
+                    self.order(game, player, BuildKind::Technology, x.tech_id, time);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn selectors(&self) -> Vec<Selector> {
+        // In the order of CREATED_ENTITIES, BUILT, RESEARCH_PROGRESS and RESEARCHED
+        let [research_progress, researched] = ResearchTracker::selectors();
+        vec![
+            Selector::new().created().field(WorldFields::Entities),
+            Selector::new().field(BuildingEntityFields::Built),
+            research_progress,
+            researched,
+        ]
+    }
+
+    fn selected(
+        &mut self,
+        frame: &pb::Frame,
+        game: &Game,
+        index: usize,
+        selected: &PatcherSelectorMatch,
+    ) {
+        match index {
+            CREATED_ENTITIES => {
+                if let Some(id) = replay::path_key(&selected.path, WorldFields::Entities) {
+                    self.entity_created(game, frame.time, id);
+                }
+            }
+            BUILT => self.building_changed(game, frame.time, selected.object_id),
+            RESEARCH_PROGRESS | RESEARCHED => self.research_changed(game, frame.time, selected),
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::PatchBuilder;
+    use crate::units::UnitInfo;
+    use crate::Replay;
+    use uncage::model::{
+        BuildingEntity, BuildingEntityFields, CombatEntity, PlayerFields, ResearchState,
+        ResearchStateFields, RootFields, Technology, TechnologyFields, WorldFields,
+    };
+    use uncage_client::pb::command;
+
+    fn frame(time: u32, patch: &PatchBuilder, commands: Vec<Command>) -> pb::Frame {
+        pb::Frame {
+            command: commands
+                .into_iter()
+                .map(|x| pb::Command { command: Some(x) })
+                .collect(),
+            ..patch.frame(time)
+        }
+    }
+
+    #[test]
+    fn test_build_order() {
+        let mut catalog = UnitCatalog::new();
+        for (id, name) in [(70, "House"), (83, "Villager")] {
+            let name = name.to_string();
+            catalog.insert(
+                id,
+                UnitInfo {
+                    name,
+                    ..Default::default()
+                },
+            );
+        }
+
+        let mut order = BuildOrder::new(catalog);
+        let mut replay = Replay::new();
+        replay.add(&mut order);
+
+        let mut patch = PatchBuilder::world();
+        patch
+            .create_key::<Technology, _>(WorldFields::Technologies, 22)
+            .set(TechnologyFields::Name, "Loom".to_string())
+            .pop();
+        patch.player(1, "Ana").pop();
+        // Starting villager
+        patch.create_entity::<CombatEntity>(40, 83, 1).pop();
+        patch.pop();
+        replay.apply(&patch.frame(0)).unwrap();
+
+        let build = Command::Build(command::Build {
+            unit_player_id: 1,
+            obj_id: 70,
+            unit_ids: vec![40],
+            ..Default::default()
+        });
+        replay
+            .apply(&frame(1_000, &PatchBuilder::new(), vec![build]))
+            .unwrap();
+
+        let mut patch = PatchBuilder::new();
+        patch.push(RootFields::World);
+        patch.create_entity::<BuildingEntity>(50, 70, 1).pop().pop();
+        replay.apply(&patch.frame(2_000)).unwrap();
+
+        let mut patch = PatchBuilder::new();
+        patch
+            .push(RootFields::World)
+            .push_key(WorldFields::Entities, 50);
+        patch.set(BuildingEntityFields::Built, true).pop().pop();
+        let queue = Command::Queue(command::Queue {
+            building_id: 50,
+            train_id: 83,
+            train_count: 1,
+        });
+        replay.apply(&frame(5_000, &patch, vec![queue])).unwrap();
+
+        let mut patch = PatchBuilder::new();
+        patch.push(RootFields::World);
+        patch.create_entity::<CombatEntity>(51, 83, 1).pop();
+        patch
+            .push_key(WorldFields::Players, 1)
+            .create_key::<ResearchState, _>(PlayerFields::ResearchStates, 22)
+            .set(ResearchStateFields::ResearchDone, 1.0f32)
+            .pop()
+            .pop()
+            .pop();
+        replay.apply(&patch.frame(7_000)).unwrap();
+
+        let mut patch = PatchBuilder::new();
+        patch
+            .push(RootFields::World)
+            .push_key(WorldFields::Players, 1);
+        patch.push_key(PlayerFields::ResearchStates, 22);
+        patch
+            .set(ResearchStateFields::ResearchDone, 25.0f32)
+            .set(ResearchStateFields::TimesResearched, 1i16);
+        patch.pop().pop().pop();
+        replay.apply(&patch.frame(10_000)).unwrap();
+
+        let steps = order.player_steps(1);
+        let summary = steps
+            .iter()
+            .map(|x| {
+                (
+                    x.name.as_deref().unwrap(),
+                    x.ordered,
+                    x.started,
+                    x.completed,
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            summary,
+            vec![
+                ("House", Some(1_000), Some(2_000), Some(5_000)),
+                ("Villager", Some(5_000), None, Some(7_000)),
+                ("Loom", None, Some(7_000), Some(10_000)),
+            ]
+        );
+        assert_eq!(steps[0].entity_id, Some(50));
+        assert_eq!(steps[1].entity_id, Some(51));
+    }
+
+    #[test]
+    fn test_build_order_edges() {
+        let mut order = BuildOrder::new(UnitCatalog::new());
+        let mut replay = Replay::new();
+        replay.add(&mut order);
+
+        let mut patch = PatchBuilder::world();
+        patch.player(1, "Ana").pop().pop();
+        replay.apply(&patch.frame(0)).unwrap();
+
+        let multi_queue = |train_count, building_ids| {
+            Command::MultiQueue(command::MultiQueue {
+                player_id: 1,
+                train_id: 83,
+                train_count,
+                building_ids,
+                ..Default::default()
+            })
+        };
+        let commands = vec![
+            // Negative and zero counts, no buildings and buildings that don't exist order nothing
+            multi_queue(-2, vec![1, 2]),
+            multi_queue(0, vec![1]),
+            multi_queue(2, vec![]),
+            Command::Queue(command::Queue {
+                building_id: 99,
+                train_id: 83,
+                train_count: 3,
+            }),
+            multi_queue(1, vec![1, 2]),
+        ];
+        replay
+            .apply(&frame(1_000, &PatchBuilder::new(), commands))
+            .unwrap();
+
+        // Gaia units don't complete a step
+        let mut patch = PatchBuilder::new();
+        patch.push(RootFields::World);
+        patch.create_entity::<CombatEntity>(60, 83, 0).pop();
+        patch.create_entity::<CombatEntity>(61, 83, 1).pop();
+        patch
+            .push_key(WorldFields::Players, 1)
+            .create_key::<ResearchState, _>(PlayerFields::ResearchStates, 22)
+            .set(ResearchStateFields::TimesResearched, 1i16)
+            .pop()
+            .pop()
+            .pop();
+        replay.apply(&patch.frame(2_000)).unwrap();
+
+        let summary = order
+            .player_steps(1)
+            .iter()
+            .map(|x| (x.kind, x.name.clone(), x.started, x.completed, x.entity_id))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            summary,
+            vec![
+                (BuildKind::Unit, None, None, Some(2_000), Some(61)),
+                (BuildKind::Unit, None, None, None, None),
+                // Done without progress being seen, of a technology not in the world
+                (BuildKind::Technology, None, None, Some(2_000), None),
+            ]
+        );
+        assert!(order.player_steps(0).is_empty());
+    }
+}
@@ -0,0 +1,199 @@
+use crate::replay::{self, Analyzer, Game, Replay};
+use serde::Serialize;
+use std::fmt::{Display, Formatter};
+use uncage_client::pb;
+use uncage_client::pb::event::player_chat::Channel;
+use uncage_client::pb::event::Event;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize)]
+pub enum ChatChannel {
+    Default,
+    All,
+    Team,
+    Enemy,
+}
+
+impl From<Channel> for ChatChannel {
+    fn from(channel: Channel) -> Self {
+        match channel {
+            Channel::Default => ChatChannel::Default,
+            Channel::AllChat => ChatChannel::All,
+            Channel::TeamChat => ChatChannel::Team,
+            Channel::EnemyChat => ChatChannel::Enemy,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ChatMessage {
+    /// Game time in milliseconds
+    pub time: u32,
+    pub player: i32,
+    /// `Player.name` when the message was sent
+    pub player_name: Option<String>,
+    pub channel: ChatChannel,
+    pub text: String,
+    pub taunt: Option<i32>,
+}
+
+impl Display for ChatMessage {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let seconds = self.time / 1_000;
+        write!(f, "[{:02}:{:02}] ", seconds / 60, seconds % 60)?;
+        match &self.player_name {
+            Some(name) => write!(f, "{}", name)?,
+            None => write!(f, "Player {}", self.player)?,
+        }
+        if self.channel == ChatChannel::Team {
+            write!(f, " (team)")?;
+        }
+        write!(f, ": {}", self.text)?;
+        if let Some(taunt) = self.taunt.filter(|_| self.text.is_empty()) {
+            write!(f, "taunt {}", taunt)?;
+        }
+        Ok(())
+    }
+}
+
+/// Collects the `PlayerChat` events of a replay
+#[derive(Debug, Default)]
+pub struct ChatLog {
+    exclude_team: bool,
+    messages: Vec<ChatMessage>,
+}
+
+impl ChatLog {
+    pub fn new() -> ChatLog {
+        ChatLog::default()
+    }
+
+    /// Leaves out team chat, e.g. to share the log without giving away strategies
+    pub fn set_exclude_team(&mut self, exclude_team: bool) {
+        self.exclude_team = exclude_team;
+    }
+
+    pub fn messages(&self) -> &[ChatMessage] {
+        &self.messages
+    }
+
+    pub fn into_messages(self) -> Vec<ChatMessage> {
+        self.messages
+    }
+}
+
+impl Analyzer for ChatLog {
+    fn before_patch(&mut self, frame: &pb::Frame, game: &Game) {
+        for event in &frame.event {
+            let Some(Event::PlayerChat(chat)) = &event.event else {
+                continue;
+            };
+
+            let channel = ChatChannel::from(chat.channel());
+            if self.exclude_team && channel == ChatChannel::Team {
+                continue;
+            }
+
+            self.messages.push(ChatMessage {
+                time: frame.time,
+                player: chat.player_id,
+                player_name: replay::player(game, chat.player_id)
+                    .map(|x| x.name.clone())
+                    .filter(|x| !x.is_empty()),
+                channel,
+                text: chat.text.clone(),
+                taunt: Some(chat.taunt).filter(|x| *x > 0),
+            });
+        }
+    }
+}
+
+/// Replays the frames and returns their chat log
+pub fn chat_log(frames: &[pb::Frame], exclude_team: bool) -> anyhow::Result<Vec<ChatMessage>> {
+    let mut log = ChatLog::new();
+    log.set_exclude_team(exclude_team);
+
+    {
+        let mut replay = Replay::new();
+        replay.add(&mut log);
+        for frame in frames {
+            replay.apply(frame)?;
+        }
+    }
+
+    Ok(log.into_messages())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{frame_with_events, PatchBuilder};
+
+    fn chat(player_id: i32, channel: Channel, text: &str, taunt: i32) -> Event {
+        Event::PlayerChat(pb::event::PlayerChat {
+            player_id,
+            comm_player_id: 0,
+            channel: channel as i32,
+            text: text.to_string(),
+            taunt,
+        })
+    }
+
+    #[test]
+    fn test_chat_log() {
+        let mut patch = PatchBuilder::world();
+        patch.player(1, "Ana").pop();
+        patch.player(2, "Bo").pop();
+        patch.pop();
+
+        let frames = vec![
+            patch.frame(0),
+            frame_with_events(
+                65_000,
+                vec![
+                    chat(1, Channel::AllChat, "gl hf", 0),
+                    chat(2, Channel::TeamChat, "rush him", 0),
+                    chat(2, Channel::AllChat, "", 11),
+                ],
+            ),
+        ];
+
+        let messages = chat_log(&frames, false).unwrap();
+        assert_eq!(messages.len(), 3);
+        assert_eq!(messages[0].to_string(), "[01:05] Ana: gl hf");
+        assert_eq!(messages[1].to_string(), "[01:05] Bo (team): rush him");
+        assert_eq!(messages[2].to_string(), "[01:05] Bo: taunt 11");
+
+        let messages = chat_log(&frames, true).unwrap();
+        let texts = messages.iter().map(|x| x.text.as_str()).collect::<Vec<_>>();
+        assert_eq!(texts, vec!["gl hf", ""]);
+    }
+
+    #[test]
+    fn test_chat_edges() {
+        assert!(chat_log(&[], false).unwrap().is_empty());
+
+        let mut patch = PatchBuilder::world();
+        patch.player(1, "").pop();
+        patch.pop();
+
+        // Before there is a world, of a player without a name and of one that isn't in the world
+        let mut invalid_channel = chat(3, Channel::AllChat, "gg", -1);
+        if let Event::PlayerChat(x) = &mut invalid_channel {
+            x.channel = 42;
+        }
+        let frames = vec![
+            frame_with_events(0, vec![chat(1, Channel::AllChat, "hi", 0)]),
+            patch.frame(1_000),
+            frame_with_events(
+                3_661_000,
+                vec![chat(1, Channel::TeamChat, "wood", 0), invalid_channel],
+            ),
+        ];
+
+        let messages = chat_log(&frames, true).unwrap();
+        let lines = messages.iter().map(|x| x.to_string()).collect::<Vec<_>>();
+        assert_eq!(lines, vec!["[00:00] Player 1: hi", "[61:01] Player 3: gg"]);
+        assert_eq!(messages[1].channel, ChatChannel::Default);
+        assert_eq!(messages[1].taunt, None);
+    }
+}
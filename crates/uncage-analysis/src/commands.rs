@@ -0,0 +1,522 @@
+use crate::replay::{self, Analyzer, Game};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::num::NonZeroU32;
+use uncage_client::pb;
+use uncage_client::pb::command::Command;
+
+/// Commands of a player that repeat the previous one within this many milliseconds don't count
+/// towards eAPM
+pub const DEFAULT_SPAM_INTERVAL: u32 = 500;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Serialize)]
+pub enum CommandCategory {
+    Move,
+    Attack,
+    Interact,
+    Stop,
+    Stance,
+    Build,
+    Train,
+    Research,
+    Trade,
+    Special,
+    Delete,
+    Flare,
+    Diplomacy,
+    Ai,
+    Game,
+    Unknown,
+}
+
+impl CommandCategory {
+    /// Whether commands of this category are counted as player actions, AI orders and game
+    /// commands like pausing or saving aren't
+    pub fn is_action(self) -> bool {
+        !matches!(
+            self,
+            CommandCategory::Ai | CommandCategory::Game | CommandCategory::Unknown
+        )
+    }
+}
+
+/// What a single command does, the player is only known for commands that carry it
+#[derive(Debug, Clone, PartialEq)]
+pub struct CommandInfo {
+    pub name: &'static str,
+    pub category: CommandCategory,
+    pub player: Option<i32>,
+    pub unit_ids: Vec<i32>,
+}
+
+pub fn describe(command: &Command) -> CommandInfo {
+    use CommandCategory::*;
+
+    let (name, category, player, unit_ids) = match command {
+        Command::Interact(x) => (
+            "Interact",
+            Interact,
+            Some(x.comm_player_id as i32),
+            x.unit_ids.clone(),
+        ),
+        Command::Stop(x) => ("Stop", Stop, None, x.unit_ids.clone()),
+        Command::Work(x) => (
+            "Work",
+            Interact,
+            Some(x.comm_player_id as i32),
+            x.unit_ids.clone(),
+        ),
+        Command::Move(x) => (
+            "Move",
+            Move,
+            Some(x.comm_player_id as i32),
+            x.unit_ids.clone(),
+        ),
+        Command::Create(x) => ("Create", Game, Some(x.player_id as i32), vec![]),
+        Command::AddAttribute(x) => ("AddAttribute", Game, Some(x.player_id as i32), vec![]),
+        Command::AiOrder(x) => ("AiOrder", Ai, Some(x.player_id as i32), x.unit_ids.clone()),
+        Command::Resign(x) => ("Resign", Game, Some(x.player_id as i32), vec![]),
+        Command::AddWaypoint(x) => ("AddWaypoint", Move, Some(x.player_id as i32), vec![]),
+        Command::Pause(x) => ("Pause", Stop, None, x.unit_ids.clone()),
+        Command::GroupWaypoint(x) => (
+            "GroupWaypoint",
+            Move,
+            Some(x.comm_player_id as i32),
+            x.unit_ids.clone(),
+        ),
+        Command::GroupAiOrder(x) => (
+            "GroupAiOrder",
+            Ai,
+            Some(x.player_id as i32),
+            x.unit_ids.clone(),
+        ),
+        Command::UnitAiState(x) => ("UnitAiState", Stance, None, x.unit_ids.clone()),
+        Command::Guard(x) => ("Guard", Move, None, x.unit_ids.clone()),
+        Command::Follow(x) => ("Follow", Move, None, x.unit_ids.clone()),
+        Command::Patrol(x) => ("Patrol", Move, None, x.unit_ids.clone()),
+        Command::Scout(x) => ("Scout", Move, None, x.unit_ids.clone()),
+        Command::FormFormation(x) => (
+            "FormFormation",
+            Stance,
+            Some(x.player_id as i32),
+            x.unit_ids.clone(),
+        ),
+        Command::MultiplayerSave(x) => {
+            ("MultiplayerSave", Game, Some(x.comm_player as i32), vec![])
+        }
+        Command::GroupMultiWaypoints(x) => ("GroupMultiWaypoints", Move, None, x.unit_ids.clone()),
+        Command::Chapter(x) => ("Chapter", Game, Some(x.comm_player_id as i32), vec![]),
+        Command::AttackMove(x) => ("AttackMove", Attack, None, x.unit_ids.clone()),
+        Command::AttackMoveTarget(x) => ("AttackMoveTarget", Attack, None, x.unit_ids.clone()),
+        Command::Retreat(x) => (
+            "Retreat",
+            Move,
+            Some(x.comm_player_id as i32),
+            x.unit_ids.clone(),
+        ),
+        Command::UnitTypeAiState(x) => ("UnitTypeAiState", Stance, Some(x.player_id), vec![]),
+        Command::AutoScout(x) => ("AutoScout", Move, None, x.unit_ids.clone()),
+        Command::StopAll(x) => ("StopAll", Stop, Some(x.player_id as i32), vec![]),
+        Command::MoreTechs(x) => ("MoreTechs", Game, Some(x.player_id as i32), vec![]),
+        Command::TransformObject(x) => (
+            "TransformObject",
+            Special,
+            Some(x.player_id as i32),
+            vec![x.obj_id],
+        ),
+        Command::Make(x) => (
+            "Make",
+            Train,
+            Some(x.unit_player_id as i32),
+            vec![x.unit_id],
+        ),
+        Command::Research(x) => {
+            let mut unit_ids = vec![x.unit_id];
+            unit_ids.extend(x.building_ids.iter().filter(|id| **id != x.unit_id));
+            (
+                "Research",
+                Research,
+                Some(x.unit_player_id as i32),
+                unit_ids,
+            )
+        }
+        Command::Build(x) => (
+            "Build",
+            Build,
+            Some(x.unit_player_id as i32),
+            x.unit_ids.clone(),
+        ),
+        Command::Game(_) => ("Game", Game, None, vec![]),
+        Command::Explore(x) => (
+            "Explore",
+            Move,
+            Some(x.unit_player_id as i32),
+            x.unit_ids.clone(),
+        ),
+        Command::BuildWall(x) => (
+            "BuildWall",
+            Build,
+            Some(x.unit_player_id as i32),
+            x.unit_ids.clone(),
+        ),
+        Command::CancelBuild(x) => (
+            "CancelBuild",
+            Train,
+            Some(x.unit_player_id),
+            vec![x.unit_id],
+        ),
+        Command::AttackGround(x) => ("AttackGround", Attack, None, x.unit_ids.clone()),
+        Command::GiveAttribute2(x) => ("GiveAttribute", Trade, Some(x.player_id as i32), vec![]),
+        Command::TradeAttribute(x) => ("TradeAttribute", Trade, None, x.unit_ids.clone()),
+        Command::Repair(x) => ("Repair", Interact, None, x.unit_ids.clone()),
+        Command::Unload(x) => ("Unload", Interact, None, x.unit_ids.clone()),
+        Command::Gate(x) => ("Gate", Special, None, vec![x.unit_id]),
+        Command::Flare(x) => ("Flare", Flare, Some(x.player_id as i32), vec![]),
+        Command::UnitOrder(x) => ("UnitOrder", Interact, None, x.unit_ids.clone()),
+        Command::Diplomacy(x) => ("Diplomacy", Diplomacy, Some(x.player_id1 as i32), vec![]),
+        Command::Queue(x) => ("Queue", Train, None, vec![x.building_id]),
+        Command::SetGatherPoint(x) => ("SetGatherPoint", Interact, None, x.unit_ids.clone()),
+        Command::SellCommodity(x) => (
+            "SellCommodity",
+            Trade,
+            Some(x.player_id as i32),
+            vec![x.unit_id],
+        ),
+        Command::BuyCommodity(x) => (
+            "BuyCommodity",
+            Trade,
+            Some(x.player_id as i32),
+            vec![x.unit_id],
+        ),
+        Command::UnitTransform(x) => (
+            "UnitTransform",
+            Special,
+            Some(x.unit_player_id as i32),
+            x.unit_ids.clone(),
+        ),
+        Command::DropRelic(x) => ("DropRelic", Special, None, vec![x.unit_id]),
+        Command::TownBell(x) => ("TownBell", Special, None, vec![x.unit_id]),
+        Command::GoBackToWork(x) => ("GoBackToWork", Interact, None, vec![x.unit_id]),
+        Command::MultiQueue(x) => (
+            "MultiQueue",
+            Train,
+            Some(x.player_id as i32),
+            x.building_ids.clone(),
+        ),
+        Command::SetGatherState(x) => ("SetGatherState", Stance, None, x.unit_ids.clone()),
+        Command::DeleteObjects(x) => (
+            "DeleteObjects",
+            Delete,
+            Some(x.player_id as i32),
+            x.unit_ids.clone(),
+        ),
+        Command::ResetBuildings(x) => ("ResetBuildings", Special, None, x.unit_ids.clone()),
+        Command::MultiGate(x) => ("MultiGate", Special, None, x.unit_ids.clone()),
+        Command::GoBackToWork2(x) => ("GoBackToWork", Interact, None, x.unit_ids.clone()),
+        Command::ChangePlayerName(x) => {
+            ("ChangePlayerName", Game, Some(x.player_id as i32), vec![])
+        }
+        Command::UnknownCommand(_) => ("Unknown", Unknown, None, vec![]),
+    };
+
+    CommandInfo {
+        name,
+        category,
+        player,
+        unit_ids,
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CommandEntry {
+    /// Game time in milliseconds
+    pub time: u32,
+    pub player: i32,
+    pub name: &'static str,
+    pub category: CommandCategory,
+    pub unit_ids: Vec<i32>,
+    /// False if the command repeats the player's previous command within the spam interval
+    pub effective: bool,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Serialize)]
+pub struct ApmPoint {
+    /// End of the window in milliseconds of game time
+    pub time: u32,
+    pub apm: f32,
+}
+
+/// Commands of every player in the order they were issued
+#[derive(Debug)]
+pub struct CommandTimeline {
+    players: BTreeMap<i32, Vec<CommandEntry>>,
+    spam_interval: u32,
+    unattributed: usize,
+    end_time: u32,
+}
+
+impl Default for CommandTimeline {
+    fn default() -> Self {
+        CommandTimeline::new()
+    }
+}
+
+impl CommandTimeline {
+    pub fn new() -> CommandTimeline {
+        CommandTimeline {
+            players: BTreeMap::new(),
+            spam_interval: DEFAULT_SPAM_INTERVAL,
+            unattributed: 0,
+            end_time: 0,
+        }
+    }
+
+    /// Commands that repeat the player's previous command (same category and units) within
+    /// `interval` milliseconds are left out of the eAPM
+    pub fn set_spam_interval(&mut self, interval: u32) {
+        self.spam_interval = interval;
+    }
+
+    /// Adds the commands of a frame, `owner` resolves unit ids to their owner for the commands that
+    /// don't name a player
+    pub fn add_frame<F: Fn(i32) -> Option<i32>>(&mut self, frame: &pb::Frame, owner: F) {
+        self.end_time = self.end_time.max(frame.time);
+        for command in &frame.command {
+            if let Some(command) = &command.command {
+                self.add_command(frame.time, command, &owner);
+            }
+        }
+    }
+
+    pub fn add_command<F: Fn(i32) -> Option<i32>>(
+        &mut self,
+        time: u32,
+        command: &Command,
+        owner: F,
+    ) {
+        self.end_time = self.end_time.max(time);
+
+        let info = describe(command);
+        let player = info
+            .player
+            .or_else(|| info.unit_ids.iter().find_map(|id| owner(*id)));
+        let Some(player) = player else {
+            self.unattributed += 1;
+            return;
+        };
+
+        let entries = self.players.entry(player).or_default();
+        let effective = match entries.last() {
+            Some(last) => {
+                time.saturating_sub(last.time) > self.spam_interval
+                    || last.category != info.category
+                    || last.unit_ids != info.unit_ids
+            }
+            None => true,
+        };
+
+        entries.push(CommandEntry {
+            time,
+            player,
+            name: info.name,
+            category: info.category,
+            unit_ids: info.unit_ids,
+            effective,
+        });
+    }
+
+    pub fn players(&self) -> impl Iterator<Item = i32> + '_ {
+        self.players.keys().copied()
+    }
+
+    pub fn entries(&self, player: i32) -> &[CommandEntry] {
+        self.players.get(&player).map_or(&[], |x| x.as_slice())
+    }
+
+    /// Commands without a player whose units `owner` couldn't resolve either
+    pub fn unattributed(&self) -> usize {
+        self.unattributed
+    }
+
+    /// Game time of the last frame or command seen, in milliseconds
+    pub fn end_time(&self) -> u32 {
+        self.end_time
+    }
+
+    /// Actions per minute over the `window` milliseconds before every `step` milliseconds of game
+    /// time
+    pub fn apm(&self, player: i32, window: NonZeroU32, step: NonZeroU32) -> Vec<ApmPoint> {
+        self.curve(player, window, step, false)
+    }
+
+    /// Like [CommandTimeline::apm], without repeated commands
+    pub fn eapm(&self, player: i32, window: NonZeroU32, step: NonZeroU32) -> Vec<ApmPoint> {
+        self.curve(player, window, step, true)
+    }
+
+    fn curve(
+        &self,
+        player: i32,
+        window: NonZeroU32,
+        step: NonZeroU32,
+        effective: bool,
+    ) -> Vec<ApmPoint> {
+        let (window, step) = (window.get(), step.get());
+        let times = self
+            .entries(player)
+            .iter()
+            .filter(|x| x.category.is_action() && (x.effective || !effective))
+            .map(|x| x.time)
+            .collect::<Vec<_>>();
+
+        let mut points = vec![];
+        let mut start = 0;
+        let mut end = 0;
+        let mut time = step;
+        loop {
+            let from = time.saturating_sub(window);
+            while end < times.len() && times[end] <= time {
+                end += 1;
+            }
+
+            while from > 0 && start < end && times[start] <= from {
+                start += 1;
+            }
+
+            // Early in the game the window isn't filled yet
+            let span = window.min(time);
+            points.push(ApmPoint {
+                time,
+                apm: (end - start) as f32 * 60_000.0 / span as f32,
+            });
+
+            if time >= self.end_time {
+                break;
+            }
+
+            time = time.saturating_add(step);
+        }
+
+        points
+    }
+}
+
+impl Analyzer for CommandTimeline {
+    fn before_patch(&mut self, frame: &pb::Frame, game: &Game) {
+        self.add_frame(frame, |id| replay::owner_of(game, id));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use uncage_client::pb::command;
+
+    fn frame(time: u32, commands: Vec<Command>) -> pb::Frame {
+        pb::Frame {
+            time,
+            command: commands
+                .into_iter()
+                .map(|x| pb::Command { command: Some(x) })
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    fn millis(value: u32) -> NonZeroU32 {
+        NonZeroU32::new(value).unwrap()
+    }
+
+    fn move_units(player: u32, unit_ids: Vec<i32>) -> Command {
+        Command::Move(command::Move {
+            comm_player_id: player,
+            unit_ids,
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn test_timeline_and_apm() {
+        let owner = |id: i32| if id >= 100 { Some(2) } else { None };
+
+        let mut timeline = CommandTimeline::new();
+        timeline.add_frame(&frame(1_000, vec![move_units(1, vec![5])]), owner);
+        // Spamming the same move doesn't count towards eAPM
+        timeline.add_frame(&frame(1_200, vec![move_units(1, vec![5])]), owner);
+        timeline.add_frame(&frame(1_400, vec![move_units(1, vec![6])]), owner);
+        timeline.add_frame(
+            &frame(
+                30_000,
+                vec![
+                    Command::Stop(command::Stop {
+                        unit_ids: vec![100],
+                    }),
+                    Command::Stop(command::Stop { unit_ids: vec![7] }),
+                ],
+            ),
+            owner,
+        );
+        timeline.add_frame(&frame(120_000, vec![]), owner);
+
+        assert_eq!(timeline.players().collect::<Vec<_>>(), vec![1, 2]);
+        assert_eq!(timeline.unattributed(), 1);
+        assert_eq!(
+            timeline
+                .entries(1)
+                .iter()
+                .map(|x| x.effective)
+                .collect::<Vec<_>>(),
+            vec![true, false, true]
+        );
+        assert_eq!(timeline.entries(2)[0].category, CommandCategory::Stop);
+
+        let apm = timeline.apm(1, millis(60_000), millis(60_000));
+        assert_eq!(
+            apm,
+            vec![
+                ApmPoint {
+                    time: 60_000,
+                    apm: 3.0
+                },
+                ApmPoint {
+                    time: 120_000,
+                    apm: 0.0
+                },
+            ]
+        );
+        assert_eq!(timeline.eapm(1, millis(60_000), millis(60_000))[0].apm, 2.0);
+        assert_eq!(timeline.apm(2, millis(30_000), millis(30_000))[0].apm, 2.0);
+    }
+
+    #[test]
+    fn test_apm_edges() {
+        let mut timeline = CommandTimeline::new();
+        assert_eq!(
+            timeline.apm(1, millis(1), millis(1)),
+            vec![ApmPoint { time: 1, apm: 0.0 }]
+        );
+
+        timeline.add_frame(&frame(500, vec![move_units(1, vec![5])]), |_| None);
+        timeline.add_frame(&frame(90_000, vec![]), |_| None);
+
+        // A step past the end still gives one point, over the part of the window that was played
+        let apm = timeline.apm(1, millis(120_000), millis(100_000));
+        assert_eq!(
+            apm,
+            vec![ApmPoint {
+                time: 100_000,
+                apm: 0.6
+            }]
+        );
+
+        // Windows that don't cover the command, and players without commands
+        assert!(timeline
+            .apm(1, millis(1_000), millis(10_000))
+            .iter()
+            .all(|x| x.apm == 0.0));
+        assert!(timeline
+            .eapm(3, millis(60_000), millis(30_000))
+            .iter()
+            .all(|x| x.apm == 0.0));
+    }
+}
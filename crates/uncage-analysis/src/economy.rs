@@ -0,0 +1,316 @@
+use crate::replay::{self, Analyzer, Game};
+use crate::units::Resources;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::num::NonZeroU32;
+use uncage::model::{PlayerNavigation, WorldNavigation};
+use uncage_client::pb;
+
+pub const DEFAULT_RESOLUTION: NonZeroU32 = NonZeroU32::new(10_000).unwrap();
+pub const DEFAULT_FLOAT_THRESHOLD: f32 = 1_000.0;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct EconomySample {
+    /// Game time in milliseconds
+    pub time: u32,
+    pub player: i32,
+    pub stockpile: Resources,
+    /// Everything gathered so far
+    pub gathered: Resources,
+    /// Gathered per minute since the player's previous sample
+    pub rate: Resources,
+}
+
+/// A resource stockpile that stayed above the threshold from `from` to `to`
+#[derive(Debug, Clone, Serialize)]
+pub struct FloatWarning {
+    pub player: i32,
+    pub resource: &'static str,
+    pub from: u32,
+    pub to: u32,
+    pub peak: f32,
+}
+
+/// Samples `PlayerAttributes` of every player at a fixed game time resolution
+#[derive(Debug, Serialize)]
+pub struct EconomyTracker {
+    #[serde(skip)]
+    resolution: NonZeroU32,
+    #[serde(skip)]
+    threshold: f32,
+    /// `None` once the next sample would be past the largest game time
+    #[serde(skip)]
+    next_sample: Option<u32>,
+    samples: Vec<EconomySample>,
+    warnings: Vec<FloatWarning>,
+    #[serde(skip)]
+    floating: BTreeMap<(i32, &'static str), usize>,
+    #[serde(skip)]
+    last: BTreeMap<i32, usize>,
+}
+
+impl Default for EconomyTracker {
+    fn default() -> Self {
+        EconomyTracker::new(DEFAULT_RESOLUTION)
+    }
+}
+
+impl EconomyTracker {
+    /// Takes a sample every `resolution` milliseconds of game time
+    pub fn new(resolution: NonZeroU32) -> EconomyTracker {
+        EconomyTracker {
+            resolution,
+            threshold: DEFAULT_FLOAT_THRESHOLD,
+            next_sample: Some(0),
+            samples: vec![],
+            warnings: vec![],
+            floating: BTreeMap::new(),
+            last: BTreeMap::new(),
+        }
+    }
+
+    /// Stockpiles above `threshold` of any resource are reported as [FloatWarning]s
+    pub fn set_float_threshold(&mut self, threshold: f32) {
+        self.threshold = threshold;
+    }
+
+    pub fn samples(&self) -> &[EconomySample] {
+        &self.samples
+    }
+
+    pub fn player_samples(&self, player: i32) -> impl Iterator<Item = &EconomySample> {
+        self.samples.iter().filter(move |x| x.player == player)
+    }
+
+    pub fn warnings(&self) -> &[FloatWarning] {
+        &self.warnings
+    }
+
+    pub fn to_json(&self) -> serde_json::Value {
+        serde_json::to_value(self).unwrap()
+    }
+
+    /// One row per sample, the stockpile, gathered and rate columns per resource
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::from("time,player");
+        for prefix in ["", "gathered_", "rate_"] {
+            for (name, _) in Resources::default().named() {
+                write!(csv, ",{}{}", prefix, name).unwrap();
+            }
+        }
+        csv.push('\n');
+
+        for sample in &self.samples {
+            write!(csv, "{},{}", sample.time, sample.player).unwrap();
+            for resources in [&sample.stockpile, &sample.gathered, &sample.rate] {
+                for (_, value) in resources.named() {
+                    write!(csv, ",{}", value).unwrap();
+                }
+            }
+            csv.push('\n');
+        }
+
+        csv
+    }
+
+    fn sample(&mut self, time: u32, player: i32, stockpile: Resources, gathered: Resources) {
+        let rate = match self.last.get(&player).map(|x| &self.samples[*x]) {
+            Some(last) if time > last.time => {
+                (gathered - last.gathered).scale(60_000.0 / (time - last.time) as f32)
+            }
+            _ => Resources::default(),
+        };
+
+        for (resource, amount) in stockpile.named() {
+            let key = (player, resource);
+            if amount <= self.threshold {
+                self.floating.remove(&key);
+                continue;
+            }
+
+            match self.floating.get(&key) {
+                Some(index) => {
+                    let warning = &mut self.warnings[*index];
+                    warning.to = time;
+                    warning.peak = warning.peak.max(amount);
+                }
+                None => {
+                    self.floating.insert(key, self.warnings.len());
+                    self.warnings.push(FloatWarning {
+                        player,
+                        resource,
+                        from: time,
+                        to: time,
+                        peak: amount,
+                    });
+                }
+            }
+        }
+
+        self.last.insert(player, self.samples.len());
+        self.samples.push(EconomySample {
+            time,
+            player,
+            stockpile,
+            gathered,
+            rate,
+        });
+    }
+}
+
+impl Analyzer for EconomyTracker {
+    fn after_patch(&mut self, frame: &pb::Frame, game: &Game) {
+        if self.next_sample.is_none_or(|x| frame.time < x) {
+            return;
+        }
+
+        let Some(world) = replay::world(game) else {
+            return;
+        };
+
+        let resolution = self.resolution.get();
+        self.next_sample = (frame.time - frame.time % resolution).checked_add(resolution);
+        for (id, player) in world.players().iter() {
+            let (Some(id), Some(attributes)) = (id.index(), player.attributes()) else {
+                continue;
+            };
+
+            let stockpile = Resources {
+                food: attributes.food,
+                wood: attributes.wood,
+                stone: attributes.stone,
+                gold: attributes.gold,
+            };
+            let gathered = Resources {
+                food: attributes.food_total,
+                wood: attributes.wood_total,
+                stone: attributes.stone_total,
+                gold: attributes.gold_total,
+            };
+            self.sample(frame.time, id as i32, stockpile, gathered);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::PatchBuilder;
+    use crate::Replay;
+    use uncage::model::{PlayerAttributes, PlayerAttributesFields, PlayerFields};
+    use uncage::model::{RootFields, WorldFields};
+
+    fn attributes(patch: &mut PatchBuilder, food: f32, food_total: f32) {
+        patch
+            .set(PlayerAttributesFields::Food, food)
+            .set(PlayerAttributesFields::FoodTotal, food_total);
+    }
+
+    fn millis(value: u32) -> NonZeroU32 {
+        NonZeroU32::new(value).unwrap()
+    }
+
+    #[test]
+    fn test_economy_samples_and_warnings() {
+        let mut economy = EconomyTracker::new(millis(30_000));
+        let mut replay = Replay::new();
+        replay.add(&mut economy);
+
+        let mut patch = PatchBuilder::world();
+        patch
+            .player(1, "Ana")
+            .create::<PlayerAttributes, _>(PlayerFields::Attributes);
+        attributes(&mut patch, 100.0, 100.0);
+        patch.pop().pop().pop();
+        replay.apply(&patch.frame(0)).unwrap();
+
+        for (time, food, food_total) in [
+            (10_000, 500.0, 500.0),
+            (30_000, 1_200.0, 1_300.0),
+            (60_000, 1_500.0, 1_900.0),
+            (90_000, 200.0, 2_000.0),
+        ] {
+            let mut patch = PatchBuilder::new();
+            patch
+                .push(RootFields::World)
+                .push_key(WorldFields::Players, 1);
+            patch.push(PlayerFields::Attributes);
+            attributes(&mut patch, food, food_total);
+            patch.pop().pop().pop();
+            replay.apply(&patch.frame(time)).unwrap();
+        }
+
+        let times = economy
+            .player_samples(1)
+            .map(|x| x.time)
+            .collect::<Vec<_>>();
+        assert_eq!(times, vec![0, 30_000, 60_000, 90_000]);
+        assert_eq!(economy.samples()[1].rate.food, 2_400.0);
+        assert_eq!(economy.samples()[2].gathered.food, 1_900.0);
+
+        let warning = &economy.warnings()[0];
+        assert_eq!(economy.warnings().len(), 1);
+        assert_eq!(
+            (warning.resource, warning.from, warning.to, warning.peak),
+            ("food", 30_000, 60_000, 1_500.0)
+        );
+
+        let csv = economy.to_csv();
+        assert!(csv.starts_with("time,player,food,wood,stone,gold,gathered_food,"));
+        assert!(csv.contains("\n30000,1,1200,0,0,0,1300,0,0,0,2400,0,0,0\n"));
+        assert_eq!(economy.to_json()["warnings"][0]["peak"], 1_500.0);
+    }
+
+    #[test]
+    fn test_economy_edges() {
+        let mut economy = EconomyTracker::new(millis(1));
+        economy.set_float_threshold(100.0);
+        let mut replay = Replay::new();
+        replay.add(&mut economy);
+
+        // Nothing to sample before there is a world
+        replay.apply(&PatchBuilder::new().frame(0)).unwrap();
+
+        // Players without attributes are skipped
+        let mut patch = PatchBuilder::world();
+        patch.player(1, "Ana").pop();
+        patch
+            .player(2, "Bo")
+            .create::<PlayerAttributes, _>(PlayerFields::Attributes);
+        attributes(&mut patch, 100.0, 100.0);
+        patch.pop().pop().pop();
+        replay.apply(&patch.frame(0)).unwrap();
+        replay.apply(&PatchBuilder::new().frame(0)).unwrap();
+        replay.apply(&PatchBuilder::new().frame(5)).unwrap();
+
+        let samples = economy
+            .samples()
+            .iter()
+            .map(|x| (x.time, x.player, x.rate.food))
+            .collect::<Vec<_>>();
+        assert_eq!(samples, vec![(0, 2, 0.0), (5, 2, 0.0)]);
+        // A stockpile at the threshold isn't floating
+        assert!(economy.warnings().is_empty());
+        assert_eq!(economy.to_csv().lines().count(), 3);
+    }
+
+    #[test]
+    fn test_economy_at_the_end_of_game_time() {
+        let mut economy = EconomyTracker::new(millis(10));
+        let mut replay = Replay::new();
+        replay.add(&mut economy);
+
+        let mut patch = PatchBuilder::world();
+        patch
+            .player(1, "Ana")
+            .create::<PlayerAttributes, _>(PlayerFields::Attributes);
+        attributes(&mut patch, 100.0, 100.0);
+        patch.pop().pop().pop();
+        replay.apply(&patch.frame(u32::MAX - 5)).unwrap();
+        replay.apply(&PatchBuilder::new().frame(u32::MAX)).unwrap();
+
+        let times = economy.samples().iter().map(|x| x.time).collect::<Vec<_>>();
+        assert_eq!(times, vec![u32::MAX - 5]);
+    }
+}
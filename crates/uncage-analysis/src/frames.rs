@@ -0,0 +1,87 @@
+use anyhow::Context;
+use bytes::{Buf, Bytes};
+use std::path::Path;
+use uncage_client::pb;
+use uncage_client::prost::{self, Message};
+
+/// Decodes a recording of length delimited `FrameSequence`s, like the frames stream is written to
+/// disk, into its frames
+pub fn decode_frames(mut data: Bytes) -> anyhow::Result<Vec<pb::Frame>> {
+    let mut frames = vec![];
+    while data.has_remaining() {
+        let length =
+            prost::decode_length_delimiter(&mut data).context("Failed to read frame length")?;
+        anyhow::ensure!(
+            length <= data.remaining(),
+            "Frame sequence of {} bytes is cut off after {} bytes",
+            length,
+            data.remaining()
+        );
+
+        let sequence = pb::FrameSequence::decode(data.split_to(length))
+            .context("Failed to decode frame sequence")?;
+        frames.extend(sequence.frame);
+    }
+
+    Ok(frames)
+}
+
+pub fn read_frames(path: &Path) -> anyhow::Result<Vec<pb::Frame>> {
+    let data = std::fs::read(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    decode_frames(Bytes::from(data))
+}
+
+/// Reads the game info the client writes next to a recording, `output.xz` has it in `output.info`
+pub fn read_info(frames: &Path) -> anyhow::Result<pb::InfoResponse> {
+    let path = frames.with_extension("info");
+    let data = std::fs::read(&path)
+        .with_context(|| format!("Failed to read game info {}", path.display()))?;
+    pb::InfoResponse::decode(Bytes::from(data)).context("Failed to decode game info")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_frames() {
+        let mut data = vec![];
+        for times in [[1, 2], [3, 4]] {
+            let sequence = pb::FrameSequence {
+                frame: times
+                    .into_iter()
+                    .map(|time| pb::Frame {
+                        time,
+                        ..Default::default()
+                    })
+                    .collect(),
+                ..Default::default()
+            };
+            sequence.encode_length_delimited(&mut data).unwrap();
+        }
+
+        let frames = decode_frames(Bytes::from(data.clone())).unwrap();
+        let times = frames.iter().map(|x| x.time).collect::<Vec<_>>();
+        assert_eq!(times, vec![1, 2, 3, 4]);
+
+        data.pop();
+        assert!(decode_frames(Bytes::from(data)).is_err());
+    }
+
+    #[test]
+    fn test_read_info() {
+        let directory = std::env::temp_dir().join(format!("uncage-info-{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let frames = directory.join("output.xz");
+        assert!(read_info(&frames).is_err());
+
+        let info = pb::InfoResponse {
+            game_version: 125283,
+            ..Default::default()
+        };
+        std::fs::write(directory.join("output.info"), info.encode_to_vec()).unwrap();
+        assert_eq!(read_info(&frames).unwrap().game_version, 125283);
+
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+}
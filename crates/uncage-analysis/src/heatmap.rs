@@ -0,0 +1,383 @@
+use crate::render::{Canvas, Projection, Rgba, Svg, TRANSPARENT, WHITE};
+use crate::replay::{self, Analyzer, Borrow, Game};
+use crate::units::UnitRole;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::num::NonZeroU32;
+use uncage::model::{Entity, EntityFields, WorldFields, WorldNavigation};
+use uncage_client::pb;
+use uncage_client::pb::event::Event;
+use uncage_model::patcher::PatcherSelectorMatch;
+use uncage_model::Selector;
+
+pub const DEFAULT_RESOLUTION: NonZeroU32 = NonZeroU32::new(10_000).unwrap();
+
+const HP: usize = 0;
+const CREATED_ENTITIES: usize = 1;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize)]
+pub enum HeatmapMetric {
+    /// HP lost by units and buildings
+    DamageTaken,
+    /// Units and buildings killed
+    Deaths,
+    /// Units on the tile, sampled at the heatmap's resolution
+    Presence,
+    /// Buildings placed
+    Buildings,
+    /// Only what's added with [Heatmap::add]
+    Custom,
+}
+
+/// Accumulates a metric per map tile from the positions of entities
+#[derive(Debug)]
+pub struct Heatmap {
+    metric: HeatmapMetric,
+    player: Option<i32>,
+    window: Option<(u32, u32)>,
+    resolution: NonZeroU32,
+    /// `None` once the next sample would be past the largest game time
+    next_sample: Option<u32>,
+    map_size: (i32, i32),
+    tiles: BTreeMap<(i32, i32), f32>,
+    hp: HashMap<i32, f32>,
+    placed: HashSet<i32>,
+}
+
+impl Heatmap {
+    pub fn new(metric: HeatmapMetric) -> Heatmap {
+        Heatmap {
+            metric,
+            player: None,
+            window: None,
+            resolution: DEFAULT_RESOLUTION,
+            next_sample: Some(0),
+            map_size: (0, 0),
+            tiles: BTreeMap::new(),
+            hp: HashMap::new(),
+            placed: HashSet::new(),
+        }
+    }
+
+    /// Only counts entities owned by `player`
+    pub fn set_player(&mut self, player: Option<i32>) {
+        self.player = player;
+    }
+
+    /// Only counts what happens from `from` until before `to` milliseconds of game time
+    pub fn set_time_window(&mut self, from: u32, to: u32) {
+        self.window = Some((from, to));
+    }
+
+    /// How often [HeatmapMetric::Presence] is sampled, in milliseconds of game time
+    pub fn set_resolution(&mut self, resolution: NonZeroU32) {
+        self.resolution = resolution;
+    }
+
+    /// The map size is taken from the world, this is only needed for heatmaps filled by hand
+    pub fn set_map_size(&mut self, width: i32, height: i32) {
+        self.map_size = (width, height);
+    }
+
+    pub fn add(&mut self, x: f32, y: f32, value: f32) {
+        let tile = (x.floor() as i32, y.floor() as i32);
+        *self.tiles.entry(tile).or_default() += value;
+    }
+
+    pub fn tiles(&self) -> &BTreeMap<(i32, i32), f32> {
+        &self.tiles
+    }
+
+    pub fn max(&self) -> f32 {
+        self.tiles.values().copied().fold(0.0, f32::max)
+    }
+
+    pub fn to_png(&self, width: u32, height: u32) -> anyhow::Result<Vec<u8>> {
+        let mut canvas = Canvas::new(width, height, TRANSPARENT);
+        self.draw(width, height, |points, color| {
+            canvas.fill_polygon(points, color)
+        });
+        canvas.to_png()
+    }
+
+    pub fn to_svg(&self, width: u32, height: u32) -> String {
+        let mut svg = Svg::new(width, height);
+        self.draw(width, height, |points, color| svg.polygon(points, color));
+        svg.finish()
+    }
+
+    /// Draws the map in white and every tile from white to red by its share of the maximum
+    fn draw<F: FnMut(&[(f64, f64)], Rgba)>(&self, width: u32, height: u32, mut fill: F) {
+        let (map_width, map_height) = self.map_size;
+        let projection = Projection::new(map_width, map_height, width, height);
+        fill(
+            &projection.area(0.0, 0.0, map_width as f64, map_height as f64),
+            WHITE,
+        );
+
+        let max = self.max();
+        if max <= 0.0 {
+            return;
+        }
+
+        for ((x, y), value) in &self.tiles {
+            let heat = (value / max).clamp(0.0, 1.0);
+            let other = (255.0 * (1.0 - heat)) as u8;
+            fill(
+                &projection.area(*x as f64, *y as f64, 1.0, 1.0),
+                [255, other, other, 255],
+            );
+        }
+    }
+
+    fn counts(&self, time: u32, entity: &Borrow<'_, Entity>) -> bool {
+        let in_window = match self.window {
+            Some((from, to)) => (from..to).contains(&time),
+            None => true,
+        };
+
+        in_window && self.player.is_none_or(|x| x == entity.owner_id as i32)
+    }
+
+    fn hp_changed(&mut self, game: &Game, time: u32, object_id: usize) {
+        let Some(entity) = game.by_id(object_id).and_then(|x| x.cast::<Entity>()) else {
+            return;
+        };
+
+        if UnitRole::of(game, &entity).is_none() {
+            return;
+        }
+
+        if let Some(previous) = self.hp.insert(entity.id, entity.hp) {
+            if previous > entity.hp && self.counts(time, &entity) {
+                self.add(entity.world_x, entity.world_y, previous - entity.hp);
+            }
+        }
+    }
+
+    fn entity_created(&mut self, game: &Game, time: u32, selected: &PatcherSelectorMatch) {
+        let Some(entity) = replay::path_key(&selected.path, WorldFields::Entities)
+            .and_then(|x| replay::entity(game, x))
+        else {
+            return;
+        };
+
+        // The selector matches every field assigned while the entity is created
+        if UnitRole::of(game, &entity) != Some(UnitRole::Building)
+            || !self.placed.insert(entity.id)
+            || !self.counts(time, &entity)
+        {
+            return;
+        }
+
+        self.add(entity.world_x, entity.world_y, 1.0);
+    }
+
+    fn sample(&mut self, game: &Game, time: u32) {
+        let Some(world) = replay::world(game) else {
+            return;
+        };
+
+        let mut positions = vec![];
+        for (_, entity) in world.entities().iter() {
+            let Some(entity) = entity.cast::<Entity>() else {
+                continue;
+            };
+
+            let is_unit = matches!(UnitRole::of(game, &entity), Some(x) if x != UnitRole::Building);
+            if is_unit && self.counts(time, &entity) {
+                positions.push((entity.world_x, entity.world_y));
+            }
+        }
+
+        for (x, y) in positions {
+            self.add(x, y, 1.0);
+        }
+    }
+}
+
+impl Analyzer for Heatmap {
+    fn before_patch(&mut self, frame: &pb::Frame, game: &Game) {
+        if self.metric != HeatmapMetric::Deaths {
+            return;
+        }
+
+        for event in &frame.event {
+            let Some(Event::EntityKilled(killed)) = &event.event else {
+                continue;
+            };
+
+            let Some(entity) = replay::entity(game, killed.id) else {
+                continue;
+            };
+
+            if UnitRole::of(game, &entity).is_some() && self.counts(frame.time, &entity) {
+                self.add(entity.world_x, entity.world_y, 1.0);
+            }
+        }
+    }
+
+    fn after_patch(&mut self, frame: &pb::Frame, game: &Game) {
+        if let Some(world) = replay::world(game) {
+            self.map_size = (world.map_width, world.map_height);
+        }
+
+        let due = self.next_sample.is_some_and(|x| frame.time >= x);
+        if self.metric == HeatmapMetric::Presence && due {
+            let resolution = self.resolution.get();
+            self.next_sample = (frame.time - frame.time % resolution).checked_add(resolution);
+            self.sample(game, frame.time);
+        }
+    }
+
+    fn selectors(&self) -> Vec<Selector> {
+        // In the order of HP and CREATED_ENTITIES
+        vec![
+            Selector::new().field(EntityFields::Hp),
+            Selector::new().created().field(WorldFields::Entities),
+        ]
+    }
+
+    fn selected(
+        &mut self,
+        frame: &pb::Frame,
+        game: &Game,
+        index: usize,
+        selected: &PatcherSelectorMatch,
+    ) {
+        match (index, self.metric) {
+            (HP, HeatmapMetric::DamageTaken) => {
+                self.hp_changed(game, frame.time, selected.object_id)
+            }
+            (CREATED_ENTITIES, HeatmapMetric::Buildings) => {
+                self.entity_created(game, frame.time, selected)
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{frame_with_events, PatchBuilder};
+    use crate::Replay;
+    use uncage::model::CombatEntity;
+
+    fn world() -> pb::Frame {
+        let mut patch = PatchBuilder::world();
+        patch
+            .set(WorldFields::MapWidth, 10)
+            .set(WorldFields::MapHeight, 10);
+        patch.player(1, "Ana").master(4, 0).pop();
+        patch.player(2, "Bo").master(4, 0).pop();
+        for (id, owner, x, y) in [(1, 1, 2.5f32, 3.5f32), (2, 2, 7.2, 7.9), (3, 1, 2.1, 3.9)] {
+            patch.create_entity::<CombatEntity>(id, 4, owner);
+            patch
+                .set(EntityFields::WorldX, x)
+                .set(EntityFields::WorldY, y)
+                .pop();
+        }
+        patch.pop();
+        patch.frame(0)
+    }
+
+    #[test]
+    fn test_heatmap() {
+        let mut presence = Heatmap::new(HeatmapMetric::Presence);
+        presence.set_player(Some(1));
+        let mut deaths = Heatmap::new(HeatmapMetric::Deaths);
+        deaths.set_time_window(0, 10_000);
+
+        let mut replay = Replay::new();
+        replay.add(&mut presence);
+        replay.add(&mut deaths);
+        replay.apply(&world()).unwrap();
+        for (time, id) in [(5_000, 2), (15_000, 1)] {
+            let killed = Event::EntityKilled(pb::event::EntityKilled { id, killer_id: 0 });
+            replay
+                .apply(&frame_with_events(time, vec![killed]))
+                .unwrap();
+        }
+
+        assert_eq!(presence.tiles().len(), 1);
+        assert_eq!(presence.tiles()[&(2, 3)], 4.0);
+        assert_eq!(
+            deaths.tiles().iter().collect::<Vec<_>>(),
+            vec![(&(7, 7), &1.0)]
+        );
+
+        let svg = deaths.to_svg(300, 100);
+        assert_eq!(svg.matches("<polygon").count(), 2);
+        assert!(svg.contains("fill=\"#ff0000\""));
+
+        let png = presence.to_png(300, 100).unwrap();
+        assert!(png.starts_with(b"\x89PNG"));
+    }
+
+    #[test]
+    fn test_heatmap_edges() {
+        let mut presence = Heatmap::new(HeatmapMetric::Presence);
+        presence.set_player(Some(2));
+        presence.set_time_window(1_000, 2_000);
+        presence.set_resolution(NonZeroU32::new(1).unwrap());
+        // Counts the deaths of a player that isn't in the world, and entity 99 doesn't exist
+        let mut deaths = Heatmap::new(HeatmapMetric::Deaths);
+        deaths.set_player(Some(5));
+
+        let mut replay = Replay::new();
+        replay.add(&mut presence);
+        replay.add(&mut deaths);
+        replay.apply(&world()).unwrap();
+        for time in [999, 1_000, 1_999, 2_000] {
+            let killed =
+                [1, 99].map(|id| Event::EntityKilled(pb::event::EntityKilled { id, killer_id: 0 }));
+            replay
+                .apply(&frame_with_events(time, killed.to_vec()))
+                .unwrap();
+        }
+
+        assert_eq!(
+            presence.tiles().iter().collect::<Vec<_>>(),
+            vec![(&(7, 7), &2.0)]
+        );
+        assert!(deaths.tiles().is_empty());
+        assert_eq!(deaths.max(), 0.0);
+        assert_eq!(deaths.to_svg(300, 100).matches("<polygon").count(), 1);
+    }
+
+    #[test]
+    fn test_empty_heatmap() {
+        // Without a world the map has no size
+        let mut heatmap = Heatmap::new(HeatmapMetric::Custom);
+        heatmap.add(-1.5, 2.5, 1.0);
+        assert_eq!(heatmap.tiles()[&(-2, 2)], 1.0);
+        assert!(heatmap.to_png(30, 10).is_ok());
+        assert!(heatmap.to_png(0, 0).is_err());
+
+        let mut replay = Replay::new();
+        replay.add(&mut heatmap);
+        replay.apply(&PatchBuilder::world().pop().frame(0)).unwrap();
+        assert_eq!(heatmap.map_size, (0, 0));
+        assert!(heatmap.to_svg(30, 10).contains("<polygon"));
+    }
+
+    #[test]
+    fn test_heatmap_at_the_end_of_game_time() {
+        let mut presence = Heatmap::new(HeatmapMetric::Presence);
+        presence.set_player(Some(2));
+        presence.set_resolution(NonZeroU32::new(10).unwrap());
+
+        let mut replay = Replay::new();
+        replay.add(&mut presence);
+        let mut frame = world();
+        frame.time = u32::MAX - 5;
+        replay.apply(&frame).unwrap();
+        replay.apply(&frame_with_events(u32::MAX, vec![])).unwrap();
+
+        assert_eq!(
+            presence.tiles().iter().collect::<Vec<_>>(),
+            vec![(&(7, 7), &1.0)]
+        );
+    }
+}
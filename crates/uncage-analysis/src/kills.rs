@@ -0,0 +1,221 @@
+use crate::replay::{self, Analyzer, Game};
+use crate::units::{Resources, UnitCatalog};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use uncage_client::pb;
+use uncage_client::pb::event::Event;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Kill {
+    /// Game time in milliseconds
+    pub time: u32,
+    pub entity_id: i32,
+    pub owner: i32,
+    pub master_id: i16,
+    /// `MasterEntity.object_group` of the killed entity
+    pub unit_class: Option<i16>,
+    pub unit_name: Option<String>,
+    pub x: f32,
+    pub y: f32,
+    pub killer_id: i32,
+    pub killer_owner: Option<i32>,
+    pub killer_master_id: Option<i16>,
+    pub killer_name: Option<String>,
+    pub value: Resources,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct CombatStats {
+    /// Kills by unit class of the killed entity, -1 if its master isn't known
+    pub kills: BTreeMap<i16, usize>,
+    /// Losses by unit class
+    pub losses: BTreeMap<i16, usize>,
+    pub value_killed: Resources,
+    pub value_lost: Resources,
+}
+
+/// Joins `EntityKilled` events with the state of the killed entity and its killer
+#[derive(Debug)]
+pub struct KillFeed {
+    catalog: UnitCatalog,
+    kills: Vec<Kill>,
+    players: BTreeMap<i32, CombatStats>,
+    unresolved: usize,
+}
+
+impl KillFeed {
+    /// Unit names and the value lost are taken from `catalog`
+    pub fn new(catalog: UnitCatalog) -> KillFeed {
+        KillFeed {
+            catalog,
+            kills: vec![],
+            players: BTreeMap::new(),
+            unresolved: 0,
+        }
+    }
+
+    pub fn kills(&self) -> &[Kill] {
+        &self.kills
+    }
+
+    pub fn players(&self) -> &BTreeMap<i32, CombatStats> {
+        &self.players
+    }
+
+    /// Kills of entities that weren't in the world when the event arrived
+    pub fn unresolved(&self) -> usize {
+        self.unresolved
+    }
+
+    fn resolve(&self, game: &Game, time: u32, id: i32, killer_id: i32) -> Option<Kill> {
+        let entity = replay::entity(game, id)?;
+        let master = replay::master_of(game, &entity);
+        let killer = replay::entity(game, killer_id);
+        let killer_master_id = killer.as_ref().map(|x| x.master_id);
+
+        Some(Kill {
+            time,
+            entity_id: id,
+            owner: entity.owner_id as i32,
+            master_id: entity.master_id,
+            unit_class: master.map(|x| x.object_group),
+            unit_name: self.catalog.name(entity.master_id).map(String::from),
+            x: entity.world_x,
+            y: entity.world_y,
+            killer_id,
+            killer_owner: killer.as_ref().map(|x| x.owner_id as i32),
+            killer_master_id,
+            killer_name: killer_master_id
+                .and_then(|x| self.catalog.name(x))
+                .map(String::from),
+            value: self.catalog.cost(entity.master_id),
+        })
+    }
+
+    fn record(&mut self, kill: Kill) {
+        let class = kill.unit_class.unwrap_or(-1);
+
+        let victim = self.players.entry(kill.owner).or_default();
+        *victim.losses.entry(class).or_default() += 1;
+        victim.value_lost += kill.value;
+
+        if let Some(killer) = kill.killer_owner.filter(|x| *x != kill.owner) {
+            let killer = self.players.entry(killer).or_default();
+            *killer.kills.entry(class).or_default() += 1;
+            killer.value_killed += kill.value;
+        }
+
+        self.kills.push(kill);
+    }
+}
+
+impl Analyzer for KillFeed {
+    fn before_patch(&mut self, frame: &pb::Frame, game: &Game) {
+        for event in &frame.event {
+            let Some(Event::EntityKilled(killed)) = &event.event else {
+                continue;
+            };
+
+            match self.resolve(game, frame.time, killed.id, killed.killer_id) {
+                Some(kill) => self.record(kill),
+                None => self.unresolved += 1,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{frame_with_events, PatchBuilder};
+    use crate::units::UnitInfo;
+    use crate::Replay;
+    use uncage::model::{RootFields, WorldFields};
+
+    fn killed(id: i32, killer_id: i32) -> Event {
+        Event::EntityKilled(pb::event::EntityKilled { id, killer_id })
+    }
+
+    #[test]
+    fn test_kill_feed() {
+        let mut patch = PatchBuilder::world();
+        patch.player(1, "Ana").master(4, 0).pop();
+        patch.player(2, "Bo").master(38, 12).pop();
+        patch.entity(10, 4, 1, (5.0, 6.0));
+        patch.entity(20, 38, 2, (7.0, 8.0));
+        patch.pop();
+
+        let mut catalog = UnitCatalog::new();
+        catalog.insert(
+            4,
+            UnitInfo {
+                name: "Archer".into(),
+                cost: Resources {
+                    wood: 25.0,
+                    gold: 45.0,
+                    ..Default::default()
+                },
+            },
+        );
+
+        let mut feed = KillFeed::new(catalog);
+        let mut replay = Replay::new();
+        replay.add(&mut feed);
+        replay.apply(&patch.frame(0)).unwrap();
+
+        let mut patch = PatchBuilder::new();
+        patch
+            .push(RootFields::World)
+            .remove_key(WorldFields::Entities, 10)
+            .pop();
+        let mut frame = frame_with_events(1_000, vec![killed(10, 20), killed(99, 20)]);
+        frame.patch = patch.build();
+        replay.apply(&frame).unwrap();
+        assert!(replay::entity(replay.game(), 10).is_none());
+
+        assert_eq!(feed.unresolved(), 1);
+        let kill = &feed.kills()[0];
+        assert_eq!(kill.owner, 1);
+        assert_eq!(kill.unit_class, Some(0));
+        assert_eq!(kill.unit_name.as_deref(), Some("Archer"));
+        assert_eq!((kill.x, kill.y), (5.0, 6.0));
+        assert_eq!(kill.killer_owner, Some(2));
+        assert_eq!(kill.killer_master_id, Some(38));
+
+        assert_eq!(feed.players()[&1].losses[&0], 1);
+        assert_eq!(feed.players()[&1].value_lost.total(), 70.0);
+        assert_eq!(feed.players()[&2].kills[&0], 1);
+    }
+    #[test]
+    fn test_kills_without_killer_or_master() {
+        let mut patch = PatchBuilder::world();
+        patch.player(1, "Ana").master(4, 0).pop();
+        // Owned by a player and of a master that aren't in the world
+        patch.entity(10, 7, 3, (1.0, 1.0));
+        patch.entity(11, 4, 1, (2.0, 2.0));
+        patch.entity(12, 4, 1, (3.0, 3.0));
+        patch.pop();
+
+        let mut feed = KillFeed::new(UnitCatalog::new());
+        let mut replay = Replay::new();
+        replay.add(&mut feed);
+        replay.apply(&patch.frame(0)).unwrap();
+        let events = vec![killed(10, 99), killed(11, 12), killed(12, 0)];
+        replay.apply(&frame_with_events(1_000, events)).unwrap();
+
+        assert_eq!(feed.unresolved(), 0);
+        let kill = &feed.kills()[0];
+        assert_eq!(
+            (kill.owner, kill.unit_class, kill.unit_name.as_ref()),
+            (3, None, None)
+        );
+        assert_eq!((kill.killer_owner, kill.killer_master_id), (None, None));
+        assert_eq!(kill.value.total(), 0.0);
+        assert_eq!(feed.players()[&3].losses[&-1], 1);
+
+        // Killed by a unit of the same player, nobody gets the kill
+        assert_eq!(feed.kills()[1].killer_owner, Some(1));
+        assert_eq!(feed.players()[&1].losses[&0], 2);
+        assert!(feed.players()[&1].kills.is_empty());
+    }
+}
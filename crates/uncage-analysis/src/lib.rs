@@ -0,0 +1,21 @@
+pub mod build_order;
+pub mod chat;
+pub mod commands;
+pub mod economy;
+pub mod frames;
+pub mod heatmap;
+pub mod kills;
+pub mod market;
+pub mod minimap;
+pub mod population;
+pub mod render;
+pub mod replay;
+pub mod research;
+pub mod timings;
+pub mod units;
+
+#[cfg(test)]
+mod testing;
+
+pub use frames::{decode_frames, read_frames, read_info};
+pub use replay::{Analyzer, Game, Replay};
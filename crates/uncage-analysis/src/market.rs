@@ -0,0 +1,353 @@
+use crate::replay::{self, Analyzer, Game};
+use crate::units::Resources;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use uncage_client::pb;
+use uncage_client::pb::event::Event;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize)]
+pub enum TradeKind {
+    Buy,
+    Sell,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Trade {
+    /// Game time in milliseconds
+    pub time: u32,
+    pub player: i32,
+    pub kind: TradeKind,
+    pub resource: &'static str,
+    /// Resource bought or sold
+    pub amount: f32,
+    /// Gold paid or received
+    pub gold: f32,
+    pub gold_rate: f32,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Tribute {
+    /// Game time in milliseconds
+    pub time: u32,
+    pub sender: i32,
+    pub receiver: i32,
+    pub resource: &'static str,
+    pub sent: f32,
+    /// What arrived after the tribute fee
+    pub received: f32,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type")]
+pub enum LedgerEntry {
+    Trade(Trade),
+    Tribute(Tribute),
+}
+
+impl LedgerEntry {
+    pub fn time(&self) -> u32 {
+        match self {
+            LedgerEntry::Trade(x) => x.time,
+            LedgerEntry::Tribute(x) => x.time,
+        }
+    }
+
+    pub fn involves(&self, player: i32) -> bool {
+        match self {
+            LedgerEntry::Trade(x) => x.player == player,
+            LedgerEntry::Tribute(x) => x.sender == player || x.receiver == player,
+        }
+    }
+}
+
+/// Market prices in gold for 100 of the resource
+#[derive(Debug, Copy, Clone, PartialEq, Serialize)]
+pub struct PricePoint {
+    pub time: u32,
+    pub food: f32,
+    pub wood: f32,
+    pub stone: f32,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct LedgerTotals {
+    pub bought: Resources,
+    pub sold: Resources,
+    pub gold_spent: f32,
+    pub gold_earned: f32,
+    pub tributes_sent: Resources,
+    pub tributes_received: Resources,
+    /// Lost to the tribute fee
+    pub tribute_fees: Resources,
+}
+
+/// Ledger of the `MarketTransaction` and `Tribute` events of every player, together with the
+/// market prices of `World`
+#[derive(Debug, Default, Serialize)]
+pub struct MarketLedger {
+    entries: Vec<LedgerEntry>,
+    prices: Vec<PricePoint>,
+    totals: BTreeMap<i32, LedgerTotals>,
+}
+
+impl MarketLedger {
+    pub fn new() -> MarketLedger {
+        MarketLedger::default()
+    }
+
+    /// All trades and tributes in the order they happened
+    pub fn entries(&self) -> &[LedgerEntry] {
+        &self.entries
+    }
+
+    /// Trades of the player and tributes sent or received by them
+    pub fn player_entries(&self, player: i32) -> impl Iterator<Item = &LedgerEntry> {
+        self.entries.iter().filter(move |x| x.involves(player))
+    }
+
+    /// The prices at the start and after every change
+    pub fn prices(&self) -> &[PricePoint] {
+        &self.prices
+    }
+
+    pub fn totals(&self) -> &BTreeMap<i32, LedgerTotals> {
+        &self.totals
+    }
+
+    pub fn to_json(&self) -> serde_json::Value {
+        serde_json::to_value(self).unwrap()
+    }
+
+    fn trade(&mut self, time: u32, transaction: &pb::event::MarketTransaction) {
+        let attribute = transaction.attribute_type;
+        let (Some(resource), Some(exchanged)) = (
+            Resources::attribute_name(attribute),
+            Resources::from_attribute(attribute, transaction.attribute_exchanged.abs()),
+        ) else {
+            return;
+        };
+
+        // The player gets the resource when buying and gives it away when selling
+        let kind = if transaction.attribute_exchanged >= 0.0 {
+            TradeKind::Buy
+        } else {
+            TradeKind::Sell
+        };
+        let player = transaction.player_id as i32;
+        let gold = transaction.gold_exchanged.abs();
+
+        let totals = self.totals.entry(player).or_default();
+        match kind {
+            TradeKind::Buy => {
+                totals.bought += exchanged;
+                totals.gold_spent += gold;
+            }
+            TradeKind::Sell => {
+                totals.sold += exchanged;
+                totals.gold_earned += gold;
+            }
+        }
+
+        self.entries.push(LedgerEntry::Trade(Trade {
+            time,
+            player,
+            kind,
+            resource,
+            amount: exchanged.total(),
+            gold,
+            gold_rate: transaction.gold_rate,
+        }));
+    }
+
+    fn tribute(&mut self, time: u32, tribute: &pb::event::Tribute) {
+        let attribute = tribute.attribute_type;
+        let sent = tribute.sender_value_change.abs();
+        let received = tribute.receiver_value_change.abs();
+        let (Some(resource), Some(sent_resources), Some(received_resources)) = (
+            Resources::attribute_name(attribute),
+            Resources::from_attribute(attribute, sent),
+            Resources::from_attribute(attribute, received),
+        ) else {
+            return;
+        };
+
+        let sender = tribute.sender_id as i32;
+        let receiver = tribute.receiver_id as i32;
+
+        let totals = self.totals.entry(sender).or_default();
+        totals.tributes_sent += sent_resources;
+        totals.tribute_fees += sent_resources - received_resources;
+        self.totals.entry(receiver).or_default().tributes_received += received_resources;
+
+        self.entries.push(LedgerEntry::Tribute(Tribute {
+            time,
+            sender,
+            receiver,
+            resource,
+            sent,
+            received,
+        }));
+    }
+}
+
+impl Analyzer for MarketLedger {
+    fn before_patch(&mut self, frame: &pb::Frame, _game: &Game) {
+        for event in &frame.event {
+            match &event.event {
+                Some(Event::MarketTransaction(x)) => self.trade(frame.time, x),
+                Some(Event::Tribute(x)) => self.tribute(frame.time, x),
+                _ => {}
+            }
+        }
+    }
+
+    fn after_patch(&mut self, frame: &pb::Frame, game: &Game) {
+        let Some(world) = replay::world(game) else {
+            return;
+        };
+
+        let point = PricePoint {
+            time: frame.time,
+            food: world.food_price,
+            wood: world.wood_price,
+            stone: world.stone_price,
+        };
+        let changed = match self.prices.last() {
+            Some(last) => {
+                (last.food, last.wood, last.stone) != (point.food, point.wood, point.stone)
+            }
+            None => true,
+        };
+        if changed {
+            self.prices.push(point);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{frame_with_events, PatchBuilder};
+    use crate::Replay;
+    use uncage::model::{RootFields, WorldFields};
+
+    #[test]
+    fn test_market_ledger() {
+        let mut ledger = MarketLedger::new();
+        let mut replay = Replay::new();
+        replay.add(&mut ledger);
+
+        let mut patch = PatchBuilder::world();
+        patch
+            .set(WorldFields::WoodPrice, 100.0f32)
+            .set(WorldFields::FoodPrice, 100.0f32)
+            .set(WorldFields::StonePrice, 130.0f32)
+            .pop();
+        replay.apply(&patch.frame(0)).unwrap();
+
+        let mut frame = frame_with_events(
+            5_000,
+            vec![
+                Event::MarketTransaction(pb::event::MarketTransaction {
+                    player_id: 1,
+                    attribute_type: 0,
+                    attribute_before: 50.0,
+                    gold_before: 300.0,
+                    attribute_exchanged: 100.0,
+                    gold_rate: 1.0,
+                    gold_exchanged: -103.0,
+                }),
+                Event::Tribute(pb::event::Tribute {
+                    sender_id: 1,
+                    receiver_id: 2,
+                    attribute_type: 3,
+                    sender_value_before: 197.0,
+                    sender_value_change: -100.0,
+                    receiver_value_before: 0.0,
+                    receiver_value_change: 70.0,
+                }),
+            ],
+        );
+        let mut patch = PatchBuilder::new();
+        patch
+            .push(RootFields::World)
+            .set(WorldFields::FoodPrice, 103.0f32)
+            .pop();
+        frame.patch = patch.build();
+        replay.apply(&frame).unwrap();
+
+        // Prices that didn't change aren't repeated
+        replay.apply(&PatchBuilder::new().frame(6_000)).unwrap();
+
+        assert_eq!(ledger.entries().len(), 2);
+        assert_eq!(ledger.player_entries(2).count(), 1);
+        let LedgerEntry::Trade(trade) = &ledger.entries()[0] else {
+            panic!("expected a trade");
+        };
+        assert_eq!(
+            (trade.kind, trade.resource, trade.amount, trade.gold),
+            (TradeKind::Buy, "food", 100.0, 103.0)
+        );
+
+        let totals = ledger.totals();
+        assert_eq!(totals[&1].bought.food, 100.0);
+        assert_eq!(totals[&1].gold_spent, 103.0);
+        assert_eq!(totals[&1].tributes_sent.gold, 100.0);
+        assert_eq!(totals[&1].tribute_fees.gold, 30.0);
+        assert_eq!(totals[&2].tributes_received.gold, 70.0);
+
+        let prices = ledger
+            .prices()
+            .iter()
+            .map(|x| (x.time, x.food))
+            .collect::<Vec<_>>();
+        assert_eq!(prices, vec![(0, 100.0), (5_000, 103.0)]);
+        assert_eq!(ledger.to_json()["entries"][1]["type"], "Tribute");
+    }
+
+    #[test]
+    fn test_market_edges() {
+        let mut ledger = MarketLedger::new();
+        let mut replay = Replay::new();
+        replay.add(&mut ledger);
+
+        let trade = |attribute_type, attribute_exchanged, gold_exchanged| {
+            Event::MarketTransaction(pb::event::MarketTransaction {
+                player_id: 3,
+                attribute_type,
+                attribute_exchanged,
+                gold_exchanged,
+                ..Default::default()
+            })
+        };
+        // Events before there is a world, of a player that isn't in it and of attributes that
+        // aren't resources
+        let events = vec![
+            trade(1, -100.0, 80.0),
+            trade(7, 100.0, -100.0),
+            Event::Tribute(pb::event::Tribute {
+                sender_id: 3,
+                receiver_id: 4,
+                attribute_type: 4,
+                sender_value_change: -100.0,
+                receiver_value_change: 70.0,
+                ..Default::default()
+            }),
+        ];
+        replay.apply(&frame_with_events(0, events)).unwrap();
+
+        assert!(ledger.prices().is_empty());
+        assert_eq!(ledger.entries().len(), 1);
+        assert_eq!(ledger.player_entries(4).count(), 0);
+        let LedgerEntry::Trade(trade) = &ledger.entries()[0] else {
+            panic!("expected a trade");
+        };
+        assert_eq!(
+            (trade.kind, trade.resource, trade.amount, trade.gold),
+            (TradeKind::Sell, "wood", 100.0, 80.0)
+        );
+        assert_eq!(ledger.totals().len(), 1);
+        assert_eq!(ledger.totals()[&3].sold.wood, 100.0);
+        assert_eq!(ledger.totals()[&3].gold_earned, 80.0);
+    }
+}
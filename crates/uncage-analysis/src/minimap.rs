@@ -0,0 +1,283 @@
+use crate::render::{Canvas, Palette, Projection, Rgba, TRANSPARENT};
+use crate::replay::{self, Game, Replay};
+use crate::units::UnitRole;
+use anyhow::Context;
+use std::collections::BTreeMap;
+use uncage::model::{Entity, WorldNavigation};
+use uncage_client::pb;
+
+/// Bit `player` of a `World.unified_visible_map` tile is set while the player sees it, bit
+/// `player + EXPLORED_SHIFT` once they explored it
+pub const EXPLORED_SHIFT: i32 = 16;
+
+const FOG: Rgba = [0, 0, 0, 112];
+const UNEXPLORED: Rgba = [0, 0, 0, 255];
+
+/// Draws the map the way the game's minimap does: terrain shaded by elevation, and the units and
+/// buildings of the players in their colors
+#[derive(Debug, Clone)]
+pub struct Minimap {
+    palette: Palette,
+    fog: Option<i32>,
+}
+
+impl Minimap {
+    /// The map colors of the models are indexes into the game's `palette`
+    pub fn new(palette: Palette) -> Minimap {
+        Minimap { palette, fog: None }
+    }
+
+    /// Covers what `player` doesn't see with fog, and what they haven't explored in black
+    pub fn set_fog(&mut self, player: Option<i32>) {
+        self.fog = player;
+    }
+
+    pub fn render(&self, game: &Game, width: u32, height: u32) -> anyhow::Result<Canvas> {
+        let world = replay::world(game).context("The game has no world yet")?;
+        let (map_width, map_height) = (world.map_width, world.map_height);
+        anyhow::ensure!(map_width > 0 && map_height > 0, "The map is empty");
+
+        let projection = Projection::new(map_width, map_height, width, height);
+        let mut canvas = Canvas::new(width, height, TRANSPARENT);
+
+        // Tiles are stored row by row
+        let tiles = world.map_tiles();
+        let terrains = world.terrain_types();
+        for (index, tile) in tiles.iter().enumerate() {
+            let Some(terrain) = terrains.get(tile.terrain_type as usize) else {
+                continue;
+            };
+
+            let color = match tile.shape {
+                0 => terrain.map_flat_color,
+                _ => terrain.map_tall_color,
+            };
+            let (x, y) = (index as i32 % map_width, index as i32 / map_width);
+            canvas.fill_polygon(
+                &projection.area(x as f64, y as f64, 1.0, 1.0),
+                shade(self.palette.color(color), tile.elev_level),
+            );
+        }
+
+        let mut colors = BTreeMap::new();
+        for (id, player) in world.players().iter() {
+            let color = world
+                .color_tables()
+                .iter()
+                .find(|x| x.id == player.color_id_current)
+                .map(|x| self.palette.color(x.map_color as u8));
+            if let (Some(id), Some(color)) = (id.index(), color) {
+                colors.insert(id as i32, color);
+            }
+        }
+
+        // Buildings first so units stay visible on top of them
+        let mut entities = vec![];
+        for (_, entity) in world.entities().iter() {
+            let Some(entity) = entity.cast::<Entity>() else {
+                continue;
+            };
+
+            let (Some(role), Some(color)) = (
+                UnitRole::of(game, &entity),
+                colors.get(&(entity.owner_id as i32)),
+            ) else {
+                continue;
+            };
+
+            let (radius_x, radius_y) = match role {
+                UnitRole::Building => replay::master_of(game, &entity)
+                    .map(|x| (x.radius_x.max(0.5), x.radius_y.max(0.5)))
+                    .unwrap_or((0.5, 0.5)),
+                _ => (0.5, 0.5),
+            };
+            entities.push((
+                role != UnitRole::Building,
+                (entity.world_x, entity.world_y),
+                (radius_x, radius_y),
+                *color,
+            ));
+        }
+
+        entities.sort_by_key(|x| x.0);
+        for (_, (x, y), (radius_x, radius_y), color) in entities {
+            let area = projection.area(
+                (x - radius_x) as f64,
+                (y - radius_y) as f64,
+                2.0 * radius_x as f64,
+                2.0 * radius_y as f64,
+            );
+            canvas.fill_polygon(&area, color);
+        }
+
+        if let Some(player) = self.fog.filter(|x| (0..EXPLORED_SHIFT).contains(x)) {
+            for (index, bits) in world.unified_visible_map.iter().enumerate() {
+                let fog = if bits & (1 << player) != 0 {
+                    continue;
+                } else if bits & (1 << (player + EXPLORED_SHIFT)) != 0 {
+                    FOG
+                } else {
+                    UNEXPLORED
+                };
+
+                let (x, y) = (index as i32 % map_width, index as i32 / map_width);
+                canvas.fill_polygon(&projection.area(x as f64, y as f64, 1.0, 1.0), fog);
+            }
+        }
+
+        Ok(canvas)
+    }
+
+    pub fn to_png(&self, game: &Game, width: u32, height: u32) -> anyhow::Result<Vec<u8>> {
+        self.render(game, width, height)?.to_png()
+    }
+}
+
+/// Lightens the color by the tile's elevation
+fn shade(color: Rgba, elevation: u8) -> Rgba {
+    let amount = (elevation as u32 * 16).min(128);
+    let channel = |x: u8| (x as u32 + (255 - x as u32) * amount / 255) as u8;
+    [
+        channel(color[0]),
+        channel(color[1]),
+        channel(color[2]),
+        color[3],
+    ]
+}
+
+/// Replays the frames up to `time` and draws the minimap of that moment as PNG
+pub fn minimap_at(
+    frames: &[pb::Frame],
+    time: u32,
+    minimap: &Minimap,
+    width: u32,
+    height: u32,
+) -> anyhow::Result<Vec<u8>> {
+    let mut replay = Replay::new();
+    for frame in frames.iter().take_while(|x| x.time <= time) {
+        replay.apply(frame)?;
+    }
+
+    minimap.to_png(replay.game(), width, height)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::PatchBuilder;
+    use uncage::model::{ColorTable, ColorTableFields, CombatEntity, EntityFields, MapTile};
+    use uncage::model::{MapTileFields, PlayerFields, TerrainType, TerrainTypeFields, WorldFields};
+
+    const GRASS: Rgba = [0, 160, 0, 255];
+    const HILL: Rgba = [0, 100, 0, 255];
+    const BLUE: Rgba = [0, 0, 255, 255];
+
+    fn palette() -> Palette {
+        let mut colors = [[0, 0, 0, 255]; 256];
+        colors[10] = GRASS;
+        colors[11] = HILL;
+        colors[20] = BLUE;
+        Palette::new(colors)
+    }
+
+    fn frame(time: u32) -> pb::Frame {
+        let mut patch = PatchBuilder::world();
+        patch
+            .set(WorldFields::MapWidth, 2)
+            .set(WorldFields::MapHeight, 2);
+        patch
+            .create_index::<TerrainType, _>(WorldFields::TerrainTypes, 0)
+            .set(TerrainTypeFields::MapFlatColor, 10u8)
+            .set(TerrainTypeFields::MapTallColor, 11u8)
+            .pop();
+        patch
+            .create_index::<ColorTable, _>(WorldFields::ColorTables, 0)
+            .set(ColorTableFields::Id, 3)
+            .set(ColorTableFields::MapColor, 20)
+            .pop();
+        for index in 0..4 {
+            // The tile at (0, 1) is a slope
+            patch
+                .create_index::<MapTile, _>(WorldFields::MapTiles, index)
+                .set(MapTileFields::TerrainType, 0u8)
+                .set(MapTileFields::Shape, (index == 2) as u8)
+                .pop();
+            let visible: u32 = if index == 3 { 1 << 17 } else { 0 };
+            patch.insert(WorldFields::UnifiedVisibleMap, index, visible);
+        }
+        patch
+            .player(1, "Ana")
+            .set(PlayerFields::ColorIdCurrent, 3)
+            .pop();
+        patch.create_entity::<CombatEntity>(1, 4, 1);
+        patch
+            .set(EntityFields::WorldX, 1.5f32)
+            .set(EntityFields::WorldY, 0.5f32)
+            .pop();
+        patch.pop();
+        patch.frame(time)
+    }
+
+    fn game() -> Replay<'static> {
+        let mut replay = Replay::new();
+        replay.apply(&frame(0)).unwrap();
+        replay
+    }
+
+    #[test]
+    fn test_minimap() {
+        let replay = game();
+        let mut minimap = Minimap::new(palette());
+        let canvas = minimap.render(replay.game(), 200, 100).unwrap();
+
+        let projection = Projection::new(2, 2, 200, 100);
+        let pixel = |canvas: &Canvas, x: f64, y: f64| {
+            let (x, y) = projection.project(x, y);
+            canvas.pixel(x as u32, y as u32)
+        };
+        assert_eq!(pixel(&canvas, 0.5, 0.5), GRASS);
+        assert_eq!(pixel(&canvas, 0.5, 1.5), HILL);
+        assert_eq!(pixel(&canvas, 1.5, 0.5), BLUE);
+        assert_eq!(pixel(&canvas, 1.5, 1.5), GRASS);
+
+        minimap.set_fog(Some(1));
+        let canvas = minimap.render(replay.game(), 200, 100).unwrap();
+        assert_eq!(pixel(&canvas, 0.5, 0.5), UNEXPLORED);
+        assert_ne!(pixel(&canvas, 1.5, 1.5), GRASS);
+        assert_ne!(pixel(&canvas, 1.5, 1.5), UNEXPLORED);
+
+        let png = minimap.to_png(replay.game(), 200, 100).unwrap();
+        assert!(png.starts_with(b"\x89PNG"));
+    }
+
+    #[test]
+    fn test_minimap_edges() {
+        let mut minimap = Minimap::new(palette());
+        assert!(minimap.render(Replay::new().game(), 200, 100).is_err());
+        assert!(minimap_at(&[], 0, &minimap, 200, 100).is_err());
+        let empty = PatchBuilder::world().pop().frame(0);
+        assert!(minimap_at(&[empty], 0, &minimap, 200, 100).is_err());
+        // Frames after `time` aren't replayed
+        assert!(minimap_at(&[frame(5_000)], 1_000, &minimap, 200, 100).is_err());
+        assert!(minimap_at(&[frame(5_000)], 5_000, &minimap, 200, 100).is_ok());
+
+        let replay = game();
+        let projection = Projection::new(2, 2, 200, 100);
+        let pixel = |canvas: &Canvas, x: f64, y: f64| {
+            let (x, y) = projection.project(x, y);
+            canvas.pixel(x as u32, y as u32)
+        };
+
+        // A player that isn't in the world hasn't explored anything
+        minimap.set_fog(Some(7));
+        let canvas = minimap.render(replay.game(), 200, 100).unwrap();
+        assert_eq!(pixel(&canvas, 1.5, 1.5), UNEXPLORED);
+
+        // Players out of the range of the visibility bits get no fog
+        for player in [-1, EXPLORED_SHIFT] {
+            minimap.set_fog(Some(player));
+            let canvas = minimap.render(replay.game(), 200, 100).unwrap();
+            assert_eq!(pixel(&canvas, 0.5, 0.5), GRASS);
+        }
+    }
+}
@@ -0,0 +1,244 @@
+use crate::replay::{self, Analyzer, Game};
+use crate::units::{Resources, UnitCatalog, UnitRole};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::num::NonZeroU32;
+use uncage::model::{Entity, WorldNavigation};
+use uncage_client::pb;
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct PopulationSample {
+    /// Game time in milliseconds
+    pub time: u32,
+    pub player: i32,
+    pub villagers: usize,
+    pub military: usize,
+    pub civilians: usize,
+    pub buildings: usize,
+    /// Count of every unit and building by master id
+    pub units: BTreeMap<i16, usize>,
+    /// What the living military units cost
+    pub army_value: Resources,
+}
+
+/// Counts the living units and buildings of every player at a fixed game time resolution. The
+/// codes of `Entity.state` aren't documented, units without hit points count as dying or dead
+#[derive(Debug)]
+pub struct PopulationTracker {
+    catalog: UnitCatalog,
+    resolution: NonZeroU32,
+    /// `None` once the next sample would be past the largest game time
+    next_sample: Option<u32>,
+    samples: Vec<PopulationSample>,
+}
+
+impl PopulationTracker {
+    /// Takes a sample every `resolution` milliseconds of game time, the army value is taken from
+    /// the costs in `catalog` as `MasterEntity` doesn't have them
+    pub fn new(catalog: UnitCatalog, resolution: NonZeroU32) -> PopulationTracker {
+        PopulationTracker {
+            catalog,
+            resolution,
+            next_sample: Some(0),
+            samples: vec![],
+        }
+    }
+
+    pub fn samples(&self) -> &[PopulationSample] {
+        &self.samples
+    }
+
+    pub fn player_samples(&self, player: i32) -> impl Iterator<Item = &PopulationSample> {
+        self.samples.iter().filter(move |x| x.player == player)
+    }
+
+    pub fn to_json(&self) -> serde_json::Value {
+        serde_json::to_value(&self.samples).unwrap()
+    }
+}
+
+impl Analyzer for PopulationTracker {
+    fn after_patch(&mut self, frame: &pb::Frame, game: &Game) {
+        if self.next_sample.is_none_or(|x| frame.time < x) {
+            return;
+        }
+
+        let Some(world) = replay::world(game) else {
+            return;
+        };
+
+        let resolution = self.resolution.get();
+        self.next_sample = (frame.time - frame.time % resolution).checked_add(resolution);
+
+        let mut players = BTreeMap::<i32, PopulationSample>::new();
+        for id in world.players().keys() {
+            if let Some(id) = id.index().filter(|x| *x > 0) {
+                players.insert(id as i32, PopulationSample::default());
+            }
+        }
+
+        for (_, entity) in world.entities().iter() {
+            let Some(entity) = entity.cast::<Entity>() else {
+                continue;
+            };
+
+            if entity.hp <= 0.0 {
+                continue;
+            }
+
+            let Some(role) = UnitRole::of(game, &entity) else {
+                continue;
+            };
+
+            let sample = players.entry(entity.owner_id as i32).or_default();
+            *sample.units.entry(entity.master_id).or_default() += 1;
+            match role {
+                UnitRole::Villager => sample.villagers += 1,
+                UnitRole::Military => {
+                    sample.military += 1;
+                    sample.army_value += self.catalog.cost(entity.master_id);
+                }
+                UnitRole::Civilian => sample.civilians += 1,
+                UnitRole::Building => sample.buildings += 1,
+            }
+        }
+
+        for (player, mut sample) in players {
+            sample.time = frame.time;
+            sample.player = player;
+            self.samples.push(sample);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::PatchBuilder;
+    use crate::units::UnitInfo;
+    use crate::Replay;
+    use uncage::model::{ActionEntity, BuildingEntity, CombatEntity, MissileEntity};
+    use uncage::model::{EntityFields, RootFields, WorldFields};
+    use uncage_model::Model;
+
+    fn unit<M: Model>(patch: &mut PatchBuilder, id: i32, master_id: i16, owner: i8) {
+        patch
+            .create_entity::<M>(id, master_id, owner)
+            .set(EntityFields::Hp, 25.0f32)
+            .pop();
+    }
+
+    fn millis(value: u32) -> NonZeroU32 {
+        NonZeroU32::new(value).unwrap()
+    }
+
+    #[test]
+    fn test_population() {
+        let mut catalog = UnitCatalog::new();
+        catalog.insert(
+            4,
+            UnitInfo {
+                name: "Archer".into(),
+                cost: Resources {
+                    wood: 25.0,
+                    gold: 45.0,
+                    ..Default::default()
+                },
+            },
+        );
+
+        let mut population = PopulationTracker::new(catalog, millis(10_000));
+        let mut replay = Replay::new();
+        replay.add(&mut population);
+
+        let mut patch = PatchBuilder::world();
+        patch.player(1, "Ana").master(4, 0).master(594, 58).pop();
+        patch.player(2, "Bo").pop();
+        unit::<CombatEntity>(&mut patch, 1, 83, 1);
+        unit::<CombatEntity>(&mut patch, 2, 4, 1);
+        unit::<CombatEntity>(&mut patch, 3, 4, 1);
+        unit::<ActionEntity>(&mut patch, 4, 594, 1);
+        unit::<BuildingEntity>(&mut patch, 5, 109, 1);
+        // Gaia and missiles don't count
+        unit::<CombatEntity>(&mut patch, 6, 4, 0);
+        unit::<MissileEntity>(&mut patch, 7, 363, 1);
+        patch.pop();
+        replay.apply(&patch.frame(0)).unwrap();
+
+        let mut patch = PatchBuilder::new();
+        patch
+            .push(RootFields::World)
+            .remove_key(WorldFields::Entities, 3)
+            .pop();
+        replay.apply(&patch.frame(5_000)).unwrap();
+        replay.apply(&PatchBuilder::new().frame(10_000)).unwrap();
+
+        let samples = population.player_samples(1).collect::<Vec<_>>();
+        assert_eq!(samples.len(), 2);
+        let counts = |x: &PopulationSample| (x.villagers, x.military, x.civilians, x.buildings);
+        assert_eq!(counts(samples[0]), (1, 2, 1, 1));
+        assert_eq!(samples[0].units[&4], 2);
+        assert_eq!(samples[0].army_value.gold, 90.0);
+        assert_eq!(counts(samples[1]), (1, 1, 1, 1));
+        assert_eq!(samples[1].time, 10_000);
+
+        let bo = population.player_samples(2).next().unwrap();
+        assert_eq!(counts(bo), (0, 0, 0, 0));
+    }
+
+    #[test]
+    fn test_population_edges() {
+        let mut population = PopulationTracker::new(UnitCatalog::new(), millis(1));
+        let mut replay = Replay::new();
+        replay.add(&mut population);
+
+        // Nothing to sample before there is a world
+        replay.apply(&PatchBuilder::new().frame(0)).unwrap();
+
+        // A player without units, a unit without hit points and one of a player that isn't in the world
+        let mut patch = PatchBuilder::world();
+        patch.player(1, "Ana").pop();
+        patch.create_entity::<CombatEntity>(1, 4, 1).pop();
+        unit::<CombatEntity>(&mut patch, 2, 4, 3);
+        patch.pop();
+        replay.apply(&patch.frame(0)).unwrap();
+        replay.apply(&PatchBuilder::new().frame(0)).unwrap();
+        replay.apply(&PatchBuilder::new().frame(2)).unwrap();
+
+        let samples = population
+            .samples()
+            .iter()
+            .map(|x| (x.time, x.player, x.military, x.army_value.total()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            samples,
+            vec![
+                (0, 1, 0, 0.0),
+                (0, 3, 1, 0.0),
+                (2, 1, 0, 0.0),
+                (2, 3, 1, 0.0)
+            ]
+        );
+        assert!(population.player_samples(1).all(|x| x.units.is_empty()));
+    }
+
+    #[test]
+    fn test_population_at_the_end_of_game_time() {
+        let mut population = PopulationTracker::new(UnitCatalog::new(), millis(10));
+        let mut replay = Replay::new();
+        replay.add(&mut population);
+
+        let mut patch = PatchBuilder::world();
+        patch.player(1, "Ana").pop();
+        patch.pop();
+        replay.apply(&patch.frame(u32::MAX - 5)).unwrap();
+        replay.apply(&PatchBuilder::new().frame(u32::MAX)).unwrap();
+
+        let times = population
+            .samples()
+            .iter()
+            .map(|x| x.time)
+            .collect::<Vec<_>>();
+        assert_eq!(times, vec![u32::MAX - 5]);
+    }
+}
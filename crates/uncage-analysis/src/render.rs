@@ -0,0 +1,292 @@
+use anyhow::Context;
+use std::f64::consts::FRAC_1_SQRT_2;
+use std::fmt::Write;
+
+pub type Rgba = [u8; 4];
+
+pub const TRANSPARENT: Rgba = [0, 0, 0, 0];
+pub const WHITE: Rgba = [255, 255, 255, 255];
+
+/// Maps tile coordinates onto an image the way the game draws the map: rotated by -45° and
+/// scaled by 15 horizontally and 5 vertically, stretched to fill the image
+#[derive(Debug, Copy, Clone)]
+pub struct Projection {
+    scale_x: f64,
+    scale_y: f64,
+    offset_x: f64,
+    offset_y: f64,
+}
+
+impl Projection {
+    pub fn new(map_width: i32, map_height: i32, image_width: u32, image_height: u32) -> Projection {
+        let (w, h) = (map_width as f64, map_height as f64);
+        let corners = [(0.0, 0.0), (w, h), (w, 0.0), (0.0, h)].map(|(x, y)| isometric(x, y));
+
+        let min_x = corners.iter().map(|x| x.0).fold(f64::MAX, f64::min);
+        let max_x = corners.iter().map(|x| x.0).fold(f64::MIN, f64::max);
+        let min_y = corners.iter().map(|x| x.1).fold(f64::MAX, f64::min);
+        let max_y = corners.iter().map(|x| x.1).fold(f64::MIN, f64::max);
+
+        let scale_x = image_width as f64 / (max_x - min_x).max(1.0);
+        let scale_y = image_height as f64 / (max_y - min_y).max(1.0);
+        Projection {
+            scale_x,
+            scale_y,
+            offset_x: -min_x * scale_x,
+            offset_y: -min_y * scale_y,
+        }
+    }
+
+    /// The image position of the world position
+    pub fn project(&self, x: f64, y: f64) -> (f64, f64) {
+        let (x, y) = isometric(x, y);
+        (
+            x * self.scale_x + self.offset_x,
+            y * self.scale_y + self.offset_y,
+        )
+    }
+
+    /// The corners of a `width` by `height` area of tiles
+    pub fn area(&self, x: f64, y: f64, width: f64, height: f64) -> [(f64, f64); 4] {
+        [
+            self.project(x, y),
+            self.project(x + width, y),
+            self.project(x + width, y + height),
+            self.project(x, y + height),
+        ]
+    }
+}
+
+fn isometric(x: f64, y: f64) -> (f64, f64) {
+    (
+        15.0 * (x + y) * FRAC_1_SQRT_2,
+        5.0 * (y - x) * FRAC_1_SQRT_2,
+    )
+}
+
+/// RGBA image to draw polygons on
+#[derive(Debug, Clone)]
+pub struct Canvas {
+    width: u32,
+    height: u32,
+    pixels: Vec<Rgba>,
+}
+
+impl Canvas {
+    pub fn new(width: u32, height: u32, background: Rgba) -> Canvas {
+        Canvas {
+            width,
+            height,
+            pixels: vec![background; width as usize * height as usize],
+        }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn pixel(&self, x: u32, y: u32) -> Rgba {
+        self.pixels[(y * self.width + x) as usize]
+    }
+
+    /// Fills every pixel whose center is inside the polygon, blending by the color's alpha
+    pub fn fill_polygon(&mut self, points: &[(f64, f64)], color: Rgba) {
+        if points.len() < 3 {
+            return;
+        }
+
+        let min_y = points.iter().map(|x| x.1).fold(f64::MAX, f64::min);
+        let max_y = points.iter().map(|x| x.1).fold(f64::MIN, f64::max);
+        let from = (min_y - 0.5).ceil().max(0.0) as u32;
+        let to = ((max_y - 0.5).floor() + 1.0).clamp(0.0, self.height as f64) as u32;
+
+        let mut crossings = vec![];
+        for row in from..to {
+            let y = row as f64 + 0.5;
+            crossings.clear();
+            for (index, (x1, y1)) in points.iter().enumerate() {
+                let (x2, y2) = points[(index + 1) % points.len()];
+                if (*y1 <= y) != (y2 <= y) {
+                    crossings.push(x1 + (y - y1) / (y2 - y1) * (x2 - x1));
+                }
+            }
+            crossings.sort_by(f64::total_cmp);
+
+            for pair in crossings.chunks_exact(2) {
+                let start = (pair[0] - 0.5).ceil().max(0.0) as u32;
+                let end = ((pair[1] - 0.5).floor() + 1.0).clamp(0.0, self.width as f64) as u32;
+                for column in start..end {
+                    let pixel = &mut self.pixels[(row * self.width + column) as usize];
+                    *pixel = blend(*pixel, color);
+                }
+            }
+        }
+    }
+
+    pub fn to_png(&self) -> anyhow::Result<Vec<u8>> {
+        let mut png = vec![];
+        let mut encoder = png::Encoder::new(&mut png, self.width, self.height);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+
+        let mut writer = encoder
+            .write_header()
+            .context("Failed to write PNG header")?;
+        writer
+            .write_image_data(self.pixels.as_flattened())
+            .context("Failed to write PNG data")?;
+        writer.finish().context("Failed to finish PNG")?;
+
+        Ok(png)
+    }
+}
+
+fn blend(below: Rgba, above: Rgba) -> Rgba {
+    let alpha = above[3] as u32;
+    let below_alpha = below[3] as u32 * (255 - alpha) / 255;
+    let out_alpha = alpha + below_alpha;
+    if out_alpha == 0 {
+        return TRANSPARENT;
+    }
+
+    let channel = |index: usize| {
+        ((above[index] as u32 * alpha + below[index] as u32 * below_alpha) / out_alpha) as u8
+    };
+    [channel(0), channel(1), channel(2), out_alpha as u8]
+}
+
+/// The game's 256 color palette, which the map colors of the models are indexes of
+#[derive(Debug, Clone)]
+pub struct Palette {
+    colors: Vec<Rgba>,
+}
+
+impl Palette {
+    pub fn new(colors: [Rgba; 256]) -> Palette {
+        Palette {
+            colors: colors.to_vec(),
+        }
+    }
+
+    /// Reads a palette in the JASC-PAL format of the game's palette files
+    pub fn from_jasc(text: &str) -> anyhow::Result<Palette> {
+        let mut lines = text.lines().map(str::trim);
+        anyhow::ensure!(lines.next() == Some("JASC-PAL"), "Not a JASC-PAL palette");
+        lines.next().context("Palette version is missing")?;
+        let count = lines
+            .next()
+            .and_then(|x| x.parse::<usize>().ok())
+            .context("Palette color count is missing")?;
+
+        let mut colors = vec![];
+        for line in lines.filter(|x| !x.is_empty()).take(count.min(256)) {
+            let channels = line
+                .split_whitespace()
+                .map(|x| x.parse::<u8>())
+                .collect::<Result<Vec<_>, _>>()
+                .with_context(|| format!("Invalid palette color {:?}", line))?;
+            let [r, g, b] = channels[..] else {
+                anyhow::bail!("Invalid palette color {:?}", line);
+            };
+            colors.push([r, g, b, 255]);
+        }
+
+        anyhow::ensure!(
+            colors.len() == count.min(256),
+            "Palette has {} of {} colors",
+            colors.len(),
+            count
+        );
+        colors.resize(256, [0, 0, 0, 255]);
+        Ok(Palette { colors })
+    }
+
+    pub fn color(&self, index: u8) -> Rgba {
+        self.colors[index as usize]
+    }
+}
+
+/// SVG document made of filled polygons
+#[derive(Debug, Clone)]
+pub struct Svg {
+    content: String,
+}
+
+impl Svg {
+    pub fn new(width: u32, height: u32) -> Svg {
+        Svg {
+            content: format!(
+                "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{0}\" height=\"{1}\" viewBox=\"0 0 {0} {1}\">\n",
+                width, height
+            ),
+        }
+    }
+
+    pub fn polygon(&mut self, points: &[(f64, f64)], color: Rgba) {
+        self.content.push_str("<polygon points=\"");
+        for (index, (x, y)) in points.iter().enumerate() {
+            let separator = if index == 0 { "" } else { " " };
+            write!(self.content, "{}{:.2},{:.2}", separator, x, y).unwrap();
+        }
+        write!(
+            self.content,
+            "\" fill=\"#{:02x}{:02x}{:02x}\"",
+            color[0], color[1], color[2]
+        )
+        .unwrap();
+        if color[3] != 255 {
+            write!(
+                self.content,
+                " fill-opacity=\"{:.3}\"",
+                color[3] as f64 / 255.0
+            )
+            .unwrap();
+        }
+        self.content.push_str("/>\n");
+    }
+
+    pub fn finish(mut self) -> String {
+        self.content.push_str("</svg>\n");
+        self.content
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_projection_and_canvas() {
+        let projection = Projection::new(10, 10, 300, 100);
+        let corners = projection.area(0.0, 0.0, 10.0, 10.0);
+        let rounded = corners.map(|(x, y)| (x.round(), y.round()));
+        assert_eq!(
+            rounded,
+            [(0.0, 50.0), (150.0, 0.0), (300.0, 50.0), (150.0, 100.0)]
+        );
+
+        let mut canvas = Canvas::new(300, 100, TRANSPARENT);
+        canvas.fill_polygon(&corners, WHITE);
+        assert_eq!(canvas.pixel(150, 50), WHITE);
+        assert_eq!(canvas.pixel(2, 2), TRANSPARENT);
+        canvas.fill_polygon(&corners, [255, 0, 0, 0]);
+        assert_eq!(canvas.pixel(150, 50), WHITE);
+
+        let png = canvas.to_png().unwrap();
+        assert!(png.starts_with(b"\x89PNG"));
+
+        let palette = Palette::from_jasc("JASC-PAL\r\n0100\r\n2\r\n1 2 3\r\n4 5 6\r\n").unwrap();
+        assert_eq!(palette.color(1), [4, 5, 6, 255]);
+        assert!(Palette::from_jasc("JASC-PAL\n0100\n2\n1 2 3\n").is_err());
+
+        let mut svg = Svg::new(300, 100);
+        svg.polygon(&rounded, [255, 0, 0, 255]);
+        assert!(svg
+            .finish()
+            .contains("<polygon points=\"0.00,50.00 150.00,0.00 300.00,50.00 150.00,100.00\" fill=\"#ff0000\"/>"));
+    }
+}
@@ -0,0 +1,149 @@
+use bytes::Bytes;
+use uncage::model::{
+    Entity, EntityNavigation, MasterEntity, Models, Player, PlayerNavigation, Root, RootNavigation,
+    World, WorldNavigation,
+};
+use uncage_client::pb;
+use uncage_model::patcher::{Patcher, PatcherSelectorMatch};
+use uncage_model::{Document, Fields, Path, PathSubSegment, Selector, TypedBorrow};
+
+/// The game state frames are applied to
+pub type Game = Document<Root, Models>;
+
+pub type Borrow<'a, T> = TypedBorrow<'a, T, Root, Models>;
+
+/// Looks at a replay frame by frame
+pub trait Analyzer {
+    /// Called before the patch of the frame is applied. This is the state the frame's events and
+    /// commands refer to, entities killed in the frame are still there
+    fn before_patch(&mut self, _frame: &pb::Frame, _game: &Game) {}
+
+    /// Called once the patch of the frame is applied
+    fn after_patch(&mut self, _frame: &pb::Frame, _game: &Game) {}
+
+    /// Selectors to watch, read once when the analyzer is added to a [Replay]
+    fn selectors(&self) -> Vec<Selector> {
+        vec![]
+    }
+
+    /// Called after the patch is applied for every change matching the selector at `index`
+    fn selected(
+        &mut self,
+        _frame: &pb::Frame,
+        _game: &Game,
+        _index: usize,
+        _selected: &PatcherSelectorMatch,
+    ) {
+    }
+}
+
+/// Applies frames to a [Game] and hands them to its analyzers
+pub struct Replay<'a> {
+    patcher: Patcher<Root, Models>,
+    analyzers: Vec<&'a mut dyn Analyzer>,
+    /// Analyzer and index of its selector, by selector key
+    selectors: Vec<(usize, usize)>,
+}
+
+impl<'a> Default for Replay<'a> {
+    fn default() -> Self {
+        Replay::new()
+    }
+}
+
+impl<'a> Replay<'a> {
+    /// The patcher is tolerant, so replays of newer game versions can still be analyzed with the
+    /// fields and models this version knows
+    pub fn new() -> Replay<'a> {
+        let mut patcher = Patcher::new(Document::new());
+        patcher.set_tolerant(true);
+        Replay {
+            patcher,
+            analyzers: vec![],
+            selectors: vec![],
+        }
+    }
+
+    pub fn add(&mut self, analyzer: &'a mut dyn Analyzer) {
+        for (index, selector) in analyzer.selectors().into_iter().enumerate() {
+            self.patcher.add_selector(self.selectors.len(), selector);
+            self.selectors.push((self.analyzers.len(), index));
+        }
+
+        self.analyzers.push(analyzer);
+    }
+
+    pub fn game(&self) -> &Game {
+        self.patcher.document()
+    }
+
+    pub fn patcher(&self) -> &Patcher<Root, Models> {
+        &self.patcher
+    }
+
+    pub fn apply(&mut self, frame: &pb::Frame) -> anyhow::Result<()> {
+        for analyzer in self.analyzers.iter_mut() {
+            analyzer.before_patch(frame, self.patcher.document());
+        }
+
+        let matches = self
+            .patcher
+            .apply_patch(Bytes::copy_from_slice(&frame.patch))?;
+
+        let game = self.patcher.document();
+        for selected in &matches {
+            let (analyzer, index) = self.selectors[selected.selector_key];
+            self.analyzers[analyzer].selected(frame, game, index, selected);
+        }
+
+        for analyzer in self.analyzers.iter_mut() {
+            analyzer.after_patch(frame, game);
+        }
+
+        Ok(())
+    }
+}
+
+pub fn world(game: &Game) -> Option<Borrow<'_, World>> {
+    game.root().world()
+}
+
+pub fn player(game: &Game, id: i32) -> Option<Borrow<'_, Player>> {
+    world(game)?.players().get(id)
+}
+
+pub fn entity(game: &Game, id: i32) -> Option<Borrow<'_, Entity>> {
+    world(game)?.entities().get(id)?.cast::<Entity>()
+}
+
+pub fn owner_of(game: &Game, id: i32) -> Option<i32> {
+    entity(game, id).map(|x| x.owner_id as i32)
+}
+
+/// The integer key of the map entry of `field` the path goes through
+pub fn path_key<F: Fields>(path: &Path, field: F) -> Option<i32> {
+    path.segments()
+        .iter()
+        .find(|x| x.model_type() == field.model() && x.field_description().index == field.field())
+        .and_then(|x| match x.sub() {
+            PathSubSegment::Key(key) => key.index().map(|x| x as i32),
+            _ => None,
+        })
+}
+
+/// The master the entity was created from, looked up in its owner's masters if the entity doesn't
+/// reference it
+pub fn master_of<'a>(
+    game: &'a Game,
+    entity: &Borrow<'a, Entity>,
+) -> Option<Borrow<'a, MasterEntity>> {
+    entity
+        .own_master()
+        .and_then(|x| x.cast::<MasterEntity>())
+        .or_else(|| {
+            player(game, entity.owner_id as i32)?
+                .master_entities()
+                .get(entity.master_id as i32)?
+                .cast::<MasterEntity>()
+        })
+}
@@ -0,0 +1,84 @@
+use crate::replay::{self, Game};
+use std::collections::BTreeMap;
+use uncage::model::{PlayerFields, ResearchState, ResearchStateFields, WorldFields};
+use uncage_model::patcher::PatcherSelectorMatch;
+use uncage_model::{ModelWithDocument, Selector};
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum ResearchEvent {
+    Started {
+        player: i32,
+        tech_id: i32,
+    },
+    /// Techs that can be researched multiple times complete once per research
+    Completed {
+        player: i32,
+        tech_id: i32,
+        times_researched: i16,
+    },
+}
+
+/// Turns the changes of `Player.research_states` into the start and completion of researches.
+///
+/// The codes of `ResearchState.state` aren't documented, so a research starts when its
+/// `research_done` grows past zero and completes when its `times_researched` goes up. A tech
+/// researched again only starts again once its `research_done` went back to zero
+#[derive(Debug, Default)]
+pub struct ResearchTracker {
+    /// Whether a research has progress and how often it was done, by player and technology
+    states: BTreeMap<(i32, i32), (bool, i16)>,
+}
+
+impl ResearchTracker {
+    pub fn new() -> ResearchTracker {
+        ResearchTracker::default()
+    }
+
+    /// The selectors of the changes to hand to [ResearchTracker::changed]
+    pub fn selectors() -> [Selector; 2] {
+        [
+            Selector::new().field(ResearchStateFields::ResearchDone),
+            Selector::new().field(ResearchStateFields::TimesResearched),
+        ]
+    }
+
+    /// The events of a change, a research started and completed at once reports both in order
+    pub fn changed(
+        &mut self,
+        game: &Game,
+        selected: &PatcherSelectorMatch,
+    ) -> impl Iterator<Item = ResearchEvent> {
+        let mut started = None;
+        let mut completed = None;
+        if let Some((player, tech_id, in_progress, times_researched)) = read(game, selected) {
+            let (had_progress, previous_times) = self
+                .states
+                .insert((player, tech_id), (in_progress, times_researched))
+                .unwrap_or_default();
+
+            if in_progress && !had_progress {
+                started = Some(ResearchEvent::Started { player, tech_id });
+            }
+
+            if times_researched > previous_times {
+                completed = Some(ResearchEvent::Completed {
+                    player,
+                    tech_id,
+                    times_researched,
+                });
+            }
+        }
+
+        started.into_iter().chain(completed)
+    }
+}
+
+/// The player, tech, whether it has progress and how often it was researched of a change
+fn read(game: &Game, selected: &PatcherSelectorMatch) -> Option<(i32, i32, bool, i16)> {
+    let player = replay::path_key(&selected.path, WorldFields::Players)?;
+    let tech_id = replay::path_key(&selected.path, PlayerFields::ResearchStates)?;
+    game.by_id(selected.object_id).and_then(|x| {
+        x.cast_ref::<ResearchState>()
+            .map(|x| (player, tech_id, x.research_done > 0.0, x.times_researched))
+    })
+}
@@ -0,0 +1,170 @@
+use bytes::{BufMut, BytesMut};
+use std::any::Any;
+use uncage::model::{Entity, EntityFields};
+use uncage::model::{
+    MasterEntity, MasterEntityFields, Models, Player, PlayerFields, RootFields, World, WorldFields,
+};
+use uncage_client::pb;
+use uncage_model::patcher::PatchAction;
+use uncage_model::{
+    FieldDescription, FieldType, Fields, MapKey, Model, ModelCollection, ValueType,
+};
+
+/// Writes patches for the game models by hand, values are written like the field of
+/// `uncage::model` they are assigned to
+pub(crate) struct PatchBuilder(BytesMut);
+
+impl PatchBuilder {
+    pub fn new() -> PatchBuilder {
+        PatchBuilder(BytesMut::new())
+    }
+
+    /// Creates the world and leaves it on top of the stack
+    pub fn world() -> PatchBuilder {
+        let mut patch = PatchBuilder::new();
+        patch.create::<World, _>(RootFields::World);
+        patch
+    }
+
+    fn op<F: Fields>(&mut self, action: PatchAction, field: F) -> &'static FieldDescription {
+        self.0.put_u8(action as u8);
+        self.0.put_u8(field.field() as u8);
+        description(field)
+    }
+
+    /// Map keys are written with the key type of the map, list indexes as `i32`
+    fn key(&mut self, field: &FieldDescription, key: i32) {
+        let key_type = match field.value_type {
+            ValueType::Map { key } => key,
+            _ => FieldType::Int32,
+        };
+
+        key_type
+            .write_key(&MapKey::Int(key as i64), &mut self.0)
+            .unwrap();
+    }
+
+    fn value(&mut self, field: &FieldDescription, value: &dyn Any) {
+        field
+            .write_value(value, &mut self.0)
+            .unwrap_or_else(|e| panic!("{}: {:?}", field.field_name, e));
+    }
+
+    pub fn pop(&mut self) -> &mut Self {
+        self.0.put_u8(PatchAction::Pop as u8);
+        self
+    }
+
+    pub fn push<F: Fields>(&mut self, field: F) -> &mut Self {
+        self.op(PatchAction::PushField, field);
+        self
+    }
+
+    pub fn create<M: Model, F: Fields>(&mut self, field: F) -> &mut Self {
+        self.op(PatchAction::PushCreateAndAssignField, field);
+        self.0.put_u8(M::model_type() as u8);
+        self
+    }
+
+    pub fn push_key<F: Fields>(&mut self, field: F, key: i32) -> &mut Self {
+        let field = self.op(PatchAction::PushKey, field);
+        self.key(field, key);
+        self
+    }
+
+    pub fn create_key<M: Model, F: Fields>(&mut self, field: F, key: i32) -> &mut Self {
+        let field = self.op(PatchAction::PushCreateAndAssignKey, field);
+        self.0.put_u8(M::model_type() as u8);
+        self.key(field, key);
+        self
+    }
+
+    pub fn create_index<M: Model, F: Fields>(&mut self, field: F, index: i32) -> &mut Self {
+        let field = self.op(PatchAction::PushCreateAndInsert, field);
+        self.0.put_u8(M::model_type() as u8);
+        self.key(field, index);
+        self
+    }
+
+    pub fn insert<F: Fields, T: Any>(&mut self, field: F, index: i32, value: T) -> &mut Self {
+        let field = self.op(PatchAction::Insert, field);
+        self.key(field, index);
+        self.value(field, &value);
+        self
+    }
+
+    pub fn remove_key<F: Fields>(&mut self, field: F, key: i32) -> &mut Self {
+        let field = self.op(PatchAction::Remove, field);
+        self.key(field, key);
+        self
+    }
+
+    pub fn set<F: Fields, T: Any>(&mut self, field: F, value: T) -> &mut Self {
+        let field = self.op(PatchAction::AssignField, field);
+        self.value(field, &value);
+        self
+    }
+
+    /// Adds a player to the world on top of the stack and leaves the player on top
+    pub fn player(&mut self, id: i32, name: &str) -> &mut Self {
+        self.create_key::<Player, _>(WorldFields::Players, id)
+            .set(PlayerFields::Id, id as i8)
+            .set(PlayerFields::Name, name.to_string())
+    }
+
+    /// Adds a master to the player on top of the stack
+    pub fn master(&mut self, id: i16, object_group: i16) -> &mut Self {
+        self.create_key::<MasterEntity, _>(PlayerFields::MasterEntities, id as i32)
+            .set(MasterEntityFields::Id, id)
+            .set(MasterEntityFields::ObjectGroup, object_group)
+            .pop()
+    }
+
+    /// Adds an entity of model `M` to the world on top of the stack and leaves it on top
+    pub fn create_entity<M: Model>(&mut self, id: i32, master_id: i16, owner: i8) -> &mut Self {
+        self.create_key::<M, _>(WorldFields::Entities, id)
+            .set(EntityFields::Id, id)
+            .set(EntityFields::MasterId, master_id)
+            .set(EntityFields::OwnerId, owner)
+    }
+
+    /// Adds an [Entity] at the given position to the world on top of the stack
+    pub fn entity(&mut self, id: i32, master_id: i16, owner: i8, (x, y): (f32, f32)) -> &mut Self {
+        self.create_entity::<Entity>(id, master_id, owner)
+            .set(EntityFields::WorldX, x)
+            .set(EntityFields::WorldY, y)
+            .pop()
+    }
+
+    pub fn build(&self) -> Vec<u8> {
+        self.0.to_vec()
+    }
+
+    pub fn frame(&self, time: u32) -> pb::Frame {
+        pb::Frame {
+            time,
+            patch: self.build(),
+            ..Default::default()
+        }
+    }
+}
+
+/// The description of a field in `uncage::model`, inherited fields are described by the model
+/// that declares them
+fn description<F: Fields>(field: F) -> &'static FieldDescription {
+    Models::create_model(field.model())
+        .boxed()
+        .get_field_description(field.field())
+        .unwrap_or_else(|| panic!("No field {} on model {}", field.field(), field.model()))
+}
+
+pub(crate) fn frame_with_events(time: u32, events: Vec<pb::event::Event>) -> pb::Frame {
+    pb::Frame {
+        time,
+        event: events
+            .into_iter()
+            .map(|x| pb::Event { event: Some(x) })
+            .collect(),
+        ..Default::default()
+    }
+}
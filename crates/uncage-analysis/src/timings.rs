@@ -0,0 +1,371 @@
+use crate::replay::{self, Analyzer, Game};
+use crate::research::{ResearchEvent, ResearchTracker};
+use serde::Serialize;
+use uncage::model::{PlayerAttributes, PlayerAttributesFields, WorldFields, WorldNavigation};
+use uncage_client::pb;
+use uncage_model::patcher::PatcherSelectorMatch;
+use uncage_model::{ModelWithDocument, Selector};
+
+const RESEARCH_PROGRESS: usize = 0;
+const RESEARCHED: usize = 1;
+const CURRENT_ERA: usize = 2;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Serialize)]
+pub enum Age {
+    Dark,
+    Feudal,
+    Castle,
+    Imperial,
+}
+
+impl Age {
+    /// The age of a `PlayerAttributes.current_era` value
+    pub fn from_era(era: f32) -> Option<Age> {
+        match era as i32 {
+            0 => Some(Age::Dark),
+            1 => Some(Age::Feudal),
+            2 => Some(Age::Castle),
+            3 => Some(Age::Imperial),
+            _ => None,
+        }
+    }
+
+    /// The technology researched to advance to the age
+    pub fn tech_id(&self) -> Option<i32> {
+        match self {
+            Age::Dark => None,
+            Age::Feudal => Some(101),
+            Age::Castle => Some(102),
+            Age::Imperial => Some(103),
+        }
+    }
+
+    pub fn from_tech_id(tech_id: i32) -> Option<Age> {
+        [Age::Feudal, Age::Castle, Age::Imperial]
+            .into_iter()
+            .find(|x| x.tech_id() == Some(tech_id))
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct AgeUp {
+    pub player: i32,
+    pub age: Age,
+    /// When the age up research started
+    pub clicked: Option<u32>,
+    /// When `current_era` changed to the age
+    pub reached: Option<u32>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TechTiming {
+    pub player: i32,
+    pub tech_id: i32,
+    pub name: Option<String>,
+    pub started: Option<u32>,
+    pub completed: Option<u32>,
+    /// `ResearchState.times_researched` once completed, techs like the age ups count once
+    pub times_researched: i16,
+}
+
+impl TechTiming {
+    /// Game time between start and completion
+    pub fn duration(&self) -> Option<u32> {
+        Some(self.completed?.saturating_sub(self.started?))
+    }
+}
+
+/// When every player clicked and reached each age and researched each technology, from
+/// `PlayerAttributes.current_era` and the progress of `Player.research_states`
+#[derive(Debug, Default, Serialize)]
+pub struct TechTimings {
+    ages: Vec<AgeUp>,
+    techs: Vec<TechTiming>,
+    #[serde(skip)]
+    research: ResearchTracker,
+}
+
+impl TechTimings {
+    pub fn new() -> TechTimings {
+        TechTimings::default()
+    }
+
+    pub fn ages(&self) -> &[AgeUp] {
+        &self.ages
+    }
+
+    pub fn techs(&self) -> &[TechTiming] {
+        &self.techs
+    }
+
+    pub fn player_ages(&self, player: i32) -> impl Iterator<Item = &AgeUp> {
+        self.ages.iter().filter(move |x| x.player == player)
+    }
+
+    pub fn player_techs(&self, player: i32) -> impl Iterator<Item = &TechTiming> {
+        self.techs.iter().filter(move |x| x.player == player)
+    }
+
+    pub fn to_json(&self) -> serde_json::Value {
+        serde_json::to_value(self).unwrap()
+    }
+
+    fn age(&mut self, player: i32, age: Age) -> &mut AgeUp {
+        let index = match self
+            .ages
+            .iter()
+            .position(|x| x.player == player && x.age == age)
+        {
+            Some(index) => index,
+            None => {
+                self.ages.push(AgeUp {
+                    player,
+                    age,
+                    clicked: None,
+                    reached: None,
+                });
+                self.ages.len() - 1
+            }
+        };
+
+        &mut self.ages[index]
+    }
+
+    /// The research of the tech that hasn't completed yet, or a new one if there's none
+    fn pending(&mut self, game: &Game, player: i32, tech_id: i32) -> &mut TechTiming {
+        let index = self
+            .techs
+            .iter()
+            .position(|x| x.player == player && x.tech_id == tech_id && x.completed.is_none());
+
+        let index = match index {
+            Some(index) => index,
+            None => {
+                let name = replay::world(game)
+                    .and_then(|x| x.technologies().get(tech_id))
+                    .map(|x| x.name.clone())
+                    .filter(|x| !x.is_empty());
+                self.techs.push(TechTiming {
+                    player,
+                    tech_id,
+                    name,
+                    started: None,
+                    completed: None,
+                    times_researched: 0,
+                });
+                self.techs.len() - 1
+            }
+        };
+
+        &mut self.techs[index]
+    }
+
+    fn research_changed(&mut self, game: &Game, time: u32, selected: &PatcherSelectorMatch) {
+        for event in self.research.changed(game, selected) {
+            match event {
+                ResearchEvent::Started { player, tech_id } => {
+                    let tech = self.pending(game, player, tech_id);
+                    tech.started.get_or_insert(time);
+                    if let Some(age) = Age::from_tech_id(tech_id) {
+                        self.age(player, age).clicked.get_or_insert(time);
+                    }
+                }
+                ResearchEvent::Completed {
+                    player,
+                    tech_id,
+                    times_researched,
+                } => {
+                    let tech = self.pending(game, player, tech_id);
+                    tech.completed = Some(time);
+                    tech.times_researched = times_researched;
+                }
+            }
+        }
+    }
+
+    fn era_changed(&mut self, game: &Game, time: u32, selected: &PatcherSelectorMatch) {
+        let Some(player) = replay::path_key(&selected.path, WorldFields::Players) else {
+            return;
+        };
+
+        let age = game
+            .by_id(selected.object_id)
+            .and_then(|x| x.cast_ref::<PlayerAttributes>().map(|x| x.current_era))
+            .and_then(Age::from_era);
+        if let Some(age) = age.filter(|x| *x != Age::Dark) {
+            self.age(player, age).reached.get_or_insert(time);
+        }
+    }
+}
+
+impl Analyzer for TechTimings {
+    fn selectors(&self) -> Vec<Selector> {
+        // In the order of RESEARCH_PROGRESS, RESEARCHED and CURRENT_ERA
+        let [research_progress, researched] = ResearchTracker::selectors();
+        vec![
+            research_progress,
+            researched,
+            Selector::new().field(PlayerAttributesFields::CurrentEra),
+        ]
+    }
+
+    fn selected(
+        &mut self,
+        frame: &pb::Frame,
+        game: &Game,
+        index: usize,
+        selected: &PatcherSelectorMatch,
+    ) {
+        match index {
+            RESEARCH_PROGRESS | RESEARCHED => self.research_changed(game, frame.time, selected),
+            CURRENT_ERA => self.era_changed(game, frame.time, selected),
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::PatchBuilder;
+    use crate::Replay;
+    use uncage::model::{PlayerFields, ResearchState, ResearchStateFields};
+    use uncage::model::{RootFields, Technology, TechnologyFields};
+
+    fn research(
+        time: u32,
+        tech_id: i32,
+        research_done: f32,
+        times_researched: i16,
+        era: Option<f32>,
+    ) -> pb::Frame {
+        let mut patch = PatchBuilder::new();
+        patch
+            .push(RootFields::World)
+            .push_key(WorldFields::Players, 1);
+        patch.push_key(PlayerFields::ResearchStates, tech_id);
+        patch
+            .set(ResearchStateFields::ResearchDone, research_done)
+            .set(ResearchStateFields::TimesResearched, times_researched)
+            .pop();
+        if let Some(era) = era {
+            patch.push(PlayerFields::Attributes);
+            patch.set(PlayerAttributesFields::CurrentEra, era).pop();
+        }
+        patch.pop().pop();
+        patch.frame(time)
+    }
+
+    #[test]
+    fn test_age_and_tech_timings() {
+        let mut timings = TechTimings::new();
+        let mut replay = Replay::new();
+        replay.add(&mut timings);
+
+        let mut patch = PatchBuilder::world();
+        for (id, name) in [(22, "Loom"), (101, "Feudal Age")] {
+            patch.create_key::<Technology, _>(WorldFields::Technologies, id);
+            patch.set(TechnologyFields::Name, name.to_string()).pop();
+        }
+        patch.player(1, "Ana");
+        patch.create::<PlayerAttributes, _>(PlayerFields::Attributes);
+        patch.set(PlayerAttributesFields::CurrentEra, 0.0f32).pop();
+        for id in [22, 101] {
+            patch.create_key::<ResearchState, _>(PlayerFields::ResearchStates, id);
+            patch.set(ResearchStateFields::ResearchDone, 0.0f32).pop();
+        }
+        patch.pop().pop();
+        replay.apply(&patch.frame(0)).unwrap();
+
+        replay.apply(&research(60_000, 22, 1.0, 0, None)).unwrap();
+        replay.apply(&research(85_000, 22, 25.0, 1, None)).unwrap();
+        replay.apply(&research(400_000, 101, 1.0, 0, None)).unwrap();
+        replay
+            .apply(&research(530_000, 101, 130.0, 1, Some(1.0)))
+            .unwrap();
+
+        let ages = timings.player_ages(1).collect::<Vec<_>>();
+        assert_eq!(ages.len(), 1);
+        assert_eq!(ages[0].age, Age::Feudal);
+        assert_eq!(
+            (ages[0].clicked, ages[0].reached),
+            (Some(400_000), Some(530_000))
+        );
+
+        let techs = timings
+            .player_techs(1)
+            .map(|x| (x.name.as_deref().unwrap(), x.duration()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            techs,
+            vec![("Loom", Some(25_000)), ("Feudal Age", Some(130_000))]
+        );
+    }
+
+    #[test]
+    fn test_timing_edges() {
+        let mut timings = TechTimings::new();
+        let mut replay = Replay::new();
+        replay.add(&mut timings);
+
+        // Without technologies in the world the researches have no names
+        let mut patch = PatchBuilder::world();
+        patch.player(1, "Ana");
+        patch.create::<PlayerAttributes, _>(PlayerFields::Attributes);
+        patch.set(PlayerAttributesFields::CurrentEra, 0.0f32).pop();
+        for id in [22, 102] {
+            patch.create_key::<ResearchState, _>(PlayerFields::ResearchStates, id);
+            patch.set(ResearchStateFields::ResearchDone, 0.0f32).pop();
+        }
+        patch.pop().pop();
+        replay.apply(&patch.frame(0)).unwrap();
+
+        // Completed without progress being seen, then assigned the same values again
+        replay.apply(&research(1_000, 22, 0.0, 1, None)).unwrap();
+        replay.apply(&research(2_000, 22, 0.0, 1, None)).unwrap();
+        // Eras out of range and the dark age aren't ages reached
+        replay
+            .apply(&research(3_000, 102, 1.0, 0, Some(5.0)))
+            .unwrap();
+        replay
+            .apply(&research(4_000, 102, 40.0, 1, Some(2.0)))
+            .unwrap();
+        replay
+            .apply(&research(4_500, 102, 40.0, 1, Some(0.0)))
+            .unwrap();
+        // Researched a second time
+        replay.apply(&research(5_000, 22, 1.0, 1, None)).unwrap();
+        let mut patch = PatchBuilder::new();
+        patch
+            .push(RootFields::World)
+            .push_key(WorldFields::Players, 1);
+        patch
+            .push_key(PlayerFields::ResearchStates, 22)
+            .set(ResearchStateFields::ResearchDone, 25.0f32)
+            .set(ResearchStateFields::TimesResearched, 2i16);
+        patch.pop().pop().pop();
+        replay.apply(&patch.frame(6_000)).unwrap();
+
+        let ages = timings
+            .player_ages(1)
+            .map(|x| (x.age, x.clicked, x.reached))
+            .collect::<Vec<_>>();
+        assert_eq!(ages, vec![(Age::Castle, Some(3_000), Some(4_000))]);
+
+        let techs = timings
+            .player_techs(1)
+            .map(|x| (x.tech_id, x.name.is_some(), x.started, x.completed))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            techs,
+            vec![
+                (22, false, None, Some(1_000)),
+                (102, false, Some(3_000), Some(4_000)),
+                (22, false, Some(5_000), Some(6_000)),
+            ]
+        );
+        assert_eq!(timings.techs()[2].times_researched, 2);
+        assert_eq!(timings.techs()[0].duration(), None);
+        assert_eq!(timings.player_techs(2).count(), 0);
+    }
+}
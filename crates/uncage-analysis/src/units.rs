@@ -0,0 +1,182 @@
+use crate::replay::{self, Borrow, Game};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::ops::{Add, AddAssign, Sub};
+use uncage::model::{ActionEntity, BuildingEntity, Entity, MissileEntity};
+
+/// Master ids of the villagers of all civilizations, male and female for every task
+pub const VILLAGER_IDS: &[i16] = &[
+    56, 57, 83, 118, 120, 122, 123, 124, 156, 206, 212, 214, 216, 218, 220, 222, 259, 293, 354,
+    579, 581, 590, 592,
+];
+
+/// `MasterEntity.object_group`s of units that don't fight: trade boats, trade carts, transport
+/// ships, fishing ships and livestock
+pub const CIVILIAN_CLASSES: &[i16] = &[2, 19, 20, 21, 58];
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Serialize)]
+pub enum UnitRole {
+    Villager,
+    Military,
+    Building,
+    /// Units that are neither villagers nor fight
+    Civilian,
+}
+
+impl UnitRole {
+    /// The role of a player's entity, `None` for gaia, missiles and other entities that aren't
+    /// buildings or units
+    pub fn of(game: &Game, entity: &Borrow<'_, Entity>) -> Option<UnitRole> {
+        if entity.owner_id <= 0 {
+            return None;
+        }
+
+        if entity.cast::<BuildingEntity>().is_some() {
+            return Some(UnitRole::Building);
+        }
+
+        if entity.cast::<ActionEntity>().is_none() || entity.cast::<MissileEntity>().is_some() {
+            return None;
+        }
+
+        let class = replay::master_of(game, entity).map(|x| x.object_group);
+        Some(UnitRole::classify(entity.master_id, class))
+    }
+
+    /// The role of a unit by its master id and, if known, its class
+    pub fn classify(master_id: i16, class: Option<i16>) -> UnitRole {
+        if VILLAGER_IDS.contains(&master_id) {
+            UnitRole::Villager
+        } else if class.is_some_and(|x| CIVILIAN_CLASSES.contains(&x)) {
+            UnitRole::Civilian
+        } else {
+            UnitRole::Military
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Resources {
+    #[serde(default)]
+    pub food: f32,
+    #[serde(default)]
+    pub wood: f32,
+    #[serde(default)]
+    pub stone: f32,
+    #[serde(default)]
+    pub gold: f32,
+}
+
+impl Resources {
+    pub fn total(&self) -> f32 {
+        self.food + self.wood + self.stone + self.gold
+    }
+
+    pub fn scale(self, factor: f32) -> Resources {
+        Resources {
+            food: self.food * factor,
+            wood: self.wood * factor,
+            stone: self.stone * factor,
+            gold: self.gold * factor,
+        }
+    }
+
+    pub fn named(&self) -> [(&'static str, f32); 4] {
+        [
+            ("food", self.food),
+            ("wood", self.wood),
+            ("stone", self.stone),
+            ("gold", self.gold),
+        ]
+    }
+
+    /// `amount` of the resource stored at index `attribute` of the player attributes, which are
+    /// in the same order as [Resources::named]
+    pub fn from_attribute(attribute: u32, amount: f32) -> Option<Resources> {
+        let mut resources = Resources::default();
+        match attribute {
+            0 => resources.food = amount,
+            1 => resources.wood = amount,
+            2 => resources.stone = amount,
+            3 => resources.gold = amount,
+            _ => return None,
+        }
+        Some(resources)
+    }
+
+    pub fn attribute_name(attribute: u32) -> Option<&'static str> {
+        Resources::default()
+            .named()
+            .get(attribute as usize)
+            .map(|x| x.0)
+    }
+}
+
+impl Add for Resources {
+    type Output = Resources;
+
+    fn add(self, rhs: Resources) -> Resources {
+        Resources {
+            food: self.food + rhs.food,
+            wood: self.wood + rhs.wood,
+            stone: self.stone + rhs.stone,
+            gold: self.gold + rhs.gold,
+        }
+    }
+}
+
+impl Sub for Resources {
+    type Output = Resources;
+
+    fn sub(self, rhs: Resources) -> Resources {
+        self + rhs.scale(-1.0)
+    }
+}
+
+impl AddAssign for Resources {
+    fn add_assign(&mut self, rhs: Resources) {
+        *self = *self + rhs;
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct UnitInfo {
+    pub name: String,
+    #[serde(default)]
+    pub cost: Resources,
+}
+
+/// Names and costs of units by master id. Neither is part of the model, so they have to come from
+/// the game's data, e.g. as JSON `{"4": {"name": "Archer", "cost": {"wood": 25, "gold": 45}}}`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct UnitCatalog {
+    units: BTreeMap<i16, UnitInfo>,
+}
+
+impl UnitCatalog {
+    pub fn new() -> UnitCatalog {
+        UnitCatalog::default()
+    }
+
+    pub fn from_json(json: &str) -> anyhow::Result<UnitCatalog> {
+        Ok(serde_json::from_str(json)?)
+    }
+
+    pub fn insert(&mut self, master_id: i16, info: UnitInfo) {
+        self.units.insert(master_id, info);
+    }
+
+    pub fn get(&self, master_id: i16) -> Option<&UnitInfo> {
+        self.units.get(&master_id)
+    }
+
+    pub fn name(&self, master_id: i16) -> Option<&str> {
+        self.get(master_id).map(|x| x.name.as_str())
+    }
+
+    /// Units missing from the catalog cost nothing
+    pub fn cost(&self, master_id: i16) -> Resources {
+        self.get(master_id).map(|x| x.cost).unwrap_or_default()
+    }
+}
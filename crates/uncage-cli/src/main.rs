@@ -0,0 +1,194 @@
+use anyhow::Context;
+use bytes::Bytes;
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use std::path::{Path, PathBuf};
+use uncage_codegen::Source;
+use uncage_model::export::Export;
+use uncage_model::registry::{SchemaRegistry, VersionedPatcher};
+
+#[derive(Debug, Parser)]
+#[command(name = "uncage", version, about)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Generate model.rs from a worker.js dump or a JSON/TOML schema
+    GenerateModel {
+        #[command(flatten)]
+        input: Input,
+    },
+    /// Write the schema found in a worker.js dump as JSON
+    ExtractSchema {
+        #[command(flatten)]
+        input: Input,
+    },
+    /// Write the models as JSON Schema or TypeScript definitions of the `Document::to_json` output
+    ExportTypes {
+        #[command(flatten)]
+        input: Input,
+        #[arg(long, value_enum, default_value_t = TypesFormat::JsonSchema)]
+        format: TypesFormat,
+    },
+    /// Print the chat log of recorded frames
+    Chat {
+        /// A file of length delimited frame sequences, as received from the game
+        frames: PathBuf,
+        /// Leave out team chat
+        #[arg(long)]
+        exclude_team: bool,
+        #[arg(long, value_enum, default_value_t = ChatFormat::Text)]
+        format: ChatFormat,
+        /// Write to this file instead of stdout
+        #[arg(long, short)]
+        output: Option<PathBuf>,
+    },
+    /// Write the document after the recorded frames as JSON, decoded with the schema of the game
+    /// build they were recorded with
+    Document {
+        /// A file of length delimited frame sequences, as received from the game
+        frames: PathBuf,
+        /// The game build, instead of the one in the `.info` file next to the frames
+        #[arg(long)]
+        build: Option<u32>,
+        /// A JSON or TOML schema registry, instead of the one of the built-in models
+        #[arg(long)]
+        registry: Option<PathBuf>,
+        /// Write to this file instead of stdout
+        #[arg(long, short)]
+        output: Option<PathBuf>,
+    },
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum TypesFormat {
+    JsonSchema,
+    Typescript,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum ChatFormat {
+    Text,
+    Json,
+}
+
+#[derive(Debug, Args)]
+#[group(required = true, multiple = false)]
+struct SourceArgs {
+    /// The game's worker.js
+    #[arg(long)]
+    worker: Option<PathBuf>,
+    /// A JSON or TOML schema
+    #[arg(long)]
+    schema: Option<PathBuf>,
+    /// A model.rs with `#[derive(Model)]` structs
+    #[arg(long)]
+    model: Option<PathBuf>,
+}
+
+#[derive(Debug, Args)]
+struct Input {
+    #[command(flatten)]
+    source: SourceArgs,
+    /// An existing model.rs to take the field types from
+    #[arg(long)]
+    types: Option<PathBuf>,
+    /// Write to this file instead of stdout
+    #[arg(long, short)]
+    output: Option<PathBuf>,
+}
+
+impl Input {
+    fn source(&self) -> Source<'_> {
+        let source = &self.source;
+        match (&source.worker, &source.schema, &source.model) {
+            (Some(worker), _, _) => Source::Worker(worker),
+            (_, Some(schema), _) => Source::Schema(schema),
+            (_, _, Some(model)) => Source::Rust(model),
+            _ => unreachable!("clap requires one of the inputs"),
+        }
+    }
+
+    fn write(&self, output: String) -> anyhow::Result<()> {
+        write(self.output.as_deref(), output)
+    }
+}
+
+fn write(path: Option<&Path>, output: String) -> anyhow::Result<()> {
+    match path {
+        Some(path) => std::fs::write(path, output)
+            .with_context(|| format!("Failed to write {}", path.display())),
+        None => {
+            print!("{}", output);
+            Ok(())
+        }
+    }
+}
+
+fn main() -> anyhow::Result<()> {
+    match Cli::parse().command {
+        Command::GenerateModel { input } => {
+            let output = uncage_codegen::generate_model(input.source(), input.types.as_deref())?;
+            input.write(output)
+        }
+        Command::ExtractSchema { input } => {
+            let schema = uncage_codegen::load_schema(input.source(), input.types.as_deref())?;
+            input.write(serde_json::to_string_pretty(&schema)? + "\n")
+        }
+        Command::ExportTypes { input, format } => {
+            let schema = uncage_codegen::load_schema(input.source(), input.types.as_deref())?;
+            let export = Export::from_schema(schema.compile()?);
+            input.write(match format {
+                TypesFormat::JsonSchema => {
+                    serde_json::to_string_pretty(&export.json_schema())? + "\n"
+                }
+                TypesFormat::Typescript => export.typescript(),
+            })
+        }
+        Command::Chat {
+            frames,
+            exclude_team,
+            format,
+            output,
+        } => {
+            let frames = uncage_analysis::read_frames(&frames)?;
+            let messages = uncage_analysis::chat::chat_log(&frames, exclude_team)?;
+            write(
+                output.as_deref(),
+                match format {
+                    ChatFormat::Text => messages.iter().map(|x| format!("{}\n", x)).collect(),
+                    ChatFormat::Json => serde_json::to_string_pretty(&messages)? + "\n",
+                },
+            )
+        }
+        Command::Document {
+            frames,
+            build,
+            registry,
+            output,
+        } => {
+            let registry = match registry {
+                Some(path) => SchemaRegistry::load(path)?,
+                None => SchemaRegistry::from_json(uncage::REGISTRY)?,
+            };
+            let build = match build {
+                Some(build) => build,
+                None => uncage_analysis::read_info(&frames)?.game_version,
+            };
+
+            let mut patcher = VersionedPatcher::new(&registry, build)?;
+            for frame in uncage_analysis::read_frames(&frames)? {
+                patcher
+                    .apply_patch(Bytes::from(frame.patch))
+                    .with_context(|| format!("Failed to apply the patch at {}", frame.time))?;
+            }
+
+            write(
+                output.as_deref(),
+                serde_json::to_string_pretty(&patcher.to_json(0))? + "\n",
+            )
+        }
+    }
+}
@@ -1,5 +1,5 @@
 use crate::pb::cade_remote_client::CadeRemoteClient;
-use crate::pb::FramesRequest;
+use crate::pb::{FramesRequest, InfoRequest};
 use hyper::client::HttpConnector;
 use hyper::{Client, Uri};
 use hyper_openssl::HttpsConnector;
@@ -72,6 +72,11 @@ pub async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     let mut client = CadeRemoteClient::new(add_origin);
 
+    // The recording alone doesn't say which game build it's from, so the info is written next to it
+    let info = client.info(InfoRequest {}).await?.into_inner();
+    println!("Recording game build {}", info.game_version);
+    std::fs::write("output.info", info.encode_to_vec())?;
+
     let request = tonic::Request::new(FramesRequest {
         desired_resolutions: vec![],
         ..Default::default()
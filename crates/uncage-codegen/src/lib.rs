@@ -0,0 +1,43 @@
+pub mod rust;
+pub mod worker;
+
+use anyhow::Context;
+use std::path::Path;
+use uncage_model::schema::Schema;
+
+/// Where to read the models from, `worker.js` only has names and indices so the types are taken
+/// from an existing model.rs when one is given
+#[derive(Debug, Clone)]
+pub enum Source<'a> {
+    Worker(&'a Path),
+    Schema(&'a Path),
+    Rust(&'a Path),
+}
+
+pub fn load_schema(source: Source, types: Option<&Path>) -> anyhow::Result<Schema> {
+    let mut schema = match source {
+        Source::Worker(path) => worker::parse(
+            &std::fs::read_to_string(path)
+                .with_context(|| format!("Failed to read {}", path.display()))?,
+        ),
+        Source::Schema(path) => Schema::load(path)?,
+        Source::Rust(path) => rust::parse(
+            &std::fs::read_to_string(path)
+                .with_context(|| format!("Failed to read {}", path.display()))?,
+        )?,
+    };
+
+    if let Some(path) = types {
+        let known = rust::parse(
+            &std::fs::read_to_string(path)
+                .with_context(|| format!("Failed to read {}", path.display()))?,
+        )?;
+        rust::merge(&mut schema, &known);
+    }
+
+    Ok(schema)
+}
+
+pub fn generate_model(source: Source, types: Option<&Path>) -> anyhow::Result<String> {
+    rust::generate(&load_schema(source, types)?)
+}
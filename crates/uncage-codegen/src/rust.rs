@@ -0,0 +1,472 @@
+use crate::worker::UNKNOWN_TYPE;
+use anyhow::{bail, Context};
+use std::collections::BTreeSet;
+use std::fmt::Write;
+use syn::{
+    AngleBracketedGenericArguments, Attribute, Expr, ExprLit, GenericArgument, Item, Lit, Meta,
+    NestedMeta, PathArguments, Type,
+};
+use uncage_model::schema::{Container, FieldSchema, ModelSchema, Schema};
+
+const KEYWORDS: &[&str] = &[
+    "as", "break", "const", "continue", "crate", "else", "enum", "extern", "false", "fn", "for",
+    "if", "impl", "in", "let", "loop", "match", "mod", "move", "mut", "pub", "ref", "return",
+    "static", "struct", "super", "trait", "true", "type", "unsafe", "use", "where", "while",
+    "async", "await", "dyn", "abstract", "become", "box", "do", "final", "macro", "override",
+    "priv", "typeof", "unsized", "virtual", "yield", "try",
+];
+
+/// Emits a model.rs with a `#[derive(Model)]` struct for every model and the `Models` collection
+pub fn generate(schema: &Schema) -> anyhow::Result<String> {
+    let mut imports = BTreeSet::new();
+    let mut enums = BTreeSet::new();
+    let mut uses_btree_map = false;
+    let mut body = String::new();
+
+    writeln!(body, "#[derive(ModelCollection, Debug)]")?;
+    writeln!(body, "pub enum Models {{")?;
+    for model in &schema.models {
+        writeln!(body, "    {}({}),", model.name, model.name)?;
+    }
+    writeln!(body, "}}")?;
+
+    for model in &schema.models {
+        writeln!(body)?;
+        writeln!(body, "#[derive(Model, Debug, Default)]")?;
+        writeln!(body, "#[uncage(type = {})]", model.model_type)?;
+        writeln!(body, "pub struct {} {{", model.name)?;
+
+        if let Some(parent) = &model.extends {
+            if schema.model(parent).is_none() {
+                bail!("Model {} extends unknown model {}", model.name, parent);
+            }
+
+            writeln!(body, "    #[uncage(extends)]")?;
+            writeln!(body, "    pub parent: {},", parent)?;
+        }
+
+        for field in &model.fields {
+            let rust_type = rust_type(schema, field, &mut imports, &mut uses_btree_map)
+                .with_context(|| format!("Failed to generate {}.{}", model.name, field.name))?;
+            let mut attributes = format!("index = {}", field.index);
+            if field.autofill {
+                attributes.push_str(", autofill");
+            }
+
+            if field.enumeration.is_some() {
+                write!(attributes, ", enum = \"{}\"", field.field_type)?;
+            }
+
+            if let Some(unit) = &field.unit {
+                write!(attributes, ", unit = {:?}", unit)?;
+            }
+
+            if let Some(description) = &field.description {
+                write!(attributes, ", description = {:?}", description)?;
+            }
+
+            for (name, set) in [
+                ("deprecated", field.deprecated),
+                ("hidden_info", field.hidden_info),
+                ("interpolatable", field.interpolatable),
+            ] {
+                if set {
+                    write!(attributes, ", {}", name)?;
+                }
+            }
+
+            writeln!(body, "    #[uncage({})]", attributes)?;
+
+            writeln!(body, "    pub {}: {},", field_ident(&field.name), rust_type)?;
+            enums.extend(field.enumeration.as_deref());
+        }
+
+        writeln!(body, "}}")?;
+    }
+
+    imports.insert("Model");
+    imports.insert("ModelCollection");
+
+    let mut output = String::new();
+    if !enums.is_empty() {
+        // Enums aren't part of the schema, they're expected next to the models in `enums.rs`
+        writeln!(
+            output,
+            "use crate::enums::{{{}}};",
+            enums.into_iter().collect::<Vec<_>>().join(", ")
+        )?;
+    }
+
+    if uses_btree_map {
+        writeln!(output, "use std::collections::BTreeMap;")?;
+    }
+
+    writeln!(
+        output,
+        "use uncage_model::{{{}}};",
+        imports.into_iter().collect::<Vec<_>>().join(", ")
+    )?;
+    writeln!(output)?;
+    output.push_str(&body);
+
+    Ok(output)
+}
+
+fn field_ident(name: &str) -> String {
+    if KEYWORDS.contains(&name) {
+        format!("r#{}", name)
+    } else {
+        name.to_string()
+    }
+}
+
+fn primitive(name: &str) -> Option<&'static str> {
+    Some(match name {
+        "bool" => "bool",
+        "u8" => "u8",
+        "i8" => "i8",
+        "u16" => "u16",
+        "i16" => "i16",
+        "u32" => "u32",
+        "i32" => "i32",
+        "u64" => "u64",
+        "i64" => "i64",
+        "u128" => "u128",
+        "i128" => "i128",
+        "f32" => "f32",
+        "f64" => "f64",
+        "string" | "String" => "String",
+        _ => return None,
+    })
+}
+
+fn rust_type(
+    schema: &Schema,
+    field: &FieldSchema,
+    imports: &mut BTreeSet<&'static str>,
+    uses_btree_map: &mut bool,
+) -> anyhow::Result<String> {
+    if field.field_type == UNKNOWN_TYPE {
+        // Left for whoever updates the model to fill in
+        return Ok("()".to_string());
+    }
+
+    let is_ref = matches!(field.field_type.as_str(), "ref" | "Ref");
+    let value = if let Some(x) = &field.enumeration {
+        x.clone()
+    } else if let Some(x) = primitive(&field.field_type) {
+        x.to_string()
+    } else if is_ref {
+        imports.insert("Ref");
+        "Ref".to_string()
+    } else if schema.model(&field.field_type).is_some() {
+        field.field_type.clone()
+    } else {
+        bail!("Unknown type {}", field.field_type);
+    };
+
+    let is_model = !is_ref && field.enumeration.is_none() && primitive(&field.field_type).is_none();
+    Ok(match field.container {
+        Container::Value if is_model => {
+            imports.insert("ModelRef");
+            format!("ModelRef<{}>", value)
+        }
+        Container::Value => value,
+        Container::List if is_model => {
+            imports.insert("ModelVec");
+            format!("ModelVec<{}>", value)
+        }
+        Container::List => format!("Vec<{}>", value),
+        Container::Array => {
+            let len = field.len.context("Arrays need a len")?;
+            if is_model {
+                imports.insert("ModelRef");
+                format!("[ModelRef<{}>; {}]", value, len)
+            } else {
+                format!("[{}; {}]", value, len)
+            }
+        }
+        Container::Map => {
+            let key = field.key.as_deref().unwrap_or("i32");
+            let key = primitive(key).with_context(|| format!("Unknown key type {}", key))?;
+            if is_model {
+                imports.insert("ModelBTreeMap");
+                format!("ModelBTreeMap<{}, {}>", key, value)
+            } else {
+                *uses_btree_map = true;
+                format!("BTreeMap<{}, {}>", key, value)
+            }
+        }
+    })
+}
+
+/// Reads the models back out of an existing model.rs, used to carry over the field types that
+/// can't be found in `worker.js`
+pub fn parse(source: &str) -> anyhow::Result<Schema> {
+    let file = syn::parse_file(source).context("Failed to parse model source")?;
+    let mut models = vec![];
+
+    for item in file.items {
+        let item = match item {
+            Item::Struct(x) => x,
+            _ => continue,
+        };
+
+        let model_type = match uncage_attribute(&item.attrs)?
+            .into_iter()
+            .find_map(|(name, value)| (name == "type").then_some(value).flatten())
+        {
+            Some(x) => x.parse()?,
+            None => continue,
+        };
+
+        let mut model = ModelSchema {
+            name: item.ident.to_string(),
+            model_type,
+            extends: None,
+            fields: vec![],
+        };
+
+        for field in item.fields {
+            let attributes = uncage_attribute(&field.attrs)?;
+            let name = field
+                .ident
+                .as_ref()
+                .context("Models can't have unnamed fields")?
+                .to_string()
+                .replace("r#", "");
+
+            if attributes.iter().any(|(name, _)| name == "extends") {
+                model.extends = Some(type_name(&field.ty)?.0);
+                continue;
+            }
+
+            let attribute = |attribute: &str| {
+                attributes
+                    .iter()
+                    .find_map(|(name, value)| (name == attribute).then(|| value.clone()).flatten())
+            };
+
+            let index = attribute("index").map_or(Ok(0), |x| x.parse())?;
+            let (container, key, len, mut field_type) = field_type(&field.ty)
+                .with_context(|| format!("Unsupported type on {}.{}", model.name, name))?;
+
+            // Enums are stored as the integer type given in the attribute
+            let enumeration =
+                attribute("enum").map(|repr| std::mem::replace(&mut field_type, repr));
+
+            let flag = |flag: &str| attributes.iter().any(|(name, _)| name == flag);
+            model.fields.push(FieldSchema {
+                index,
+                name,
+                field_type,
+                container,
+                key,
+                len,
+                autofill: flag("autofill"),
+                enumeration,
+                unit: attribute("unit"),
+                description: attribute("description"),
+                deprecated: flag("deprecated"),
+                hidden_info: flag("hidden_info"),
+                interpolatable: flag("interpolatable"),
+            });
+        }
+
+        models.push(model);
+    }
+
+    let root = models
+        .iter()
+        .find(|x| x.name == "Root")
+        .or_else(|| models.iter().find(|x| x.model_type == 0))
+        .map(|x| x.name.clone())
+        .context("No root model found")?;
+
+    Ok(Schema { root, models })
+}
+
+fn uncage_attribute(attributes: &[Attribute]) -> anyhow::Result<Vec<(String, Option<String>)>> {
+    let mut result = vec![];
+    for attribute in attributes {
+        if !attribute.path.is_ident("uncage") {
+            continue;
+        }
+
+        let list = match attribute.parse_meta()? {
+            Meta::List(x) => x,
+            _ => continue,
+        };
+
+        for item in list.nested {
+            match item {
+                NestedMeta::Meta(Meta::Path(path)) => {
+                    if let Some(ident) = path.get_ident() {
+                        result.push((ident.to_string(), None));
+                    }
+                }
+                NestedMeta::Meta(Meta::NameValue(value)) => {
+                    let literal = match &value.lit {
+                        Lit::Int(x) => Some(x.base10_digits().to_string()),
+                        Lit::Str(x) => Some(x.value()),
+                        _ => None,
+                    };
+
+                    if let Some(ident) = value.path.get_ident() {
+                        result.push((ident.to_string(), literal));
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    Ok(result)
+}
+
+fn type_name(ty: &Type) -> anyhow::Result<(String, Vec<Type>)> {
+    let path = match ty {
+        Type::Path(x) => x,
+        Type::Tuple(x) if x.elems.is_empty() => return Ok((UNKNOWN_TYPE.to_string(), vec![])),
+        _ => bail!("Only plain types are supported"),
+    };
+
+    let segment = path.path.segments.last().context("Empty type")?;
+    let arguments = match &segment.arguments {
+        PathArguments::AngleBracketed(AngleBracketedGenericArguments { args, .. }) => args
+            .iter()
+            .filter_map(|x| match x {
+                GenericArgument::Type(x) => Some(x.clone()),
+                _ => None,
+            })
+            .collect(),
+        _ => vec![],
+    };
+
+    Ok((segment.ident.to_string(), arguments))
+}
+
+fn value_name(ty: &Type) -> anyhow::Result<String> {
+    let (name, arguments) = type_name(ty)?;
+    Ok(match name.as_str() {
+        "ModelRef" => type_name(arguments.first().context("ModelRef without type")?)?.0,
+        "Ref" => "ref".to_string(),
+        "String" => "string".to_string(),
+        _ => name,
+    })
+}
+
+/// The container, map key, array length and value type of a field
+type FieldShape = (Container, Option<String>, Option<usize>, String);
+
+fn field_type(ty: &Type) -> anyhow::Result<FieldShape> {
+    if let Type::Array(array) = ty {
+        let len = match &array.len {
+            Expr::Lit(ExprLit {
+                lit: Lit::Int(x), ..
+            }) => x.base10_parse()?,
+            _ => bail!("Array lengths have to be literals"),
+        };
+
+        return Ok((Container::Array, None, Some(len), value_name(&array.elem)?));
+    }
+
+    let (name, arguments) = type_name(ty)?;
+    Ok(match name.as_str() {
+        "Vec" | "ModelVec" => (
+            Container::List,
+            None,
+            None,
+            value_name(arguments.first().context("List without type")?)?,
+        ),
+        "BTreeMap" | "HashMap" | "ModelBTreeMap" | "ModelHashMap" => {
+            if arguments.len() != 2 {
+                bail!("Maps need a key and value type");
+            }
+
+            (
+                Container::Map,
+                Some(value_name(&arguments[0])?),
+                None,
+                value_name(&arguments[1])?,
+            )
+        }
+        _ => (Container::Value, None, None, value_name(ty)?),
+    })
+}
+
+/// Copies types, containers and parents from `known` onto the models and fields of `schema` that
+/// match by name
+pub fn merge(schema: &mut Schema, known: &Schema) {
+    for model in &mut schema.models {
+        let known_model = match known.model(&model.name) {
+            Some(x) => x,
+            None => continue,
+        };
+
+        if model.extends.is_none() {
+            model.extends = known_model.extends.clone();
+        }
+
+        for field in &mut model.fields {
+            let known_field = match known_model.fields.iter().find(|x| x.name == field.name) {
+                Some(x) => x,
+                None => continue,
+            };
+
+            if field.field_type == UNKNOWN_TYPE {
+                field.field_type = known_field.field_type.clone();
+                field.container = known_field.container;
+                field.key = known_field.key.clone();
+                field.len = known_field.len;
+                field.autofill = known_field.autofill;
+                field.enumeration = known_field.enumeration.clone();
+            }
+
+            // worker.js has no metadata, it's only ever added by hand
+            field.unit = field.unit.take().or_else(|| known_field.unit.clone());
+            field.description = field
+                .description
+                .take()
+                .or_else(|| known_field.description.clone());
+            field.deprecated |= known_field.deprecated;
+            field.hidden_info |= known_field.hidden_info;
+            field.interpolatable |= known_field.interpolatable;
+        }
+
+        // Fields of the parent aren't repeated in worker.js enums for the child
+        if let Some(parent) = model.extends.clone().and_then(|x| known.model(&x).cloned()) {
+            model
+                .fields
+                .retain(|field| !parent.fields.iter().any(|x| x.name == field.name));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::rust::{generate, parse};
+
+    #[test]
+    pub fn test_model_roundtrip() {
+        let source = include_str!("../../uncage/src/model.rs");
+        let schema = parse(source).unwrap();
+        assert_eq!(schema.root, "Root");
+        assert_eq!(schema.models.len(), 44);
+
+        let entity = schema.model("Entity").unwrap();
+        let state = entity.fields.iter().find(|x| x.name == "state").unwrap();
+        assert_eq!(state.field_type, "i8");
+        assert_eq!(state.enumeration, None);
+        let unit_type = entity.fields.iter().find(|x| x.name == "type").unwrap();
+        assert_eq!(unit_type.field_type, "i8");
+        assert_eq!(unit_type.enumeration.as_deref(), Some("EntityType"));
+
+        let world_x = &schema.model("Entity").unwrap().fields[3];
+        assert_eq!(world_x.unit.as_deref(), Some("tiles"));
+        assert!(world_x.interpolatable);
+
+        let generated = generate(&schema).unwrap();
+        assert_eq!(parse(&generated).unwrap(), schema);
+    }
+}
@@ -0,0 +1,124 @@
+use regex::Regex;
+use uncage_model::schema::{Container, FieldSchema, ModelSchema, Schema};
+
+pub const UNKNOWN_TYPE: &str = "unknown";
+
+/// Reads the model and field enums out of the game's `worker.js`, these only contain names and
+/// indices so every field gets the type [UNKNOWN_TYPE]
+pub fn parse(source: &str) -> Schema {
+    let field = Regex::new(r"e\[e\.([A-Za-z0-9_]+) = (\d+)]").unwrap();
+
+    // The first enum lists the primitives, the second the models and every enum after that the
+    // fields of the models in the same order
+    let mut state = 0;
+    let mut is_modeling = true;
+    let mut models: Vec<ModelSchema> = vec![];
+    let mut model_index = 0;
+
+    for item in field.captures_iter(source) {
+        let name = &item[1];
+        let value: usize = match item[2].parse() {
+            Ok(x) => x,
+            Err(_) => continue,
+        };
+
+        if value == 0 {
+            state += 1;
+        }
+
+        if state < 2 {
+            continue;
+        }
+
+        if value == 0 && !is_modeling {
+            model_index += 1;
+
+            if model_index >= models.len() {
+                break;
+            }
+        }
+
+        if value == 0 && !models.is_empty() {
+            is_modeling = false;
+        }
+
+        if is_modeling {
+            models.push(ModelSchema {
+                name: name.to_string(),
+                model_type: value,
+                extends: None,
+                fields: vec![],
+            });
+            continue;
+        }
+
+        models[model_index].fields.push(FieldSchema {
+            index: value,
+            name: field_name(name),
+            field_type: UNKNOWN_TYPE.to_string(),
+            container: Container::Value,
+            key: None,
+            len: None,
+            autofill: false,
+            enumeration: None,
+            unit: None,
+            description: None,
+            deprecated: false,
+            hidden_info: false,
+            interpolatable: false,
+        });
+    }
+
+    Schema {
+        root: models
+            .first()
+            .map(|x| x.name.clone())
+            .unwrap_or_else(|| "Root".to_string()),
+        models,
+    }
+}
+
+/// `worldX` becomes `world_x` and `event0` becomes `event_0`
+pub fn field_name(name: &str) -> String {
+    let mut result = String::with_capacity(name.len() + 4);
+    for c in name.chars() {
+        if c.is_ascii_uppercase() || c.is_ascii_digit() {
+            if !result.is_empty() {
+                result.push('_');
+            }
+
+            result.push(c.to_ascii_lowercase());
+        } else {
+            result.push(c);
+        }
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::worker::{field_name, parse, UNKNOWN_TYPE};
+
+    #[test]
+    pub fn test_parse_worker() {
+        let source = r#"
+            (function (e) { e[e.Int = 0] = "Int"; e[e.Float = 1] = "Float"; })(a || (a = {}));
+            (function (e) { e[e.Root = 0] = "Root"; e[e.World = 1] = "World"; })(b || (b = {}));
+            (function (e) { e[e.world = 0] = "world"; e[e.gameOptions = 1] = "gameOptions"; })(c);
+            (function (e) { e[e.mapWidth = 0] = "mapWidth"; e[e.event0 = 3] = "event0"; })(d);
+        "#;
+
+        let schema = parse(source);
+        assert_eq!(schema.root, "Root");
+        assert_eq!(schema.models.len(), 2);
+        assert_eq!(schema.models[1].name, "World");
+        assert_eq!(schema.models[1].model_type, 1);
+
+        let fields = &schema.models[1].fields;
+        assert_eq!(fields[1].name, "event_0");
+        assert_eq!(fields[1].index, 3);
+        assert_eq!(fields[1].field_type, UNKNOWN_TYPE);
+        assert_eq!(field_name("worldX"), "world_x");
+    }
+}
@@ -1,17 +1,23 @@
-use darling::{FromDeriveInput, FromField};
+use darling::{FromDeriveInput, FromField, FromVariant};
 use heck::{CamelCase, ShoutySnakeCase};
 use proc_macro::TokenStream;
 use proc_macro2::Ident;
+use std::collections::HashMap;
+use syn::spanned::Spanned;
 use syn::{parse_macro_input, AngleBracketedGenericArguments, PathArguments, Type};
 use syn::{DeriveInput, GenericArgument};
 
 #[proc_macro_derive(Model, attributes(uncage))]
 pub fn derive_model(token_stream: TokenStream) -> TokenStream {
     let ast = parse_macro_input!(token_stream as DeriveInput);
+    expand_model(&ast)
+        .unwrap_or_else(|err| err.to_compile_error())
+        .into()
+}
+
+fn expand_model(ast: &DeriveInput) -> syn::Result<proc_macro2::TokenStream> {
     let model_ident = &ast.ident;
-    let config: StructConfig = StructConfig::from_derive_input(&ast)
-        .map_err(|err| format!("{}", err))
-        .expect("Failed to parse attributes for struct");
+    let config = StructConfig::from_derive_input(ast)?;
 
     let model_type = config.model_type;
     let model_name = model_ident.to_string();
@@ -19,12 +25,13 @@ pub fn derive_model(token_stream: TokenStream) -> TokenStream {
     let data = config
         .data
         .take_struct()
-        .expect("A model should be a struct");
+        .ok_or_else(|| syn::Error::new_spanned(model_ident, "A model should be a struct"))?;
 
     let field_len = data.len();
 
     let mut field_definitions: Vec<proc_macro2::TokenStream> = vec![];
     let mut fields: Vec<Ident> = vec![];
+    let mut field_names: Vec<String> = vec![];
     let mut indexes: Vec<usize> = vec![];
     let mut field_indexes = vec![];
     let mut i = 0;
@@ -51,44 +58,131 @@ pub fn derive_model(token_stream: TokenStream) -> TokenStream {
     let mut ref_list_fields = vec![];
     let mut ref_list_indexes = vec![];
 
+    let mut list_resize = vec![];
+    let mut list_insert = vec![];
+    let mut ref_list_insert = vec![];
     let mut list_remove = vec![];
 
+    let mut field_getters = vec![];
+    let mut field_getters_mut = vec![];
+    let mut optional_fields = vec![];
+    let mut optional_indexes = vec![];
+
     let model_fields_enum_str = model_name.to_string() + "Fields";
     let model_fields_enum = syn::Ident::new(&model_fields_enum_str, proc_macro2::Span::call_site());
     let mut model_fields_enum_names = vec![];
 
+    let model_navigation_trait_str = model_name.to_string() + "Navigation";
+    let model_navigation_trait =
+        syn::Ident::new(&model_navigation_trait_str, proc_macro2::Span::call_site());
+    let mut navigation_signatures = vec![];
+    let mut navigation_bodies = vec![];
+
+    let mut enum_checks = vec![];
+
+    let mut field_spans: HashMap<usize, &Ident> = HashMap::new();
     let mut has_fields = false;
-    for field in data {
-        if field.extends {
-            extension_field_ident = Some(field.ident.as_ref().unwrap().clone());
-            extension_field_model = Some(field.ty);
+    for field in data.iter() {
+        let field_ident = field
+            .ident
+            .as_ref()
+            .ok_or_else(|| syn::Error::new_spanned(&field.ty, "Model fields need a name"))?;
+
+        if field.extends.unwrap_or_default() {
+            if extension_field_ident.is_some() {
+                return Err(syn::Error::new_spanned(
+                    field_ident,
+                    "A model can only extend one other model",
+                ));
+            }
+
+            extension_field_ident = Some(field_ident.clone());
+            extension_field_model = Some(field.ty.clone());
             continue;
         }
 
         has_fields = true;
 
-        let field_ident = field.ident.as_ref().unwrap();
         let field_name = field_ident.to_string();
         let field_name = field_name.replace("r#", "");
 
+        let field_index = field.index.unwrap_or_default();
+        if let Some(other) = field_spans.insert(field_index, field_ident) {
+            return Err(syn::Error::new_spanned(
+                field_ident,
+                format!(
+                    "Field index {} is already used by {}.{}",
+                    field_index, model_name, other
+                ),
+            ));
+        }
+
         let field_type_ident =
             syn::Ident::new(&field_name.to_camel_case(), proc_macro2::Span::call_site());
 
-        let field_index = field.index;
-        let field_autofill = field.autofill;
-        let field_local_type = LocalType::parse(&field.ty);
-        let field_field_type = get_field_type(&field_local_type);
+        let field_autofill = field.autofill.unwrap_or_default();
+        let field_local_type = LocalType::parse(&field.ty)?;
+        let field_nested = get_nested(&field_local_type, &field.ty)?;
+        let field_optional = matches!(field_local_type, LocalType::Optional { .. });
+        let (field_field_type, field_enumeration) = match &field.enumeration {
+            Some(_) if field_nested => {
+                return Err(syn::Error::new_spanned(
+                    &field.ty,
+                    "Enums can't be stored in nested lists",
+                ))
+            }
+            Some(repr) => {
+                let enum_type = match &field_local_type {
+                    LocalType::Single(ident) => ident,
+                    other => other.value().ident(),
+                };
+
+                let field_type = get_primitive_field_type(repr).ok_or_else(|| {
+                    syn::Error::new_spanned(
+                        field_ident,
+                        format!("Enum field {} can't be stored as {}", field_name, repr),
+                    )
+                })?;
+
+                // The enum keeps unknown codes in its own integer type, which has to be the one
+                // on the wire so they are sign extended the same way
+                let repr = syn::Ident::new(repr, field_ident.span());
+                enum_checks.push(quote::quote_spanned! {field.ty.span()=>
+                    const _: fn(#repr) -> <#enum_type as ::uncage_model::ModelEnum>::Repr = |x| x;
+                });
+
+                (
+                    field_type,
+                    quote::quote! { Some(::uncage_model::EnumDescription::of::<#enum_type>()) },
+                )
+            }
+            None => (
+                get_field_type(&field_local_type, &field.ty)?,
+                quote::quote! { None },
+            ),
+        };
+        let field_metadata = get_metadata(field);
         let field_value_type = match &field_local_type {
             LocalType::Map { key, .. } => {
-                let key_type = get_field_type(key);
-                quote::quote! { crate::ValueType::Map { key: #key_type } }
+                if !MAP_KEY_TYPES.iter().any(|x| key.ident() == x) {
+                    return Err(syn::Error::new_spanned(
+                        &field.ty,
+                        format!("Map keys have to be one of {}", MAP_KEY_TYPES.join(", ")),
+                    ));
+                }
+
+                let key_type = get_field_type(key, &field.ty)?;
+                quote::quote! { ::uncage_model::ValueType::Map { key: #key_type } }
+            }
+            LocalType::Vector { .. } => quote::quote! { ::uncage_model::ValueType::List },
+            LocalType::Array { len, .. } => {
+                quote::quote! { ::uncage_model::ValueType::Array { len: #len } }
             }
-            LocalType::Vector { .. } => quote::quote! { crate::ValueType::List },
-            _ => quote::quote! { crate::ValueType::Value },
+            _ => quote::quote! { ::uncage_model::ValueType::Value },
         };
 
         field_definitions.push(quote::quote! {
-            crate::FieldDescription {
+            ::uncage_model::FieldDescription {
                 value_type: #field_value_type,
                 field_type: #field_field_type,
                 index: #field_index,
@@ -96,37 +190,63 @@ pub fn derive_model(token_stream: TokenStream) -> TokenStream {
                 model_name: #model_ident::const_model_name(),
                 field_name: #field_name,
                 autofill: #field_autofill,
+                enumeration: #field_enumeration,
+                optional: #field_optional,
+                nested: #field_nested,
+                metadata: #field_metadata,
             }
         });
 
         model_fields_enum_names.push(field_type_ident);
 
         indexes.push(i);
-        field_indexes.push(field.index);
+        field_indexes.push(field_index);
         fields.push(field_ident.clone());
+        field_names.push(field_name.clone());
+
+        if field_optional {
+            field_getters.push(quote::quote! {
+                self.#field_ident.as_ref().map(|x| x as &dyn ::std::any::Any)
+            });
+            field_getters_mut.push(quote::quote! {
+                Some(self.#field_ident.get_or_insert_with(Default::default))
+            });
+            optional_fields.push(field_ident.clone());
+            optional_indexes.push(field_index);
+        } else {
+            field_getters.push(quote::quote! { Some(&self.#field_ident) });
+            field_getters_mut.push(quote::quote! { Some(&mut self.#field_ident) });
+        }
 
         if let LocalType::Reference { .. } = &field_local_type {
             reference_fields.push(field_ident.clone());
-            reference_indexes.push(field.index);
+            reference_indexes.push(field_index);
+        }
+
+        if let Some((signature, body)) = get_navigation(field_ident, field_index, &field_local_type)
+        {
+            navigation_signatures.push(signature);
+            navigation_bodies.push(body);
         }
 
         if let LocalType::Map { key, is_model, .. } = &field_local_type {
+            let key = key.ident();
             map_fields.push(field_ident.clone());
-            map_indexes.push(field.index);
-            map_keys.push(key.ident().clone());
+            map_indexes.push(field_index);
+            map_keys.push(key.clone());
 
             if *is_model {
                 ref_map_fields.push(field_ident.clone());
-                ref_map_indexes.push(field.index);
-                ref_map_keys.push(key.ident().clone());
+                ref_map_indexes.push(field_index);
+                ref_map_keys.push(key.clone());
 
                 map_remove.push(quote::quote! {
-                    self.#field_ident.remove(&key).map(|x| x.into_ref())
+                    self.#field_ident.remove(&<#key as ::uncage_model::MapKeyType>::from_map_key(key)?).map(::uncage_model::Reference::into_ref)
                 });
             } else {
                 map_remove.push(quote::quote! {
                     {
-                        self.#field_ident.remove(&key);
+                        self.#field_ident.remove(&<#key as ::uncage_model::MapKeyType>::from_map_key(key)?);
                         None
                     }
                 });
@@ -135,14 +255,30 @@ pub fn derive_model(token_stream: TokenStream) -> TokenStream {
 
         if let LocalType::Vector { is_model, .. } = &field_local_type {
             list_fields.push(field_ident.clone());
-            list_indexes.push(field.index);
+            list_indexes.push(field_index);
+
+            list_resize.push(quote::quote! {
+                self.#field_ident.resize_with(new_len, || { Default::default() })
+            });
+            list_insert.push(quote::quote! {
+                {
+                    self.#field_ident.insert(index, Default::default());
+                    &mut self.#field_ident[index]
+                }
+            });
 
             if *is_model {
                 ref_list_fields.push(field_ident.clone());
-                ref_list_indexes.push(field.index);
+                ref_list_indexes.push(field_index);
 
+                ref_list_insert.push(quote::quote! {
+                    {
+                        self.#field_ident.insert(index, Default::default());
+                        ::uncage_model::Reference::as_ref_mut(&mut self.#field_ident[index])
+                    }
+                });
                 list_remove.push(quote::quote! {
-                    Some(self.#field_ident.remove(index).into_ref())
+                    Some(::uncage_model::Reference::into_ref(self.#field_ident.remove(index)))
                 });
             } else {
                 list_remove.push(quote::quote! {
@@ -154,6 +290,26 @@ pub fn derive_model(token_stream: TokenStream) -> TokenStream {
             }
         }
 
+        // Arrays share the list accessors, the patcher refuses to change their length
+        if let LocalType::Array { is_model, .. } = &field_local_type {
+            let message = format!(
+                "Can't change the length of the array on field {}",
+                field_index
+            );
+
+            list_fields.push(field_ident.clone());
+            list_indexes.push(field_index);
+            list_resize.push(quote::quote! { panic!(#message) });
+            list_insert.push(quote::quote! { panic!(#message) });
+            list_remove.push(quote::quote! { panic!(#message) });
+
+            if *is_model {
+                ref_list_fields.push(field_ident.clone());
+                ref_list_indexes.push(field_index);
+                ref_list_insert.push(quote::quote! { panic!(#message) });
+            }
+        }
+
         i += 1;
     }
     let model_fields_const_name = model_name.to_string().to_shouty_snake_case() + "_FIELDS";
@@ -161,7 +317,7 @@ pub fn derive_model(token_stream: TokenStream) -> TokenStream {
         syn::Ident::new(&model_fields_const_name, proc_macro2::Span::call_site());
 
     let model_get_fields = if let Some(extension) = &extension_field_model {
-        let extension_fields = LocalType::parse(extension)
+        let extension_fields = LocalType::parse(extension)?
             .ident()
             .to_string()
             .to_shouty_snake_case()
@@ -283,11 +439,15 @@ pub fn derive_model(token_stream: TokenStream) -> TokenStream {
     let get_map_field_mut =
         fallthrough_to_extension("get_map_field_mut", "No map on field {}", &["field", "key"]);
 
-    let swap_map_field = fallthrough_to_extension(
-        "swap_map_field",
-        "No map on field {}",
-        &["field", "lhs", "rhs"],
-    );
+    let swap_map_field = if let Some(extension) = &extension_field_ident {
+        quote::quote! {
+            field => self.#extension.swap_map_field(field, lhs, rhs)
+        }
+    } else {
+        quote::quote! {
+            _ => Err(::uncage_model::anyhow::anyhow!("No map on field {}", field))
+        }
+    };
     let remove_map_field =
         fallthrough_to_extension("remove_map_field", "No map on field {}", &["field", "key"]);
 
@@ -301,6 +461,9 @@ pub fn derive_model(token_stream: TokenStream) -> TokenStream {
         }
     };
 
+    let clear_field =
+        fallthrough_to_extension("clear_field", "No optional value on field {}", &["field"]);
+
     let get_list_len = fallthrough_to_extension("get_list_len", "No list on field {}", &["field"]);
     let resize_list = fallthrough_to_extension(
         "resize_list_field",
@@ -353,7 +516,7 @@ pub fn derive_model(token_stream: TokenStream) -> TokenStream {
                 #(#model_fields_enum_names = #field_indexes),*
             }
 
-            impl crate::Fields for #model_fields_enum {
+            impl ::uncage_model::Fields for #model_fields_enum {
                 fn field(&self) -> usize {
                     *self as usize
                 }
@@ -367,6 +530,22 @@ pub fn derive_model(token_stream: TokenStream) -> TokenStream {
         quote::quote! {}
     };
 
+    let navigation = if !navigation_signatures.is_empty() {
+        quote::quote! {
+            pub trait #model_navigation_trait<'a, R: ::uncage_model::Model, C: ::uncage_model::ModelCollection> {
+                #(#navigation_signatures;)*
+            }
+
+            impl<'a, R: ::uncage_model::Model, C: ::uncage_model::ModelCollection> #model_navigation_trait<'a, R, C>
+                for ::uncage_model::TypedBorrow<'a, #model_ident, R, C>
+            {
+                #(#navigation_signatures #navigation_bodies)*
+            }
+        }
+    } else {
+        quote::quote! {}
+    };
+
     let get_parent = if let Some(extension) = &extension_field_ident {
         quote::quote! {
             Some(&self.#extension)
@@ -375,7 +554,37 @@ pub fn derive_model(token_stream: TokenStream) -> TokenStream {
         quote::quote! { None }
     };
 
-    (quote::quote! {
+    let has_parent_field = if let Some(model) = &extension_field_model {
+        quote::quote! { #model::const_has_field(index) }
+    } else {
+        quote::quote! { false }
+    };
+
+    // Parent fields live in another derive, so collisions can only be checked by the compiler
+    let mut parent_checks = vec![];
+    if let Some(model) = &extension_field_model {
+        let parent_name = quote::quote!(#model).to_string();
+        for (field, index) in fields.iter().zip(&field_indexes) {
+            let message = format!(
+                "Field index {} of {}.{} is already used by {}",
+                index, model_name, field, parent_name
+            );
+            parent_checks.push(quote::quote_spanned! {field.span()=>
+                const _: () = assert!(!#model::const_has_field(#index), #message);
+            });
+        }
+    }
+
+    let serde = get_serde(
+        model_ident,
+        &fields,
+        &field_names,
+        extension_field_ident
+            .as_ref()
+            .zip(extension_field_model.as_ref()),
+    );
+
+    Ok(quote::quote! {
         impl #model_ident {
             pub const fn const_model_type() -> usize {
                 #model_type
@@ -388,13 +597,28 @@ pub fn derive_model(token_stream: TokenStream) -> TokenStream {
             pub const fn fields_len() -> usize {
                 #fields_len
             }
+
+            pub const fn const_has_field(index: usize) -> bool {
+                match index {
+                    #(#field_indexes => true,)*
+                    _ => #has_parent_field,
+                }
+            }
         }
 
+        #(#parent_checks)*
+
+        #(#enum_checks)*
+
         #fields_enum
 
-        const #model_fields_const_ident: [crate::FieldDescription; #i] = [#(#field_definitions),*];
+        #navigation
 
-        impl crate::Model for #model_ident {
+        #serde
+
+        const #model_fields_const_ident: [::uncage_model::FieldDescription; #i] = [#(#field_definitions),*];
+
+        impl ::uncage_model::Model for #model_ident {
             fn model_type() -> usize {
                 #model_ident::const_model_type()
             }
@@ -405,40 +629,36 @@ pub fn derive_model(token_stream: TokenStream) -> TokenStream {
         }
 
         // TODO!: Do we need to implement this?
-        impl crate::ModelExt for #model_ident {
-            fn assign<B: Buf>(&mut self, index: usize, from: &mut B) -> anyhow::Result<()> {
-                match index {
-                    #(#field_indexes => {
-                        // TODO: write to the field
-                        // self.#fields = todo!();
-
-                        Ok(())
-                    },)*
-                    _ => Err(anyhow::anyhow!("No field with index {}", index))
-                }
+        impl ::uncage_model::ModelExt for #model_ident {
+            fn assign<B: ::uncage_model::bytes::Buf>(&mut self, index: usize, from: &mut B) -> ::uncage_model::anyhow::Result<()> {
+                let description = ::uncage_model::ModelDescription::get_field_description(self, index)
+                    .ok_or_else(|| ::uncage_model::anyhow::anyhow!("No field with index {}", index))?;
+                let value = ::uncage_model::ModelDescription::get_field_mut(self, index)
+                    .ok_or_else(|| ::uncage_model::anyhow::anyhow!("No field with index {}", index))?;
+                description.assign_value(value, from)
             }
 
-            fn reset<B: Buf>(&mut self, index: usize) -> anyhow::Result<()> {
+            fn reset<B: ::uncage_model::bytes::Buf>(&mut self, index: usize) -> ::uncage_model::anyhow::Result<()> {
                 match index {
                     #(#field_indexes => {
                         // TODO: reset to default value?
-                        // 
+                        //
                         // self.#fields = self.#fields.default();
 
 
                         Ok(())
                     },)*
-                    _ => Err(anyhow::anyhow!("No field with index {}", index))
+                    _ => Err(::uncage_model::anyhow::anyhow!("No field with index {}", index))
                 }
             }
         }
 
-        impl crate::ModelDescription for #model_ident {
+        impl ::uncage_model::ModelDescription for #model_ident {
             fn as_any(&self) -> &dyn ::std::any::Any {
                 self as &dyn ::std::any::Any
             }
 
-            fn get_parent(&self) -> Option<&dyn crate::ModelDescription> {
+            fn get_parent(&self) -> Option<&dyn ::uncage_model::ModelDescription> {
                 #get_parent
             }
 
@@ -450,11 +670,11 @@ pub fn derive_model(token_stream: TokenStream) -> TokenStream {
                 #model_ident::const_model_name()
             }
 
-            fn get_fields(&self) -> Vec<crate::FieldDescription> {
+            fn get_fields(&self) -> Vec<::uncage_model::FieldDescription> {
                 #model_get_fields
             }
 
-            fn get_field_description(&self, field: usize) -> Option<&'static crate::FieldDescription> {
+            fn get_field_description(&self, field: usize) -> Option<&'static ::uncage_model::FieldDescription> {
                 match field {
                     #(#field_indexes => Some(&#model_fields_const_ident[#indexes]),)*
                     #get_field_desc
@@ -463,98 +683,116 @@ pub fn derive_model(token_stream: TokenStream) -> TokenStream {
 
             fn get_field(&self, field: usize) -> Option<&dyn ::std::any::Any> {
                 match field {
-                    #(#field_indexes => Some(&self.#fields),)*
+                    #(#field_indexes => #field_getters,)*
                     #get_field_val
                 }
             }
 
             fn get_field_mut(&mut self, field: usize) -> Option<&mut dyn ::std::any::Any> {
                 match field {
-                    #(#field_indexes => Some(&mut self.#fields),)*
+                    #(#field_indexes => #field_getters_mut,)*
                     #get_field_mut_val
                 }
             }
 
-            fn get_model_ref(&self, field: usize) -> Option<&Ref> {
+            fn get_model_ref(&self, field: usize) -> Option<&::uncage_model::Ref> {
                 match field {
-                    #(#reference_indexes => Some(self.#reference_fields.as_ref()),)*
+                    #(#reference_indexes => Some(::uncage_model::Reference::as_ref(&self.#reference_fields)),)*
                     #get_ref_val
                 }
             }
 
-            fn get_model_ref_mut(&mut self, field: usize) -> Option<&mut crate::Ref> {
+            fn get_model_ref_mut(&mut self, field: usize) -> Option<&mut ::uncage_model::Ref> {
                 match field {
-                    #(#reference_indexes => Some(self.#reference_fields.as_ref_mut()),)*
+                    #(#reference_indexes => Some(::uncage_model::Reference::as_ref_mut(&mut self.#reference_fields)),)*
                     #get_ref_val_mut
                 }
             }
 
-            fn get_map_keys(&self, field: usize) -> Vec<i32> {
+            fn get_map_keys(&self, field: usize) -> Vec<::uncage_model::MapKey> {
                 match field {
-                    #(#map_indexes => self.#map_fields.keys().copied().collect(),)*
+                    #(#map_indexes => self.#map_fields.keys().map(::uncage_model::MapKeyType::to_map_key).collect(),)*
                     #get_map_keys
                 }
             }
 
-            fn create_map_field(&mut self, field: usize, key: i32) -> Option<&mut dyn ::std::any::Any> {
+            fn create_map_field(&mut self, field: usize, key: &::uncage_model::MapKey) -> Option<&mut dyn ::std::any::Any> {
                 match field {
-                    #(#map_indexes => Some(self.#map_fields.entry(key).or_insert_with(|| Default::default())),)*
+                    #(#map_indexes => Some(self.#map_fields.entry(<#map_keys as ::uncage_model::MapKeyType>::from_map_key(key)?).or_insert_with(|| Default::default())),)*
                     #create_map_field
                 }
             }
 
-            fn create_map_field_ref(&mut self, field: usize, key: i32) -> Option<&mut crate::Ref> {
+            fn create_map_field_ref(&mut self, field: usize, key: &::uncage_model::MapKey) -> Option<&mut ::uncage_model::Ref> {
                 match field {
-                    #(#ref_map_indexes => Some(self.#ref_map_fields.entry(key).or_insert_with(|| Default::default()).as_ref_mut()),)*
+                    #(#ref_map_indexes => Some(::uncage_model::Reference::as_ref_mut(self.#ref_map_fields.entry(<#ref_map_keys as ::uncage_model::MapKeyType>::from_map_key(key)?).or_insert_with(|| Default::default()))),)*
                     #create_map_field_ref
                 }
             }
 
-            fn get_map_field(&self, field: usize, key: i32) -> Option<&dyn ::std::any::Any> {
+            fn get_map_field(&self, field: usize, key: &::uncage_model::MapKey) -> Option<&dyn ::std::any::Any> {
                 match field {
-                    #(#map_indexes => Some(self.#map_fields.get(&key)?),)*
+                    #(#map_indexes => Some(self.#map_fields.get(&<#map_keys as ::uncage_model::MapKeyType>::from_map_key(key)?)?),)*
                     #get_map_field
                 }
             }
 
-            fn get_map_field_mut(&mut self, field: usize, key: i32) -> Option<&mut dyn ::std::any::Any> {
+            fn get_map_field_mut(&mut self, field: usize, key: &::uncage_model::MapKey) -> Option<&mut dyn ::std::any::Any> {
                 match field {
-                    #(#map_indexes => Some(self.#map_fields.get_mut(&key)?),)*
+                    #(#map_indexes => Some(self.#map_fields.get_mut(&<#map_keys as ::uncage_model::MapKeyType>::from_map_key(key)?)?),)*
                     #get_map_field_mut
                 }
             }
 
-            fn get_map_field_ref(&self, field: usize, key: i32) -> Option<&Ref> {
+            fn get_map_field_ref(&self, field: usize, key: &::uncage_model::MapKey) -> Option<&::uncage_model::Ref> {
                 match field {
-                    #(#ref_map_indexes => Some(self.#ref_map_fields.get(&key)?.as_ref()),)*
+                    #(#ref_map_indexes => Some(::uncage_model::Reference::as_ref(self.#ref_map_fields.get(&<#ref_map_keys as ::uncage_model::MapKeyType>::from_map_key(key)?)?)),)*
                     #get_map_field_ref
                 }
             }
-            fn get_map_field_ref_mut(&mut self, field: usize, key: i32) -> Option<&mut crate::Ref> {
+            fn get_map_field_ref_mut(&mut self, field: usize, key: &::uncage_model::MapKey) -> Option<&mut ::uncage_model::Ref> {
                 match field {
-                    #(#ref_map_indexes => Some(self.#ref_map_fields.get_mut(&key)?.as_ref_mut()),)*
+                    #(#ref_map_indexes => Some(::uncage_model::Reference::as_ref_mut(self.#ref_map_fields.get_mut(&<#ref_map_keys as ::uncage_model::MapKeyType>::from_map_key(key)?)?)),)*
                     #get_map_field_ref_mut
                 }
             }
 
-            fn remove_map_field(&mut self, field: usize, key: i32) -> Option<crate::Ref> {
+            fn remove_map_field(&mut self, field: usize, key: &::uncage_model::MapKey) -> Option<::uncage_model::Ref> {
                 match field {
                     #(#map_indexes => #map_remove,)*
                     #remove_map_field
                 }
             }
 
-            fn swap_map_field(&mut self, field: usize, lhs: i32, rhs: i32) {
+            fn swap_map_field(&mut self, field: usize, lhs: &::uncage_model::MapKey, rhs: &::uncage_model::MapKey) -> ::uncage_model::anyhow::Result<()> {
                 match field {
                     #(#map_indexes => {
-                        let mut old = self.#map_fields.remove(&lhs).unwrap();
-                        ::std::mem::swap(&mut old, self.#map_fields.get_mut(&rhs).unwrap());
+                        let lhs = <#map_keys as ::uncage_model::MapKeyType>::from_map_key(lhs)
+                            .ok_or_else(|| ::uncage_model::anyhow::anyhow!("Invalid key {} for map on field {}", lhs, field))?;
+                        let rhs = <#map_keys as ::uncage_model::MapKeyType>::from_map_key(rhs)
+                            .ok_or_else(|| ::uncage_model::anyhow::anyhow!("Invalid key {} for map on field {}", rhs, field))?;
+                        if !self.#map_fields.contains_key(&rhs) {
+                            return Err(::uncage_model::anyhow::anyhow!("No key {} in map on field {}", rhs, field));
+                        }
+                        let mut old = self.#map_fields.remove(&lhs)
+                            .ok_or_else(|| ::uncage_model::anyhow::anyhow!("No key {} in map on field {}", lhs, field))?;
+                        if let Some(other) = self.#map_fields.get_mut(&rhs) {
+                            ::std::mem::swap(&mut old, other);
+                        }
                         self.#map_fields.insert(lhs, old);
+                        Ok(())
                     },)*
                     #swap_map_field
                 }
             }
 
+            fn clear_field(&mut self, field: usize) {
+                match field {
+                    #(#optional_indexes => self.#optional_fields = None,)*
+                    #clear_field
+                }
+            }
+
             fn get_list_len(&self, field: usize) -> usize {
                 match field {
                     #(#list_indexes => self.#list_fields.len(),)*
@@ -564,7 +802,7 @@ pub fn derive_model(token_stream: TokenStream) -> TokenStream {
 
             fn resize_list_field(&mut self, field: usize, new_len: usize) {
                 match field {
-                    #(#list_indexes => self.#list_fields.resize_with(new_len, || { Default::default() }),)*
+                    #(#list_indexes => #list_resize,)*
                     #resize_list
                 }
             }
@@ -585,39 +823,33 @@ pub fn derive_model(token_stream: TokenStream) -> TokenStream {
 
             fn insert_list_field(&mut self, field: usize, index: usize) -> &mut dyn ::std::any::Any  {
                 match field {
-                    #(#list_indexes => {
-                        self.#list_fields.insert(index, Default::default());
-                        &mut self.#list_fields[index]
-                    },)*
+                    #(#list_indexes => #list_insert,)*
                     #insert_list_field
                 }
             }
 
-            fn get_list_field_ref(&self, field: usize, index: usize) -> &crate::Ref {
+            fn get_list_field_ref(&self, field: usize, index: usize) -> &::uncage_model::Ref {
                 match field {
-                    #(#ref_list_indexes => self.#ref_list_fields[index].as_ref(),)*
+                    #(#ref_list_indexes => ::uncage_model::Reference::as_ref(&self.#ref_list_fields[index]),)*
                     #get_list_field_ref
                 }
             }
 
-            fn get_list_field_ref_mut(&mut self, field: usize, index: usize) -> &mut crate::Ref {
+            fn get_list_field_ref_mut(&mut self, field: usize, index: usize) -> &mut ::uncage_model::Ref {
                 match field {
-                    #(#ref_list_indexes => self.#ref_list_fields[index].as_ref_mut(),)*
+                    #(#ref_list_indexes => ::uncage_model::Reference::as_ref_mut(&mut self.#ref_list_fields[index]),)*
                     #get_list_field_ref_mut
                 }
             }
 
-            fn insert_list_field_ref(&mut self, field: usize, index: usize) -> &mut crate::Ref {
+            fn insert_list_field_ref(&mut self, field: usize, index: usize) -> &mut ::uncage_model::Ref {
                 match field {
-                    #(#ref_list_indexes => {
-                        self.#ref_list_fields.insert(index, Default::default());
-                        self.#ref_list_fields[index].as_ref_mut()
-                    },)*
+                    #(#ref_list_indexes => #ref_list_insert,)*
                     #insert_list_field_ref
                 }
             }
 
-            fn remove_list_field(&mut self, field: usize, index: usize) -> Option<crate::Ref> {
+            fn remove_list_field(&mut self, field: usize, index: usize) -> Option<::uncage_model::Ref> {
                 match field {
                     #(#list_indexes => #list_remove,)*
                     #remove_list_field
@@ -632,30 +864,69 @@ pub fn derive_model(token_stream: TokenStream) -> TokenStream {
             }
         }
     })
-        .into()
 }
 
 #[proc_macro_derive(ModelCollection)]
 pub fn derive_model_collection(token_stream: TokenStream) -> TokenStream {
     let ast: DeriveInput = parse_macro_input!(token_stream as DeriveInput);
+    expand_model_collection(&ast)
+        .unwrap_or_else(|err| err.to_compile_error())
+        .into()
+}
+
+fn expand_model_collection(ast: &DeriveInput) -> syn::Result<proc_macro2::TokenStream> {
     let name = &ast.ident;
     let enum_data = if let syn::Data::Enum(enum_data) = &ast.data {
         enum_data
     } else {
-        panic!("ModelCollection can only be derived from enums");
+        return Err(syn::Error::new_spanned(
+            name,
+            "ModelCollection can only be derived from enums",
+        ));
     };
 
     let mut names = vec![];
     let mut models = vec![];
+    let mut type_checks = vec![];
 
     for variant in enum_data.variants.iter() {
+        let item = match variant.fields.iter().collect::<Vec<_>>().as_slice() {
+            [item] => *item,
+            _ => {
+                return Err(syn::Error::new_spanned(
+                    variant,
+                    "Every variant of a ModelCollection holds exactly one model",
+                ))
+            }
+        };
+
+        // Model types come from the derives of the models, so the compiler has to compare them
+        let model = &item.ty;
+        let message = format!(
+            "The model type of {} is already used by another model in {}",
+            quote::quote!(#model),
+            name
+        );
+        type_checks.push(quote::quote_spanned! {variant.ident.span()=>
+            const _: () = {
+                let model_type = #model::const_model_type();
+                let previous: &[usize] = &[#(#models::const_model_type()),*];
+                let mut i = 0;
+                while i < previous.len() {
+                    assert!(previous[i] != model_type, #message);
+                    i += 1;
+                }
+            };
+        });
+
         names.push(&variant.ident);
-        let item = variant.fields.iter().next().unwrap();
-        models.push(&item.ty);
+        models.push(model);
     }
 
-    let code = quote::quote! {
-        impl crate::ModelCollection for #name {
+    Ok(quote::quote! {
+        #(#type_checks)*
+
+        impl ::uncage_model::ModelCollection for #name {
             fn create_model(id: usize) -> Self {
                 #(if id == #models::const_model_type() { return #name::#names(#models::default()) })*
                 panic!("No model in model collection with model type {}", id)
@@ -666,17 +937,138 @@ pub fn derive_model_collection(token_stream: TokenStream) -> TokenStream {
                 false
             }
 
-            fn boxed(self) -> Box<dyn crate::ModelDescription> {
+            fn model_types() -> Vec<usize> {
+                vec![#(#models::const_model_type()),*]
+            }
+
+            fn boxed(self) -> Box<dyn ::uncage_model::ModelDescription> {
                 match self {
                     #(#name::#names(model) => Box::new(model),)*
                 }
             }
         }
-    };
+    })
+}
 
-    code.into()
+/// Derives `ModelEnum` for an enum of unit variants marked with `#[uncage(value = N)]` and one
+/// `Unknown(n)` variant that keeps every other value, `Default` is the variant for 0
+#[proc_macro_derive(ModelEnum, attributes(uncage))]
+pub fn derive_model_enum(token_stream: TokenStream) -> TokenStream {
+    let ast: DeriveInput = parse_macro_input!(token_stream as DeriveInput);
+    expand_model_enum(&ast)
+        .unwrap_or_else(|err| err.to_compile_error())
+        .into()
 }
 
+fn expand_model_enum(ast: &DeriveInput) -> syn::Result<proc_macro2::TokenStream> {
+    let config = EnumConfig::from_derive_input(ast)?;
+
+    let name = &config.ident;
+    let enum_name = name.to_string();
+    let variants = config
+        .data
+        .take_enum()
+        .ok_or_else(|| syn::Error::new_spanned(name, "ModelEnum can only be derived from enums"))?;
+
+    let mut idents = vec![];
+    let mut names = vec![];
+    let mut values = vec![];
+    let mut unknown = None;
+
+    for variant in variants {
+        if variant.fields.is_unit() {
+            let value = variant.value.ok_or_else(|| {
+                syn::Error::new_spanned(
+                    &variant.ident,
+                    format!(
+                        "Variant {}::{} needs a #[uncage(value = ...)]",
+                        enum_name, variant.ident
+                    ),
+                )
+            })?;
+
+            if let Some(i) = values.iter().position(|x| *x == value) {
+                return Err(syn::Error::new_spanned(
+                    &variant.ident,
+                    format!(
+                        "Value {} is already used by {}::{}",
+                        value, enum_name, idents[i]
+                    ),
+                ));
+            }
+
+            names.push(variant.ident.to_string());
+            idents.push(variant.ident);
+            values.push(value);
+        } else if variant.fields.len() == 1 && unknown.is_none() {
+            unknown = Some((variant.ident, variant.fields.fields[0].clone()));
+        } else {
+            return Err(syn::Error::new_spanned(
+                &variant.ident,
+                format!(
+                    "{} can only have unit variants and a single Unknown(n) variant",
+                    enum_name
+                ),
+            ));
+        }
+    }
+
+    let (unknown, repr) = unknown.ok_or_else(|| {
+        syn::Error::new_spanned(name, format!("{} needs an Unknown(n) variant", enum_name))
+    })?;
+    let literals = values
+        .iter()
+        .map(|x| proc_macro2::Literal::i64_unsuffixed(*x));
+
+    Ok(quote::quote! {
+        // Every value has to fit the type unknown values are kept in
+        const _: &[#repr] = &[#(#literals),*];
+
+        impl ::uncage_model::ModelEnum for #name {
+            type Repr = #repr;
+
+            const NAME: &'static str = #enum_name;
+            const VARIANTS: &'static [(i64, &'static str)] = &[#((#values, #names)),*];
+
+            fn from_value(value: i64) -> Self {
+                match value {
+                    #(#values => #name::#idents,)*
+                    value => #name::#unknown(value as _),
+                }
+            }
+
+            fn value(&self) -> i64 {
+                match self {
+                    #(#name::#idents => #values,)*
+                    #name::#unknown(value) => *value as i64,
+                }
+            }
+        }
+
+        impl Default for #name {
+            fn default() -> Self {
+                <#name as ::uncage_model::ModelEnum>::from_value(0)
+            }
+        }
+
+        #[cfg(feature = "serde")]
+        impl ::serde::Serialize for #name {
+            fn serialize<S: ::serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                ::uncage_model::serialize_enum(self, serializer)
+            }
+        }
+
+        #[cfg(feature = "serde")]
+        impl<'de> ::serde::Deserialize<'de> for #name {
+            fn deserialize<D: ::serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                ::uncage_model::deserialize_enum(deserializer)
+            }
+        }
+    })
+}
+
+const MAP_KEY_TYPES: &[&str] = &["i8", "u8", "i16", "u16", "i32", "u32", "i64", "String"];
+
 enum LocalType {
     Single(Ident),
     Map {
@@ -690,35 +1082,270 @@ enum LocalType {
         is_model: bool,
         value: Box<LocalType>,
     },
+    Array {
+        is_model: bool,
+        value: Box<LocalType>,
+        len: Box<syn::Expr>,
+    },
+    Optional {
+        option_type: Ident,
+        value: Box<LocalType>,
+    },
     Reference {
         ref_type: Ident,
         target: Option<Box<LocalType>>,
     },
 }
 
-fn get_field_type(from: &LocalType) -> proc_macro2::TokenStream {
-    match from.ident().to_string().as_str() {
-        "bool" => quote::quote! { crate::FieldType::Boolean },
-        "u8" => quote::quote! { crate::FieldType::UInt8 },
-        "i8" => quote::quote! { crate::FieldType::Int8 },
-        "u16" => quote::quote! { crate::FieldType::UInt16 },
-        "i16" => quote::quote! { crate::FieldType::Int16 },
-        "u32" => quote::quote! { crate::FieldType::UInt32 },
-        "i32" => quote::quote! { crate::FieldType::Int32 },
-        "u64" => quote::quote! { crate::FieldType::UInt64 },
-        "i64" => quote::quote! { crate::FieldType::Int64 },
-        "u128" => quote::quote! { crate::FieldType::UInt128 },
-        "i128" => quote::quote! { crate::FieldType::Int128 },
-        "f32" => quote::quote! { crate::FieldType::Float },
-        "f64" => quote::quote! { crate::FieldType::Double },
-        "String" => quote::quote! { crate::FieldType::String },
-        "Ref" => quote::quote! { crate::FieldType::Model },
-        "BTreeMap" | "HashMap" | "Vec" => get_field_type(from.value()),
+/// Serde support is only generated for crates that enable their own `serde` feature
+fn get_serde(
+    model_ident: &Ident,
+    fields: &[Ident],
+    field_names: &[String],
+    extension: Option<(&Ident, &Type)>,
+) -> proc_macro2::TokenStream {
+    let field_count = fields.len();
+    let (parent_count, parent_serialize, parent_deserialize) = match extension {
+        Some((ident, ty)) => (
+            quote::quote! { + <#ty as ::uncage_model::SerdeModel>::FIELD_COUNT },
+            quote::quote! { ::uncage_model::SerdeModel::serialize_fields(&self.#ident, map)?; },
+            quote::quote! { ::uncage_model::SerdeModel::deserialize_field(&mut self.#ident, key, map) },
+        ),
+        None => (
+            quote::quote! {},
+            quote::quote! {},
+            quote::quote! { Ok(false) },
+        ),
+    };
+
+    quote::quote! {
+        #[cfg(feature = "serde")]
+        impl ::uncage_model::SerdeModel for #model_ident {
+            const FIELD_COUNT: usize = #field_count #parent_count;
+
+            #[allow(unused_variables)]
+            fn serialize_fields<M: ::serde::ser::SerializeMap>(&self, map: &mut M) -> Result<(), M::Error> {
+                #parent_serialize
+                #(map.serialize_entry(#field_names, &self.#fields)?;)*
+                Ok(())
+            }
+
+            #[allow(unused_variables)]
+            fn deserialize_field<'de, A: ::serde::de::MapAccess<'de>>(
+                &mut self,
+                key: &str,
+                map: &mut A,
+            ) -> Result<bool, A::Error> {
+                #(if key == #field_names {
+                    self.#fields = map.next_value()?;
+                    return Ok(true);
+                })*
+
+                #parent_deserialize
+            }
+        }
+
+        #[cfg(feature = "serde")]
+        impl ::serde::Serialize for #model_ident {
+            fn serialize<S: ::serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                ::uncage_model::serialize_model(self, serializer)
+            }
+        }
+
+        #[cfg(feature = "serde")]
+        impl<'de> ::serde::Deserialize<'de> for #model_ident {
+            fn deserialize<D: ::serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                ::uncage_model::deserialize_model(deserializer)
+            }
+        }
+    }
+}
+
+fn get_metadata(field: &FieldConfig) -> proc_macro2::TokenStream {
+    let optional = |value: &Option<String>| match value {
+        Some(value) => quote::quote! { Some(#value) },
+        None => quote::quote! { None },
+    };
+
+    let unit = optional(&field.unit);
+    let description = optional(&field.description);
+    let deprecated = field.deprecated.unwrap_or_default();
+    let hidden_info = field.hidden_info.unwrap_or_default();
+    let interpolatable = field.interpolatable.unwrap_or_default();
+
+    quote::quote! {
+        ::uncage_model::FieldMetadata {
+            unit: #unit,
+            description: #description,
+            deprecated: #deprecated,
+            hidden_info: #hidden_info,
+            interpolatable: #interpolatable,
+        }
+    }
+}
+
+fn get_primitive_field_type(name: &str) -> Option<proc_macro2::TokenStream> {
+    Some(match name {
+        "bool" => quote::quote! { ::uncage_model::FieldType::Boolean },
+        "u8" => quote::quote! { ::uncage_model::FieldType::UInt8 },
+        "i8" => quote::quote! { ::uncage_model::FieldType::Int8 },
+        "u16" => quote::quote! { ::uncage_model::FieldType::UInt16 },
+        "i16" => quote::quote! { ::uncage_model::FieldType::Int16 },
+        "u32" => quote::quote! { ::uncage_model::FieldType::UInt32 },
+        "i32" => quote::quote! { ::uncage_model::FieldType::Int32 },
+        "u64" => quote::quote! { ::uncage_model::FieldType::UInt64 },
+        "i64" => quote::quote! { ::uncage_model::FieldType::Int64 },
+        "u128" => quote::quote! { ::uncage_model::FieldType::UInt128 },
+        "i128" => quote::quote! { ::uncage_model::FieldType::Int128 },
+        "f32" => quote::quote! { ::uncage_model::FieldType::Float },
+        "f64" => quote::quote! { ::uncage_model::FieldType::Double },
+        "String" => quote::quote! { ::uncage_model::FieldType::String },
+        _ => return None,
+    })
+}
+
+/// Whether the elements of a list, array or map are lists themselves, which only works for
+/// one level of plain values
+fn get_nested(from: &LocalType, ty: &Type) -> syn::Result<bool> {
+    let element = match from {
+        LocalType::Vector { value, .. }
+        | LocalType::Array { value, .. }
+        | LocalType::Map { value, .. } => value,
+        LocalType::Optional { value, .. } => {
+            return match value.as_ref() {
+                LocalType::Single(_) => Ok(false),
+                LocalType::Reference { .. } => Err(syn::Error::new_spanned(
+                    ty,
+                    "Model references are optional already",
+                )),
+                _ => Err(syn::Error::new_spanned(
+                    ty,
+                    "Only plain values can be optional",
+                )),
+            };
+        }
+        _ => return Ok(false),
+    };
+
+    match element.as_ref() {
+        LocalType::Single(_) | LocalType::Reference { .. } => Ok(false),
+        LocalType::Vector {
+            is_model: false,
+            value,
+            ..
+        } if matches!(value.as_ref(), LocalType::Single(_)) => Ok(true),
+        _ => Err(syn::Error::new_spanned(
+            ty,
+            "Lists, arrays and maps can only hold values, models or lists of plain values",
+        )),
+    }
+}
+
+/// `ty` is the type written on the field, errors point at it
+fn get_field_type(from: &LocalType, ty: &Type) -> syn::Result<proc_macro2::TokenStream> {
+    if let LocalType::Array { value, .. } | LocalType::Optional { value, .. } = from {
+        return get_field_type(value, ty);
+    }
+
+    if let Some(field_type) = get_primitive_field_type(&from.ident().to_string()) {
+        return Ok(field_type);
+    }
+
+    Ok(match from.ident().to_string().as_str() {
+        "Ref" => quote::quote! { ::uncage_model::FieldType::Model },
+        "BTreeMap" | "HashMap" | "Vec" => get_field_type(from.value(), ty)?,
         "ModelRef" | "ModelVec" | "ModelHashMap" | "ModelBTreeMap" => {
             let value_type = from.value().ident();
-            quote::quote! { crate::FieldType::TypeModel(#value_type::const_model_type()) }
+            quote::quote! { ::uncage_model::FieldType::TypeModel(#value_type::const_model_type()) }
+        }
+        _ => {
+            return Err(syn::Error::new_spanned(
+                ty,
+                format!(
+                    "Don't have a field type for {}, models have to be wrapped in ModelRef",
+                    from.ident()
+                ),
+            ))
+        }
+    })
+}
+
+fn get_reference_type(from: &LocalType) -> proc_macro2::TokenStream {
+    match from {
+        LocalType::Reference {
+            target: Some(target),
+            ..
+        } => {
+            let target = target.ident();
+            quote::quote! { ::uncage_model::ModelRef<#target> }
+        }
+        LocalType::Reference { target: None, .. } => quote::quote! { ::uncage_model::Ref },
+        _ => {
+            let target = from.ident();
+            quote::quote! { ::uncage_model::ModelRef<#target> }
         }
-        _ => panic!("Don't have a field type for {}", from.ident()),
+    }
+}
+
+fn get_navigation(
+    field_ident: &Ident,
+    field_index: usize,
+    from: &LocalType,
+) -> Option<(proc_macro2::TokenStream, proc_macro2::TokenStream)> {
+    match from {
+        LocalType::Reference { .. } => {
+            let reference_type = get_reference_type(from);
+            Some((
+                quote::quote! {
+                    fn #field_ident(&self) -> Option<<#reference_type as ::uncage_model::Resolve<'a, R, C>>::Output>
+                },
+                quote::quote! {
+                    {
+                        self.follow::<#reference_type>(#field_index)
+                    }
+                },
+            ))
+        }
+        LocalType::Vector {
+            is_model: true,
+            value,
+            ..
+        }
+        | LocalType::Array {
+            is_model: true,
+            value,
+            ..
+        } => {
+            let reference_type = get_reference_type(value);
+            Some((
+                quote::quote! {
+                    fn #field_ident(&self) -> ::uncage_model::RefList<'a, #reference_type, R, C>
+                },
+                quote::quote! {
+                    {
+                        self.list::<#reference_type>(#field_index)
+                    }
+                },
+            ))
+        }
+        LocalType::Map {
+            is_model: true,
+            value,
+            ..
+        } => {
+            let reference_type = get_reference_type(value);
+            Some((
+                quote::quote! {
+                    fn #field_ident(&self) -> ::uncage_model::RefMap<'a, #reference_type, R, C>
+                },
+                quote::quote! {
+                    {
+                        self.map::<#reference_type>(#field_index)
+                    }
+                },
+            ))
+        }
+        _ => None,
     }
 }
 
@@ -728,6 +1355,8 @@ impl LocalType {
             LocalType::Single(s) => s,
             LocalType::Map { map_type, .. } => map_type,
             LocalType::Vector { vector_type, .. } => vector_type,
+            LocalType::Array { value, .. } => value.ident(),
+            LocalType::Optional { option_type, .. } => option_type,
             LocalType::Reference { ref_type, .. } => ref_type,
         }
     }
@@ -736,18 +1365,37 @@ impl LocalType {
         match self {
             LocalType::Map { value, .. } => value,
             LocalType::Vector { value, .. } => value,
+            LocalType::Array { value, .. } => value,
+            LocalType::Optional { value, .. } => value,
             LocalType::Reference { target, .. } => target.as_ref().unwrap(),
             _ => panic!(),
         }
     }
 
-    pub fn parse(ty: &Type) -> LocalType {
+    pub fn parse(ty: &Type) -> syn::Result<LocalType> {
         let path = match ty {
             Type::Path(path) => path,
-            _ => panic!("Model can only have plain types: {:?}", ty),
+            Type::Array(array) => {
+                let value_local_type = LocalType::parse(&array.elem)?;
+                return Ok(LocalType::Array {
+                    is_model: matches!(value_local_type, LocalType::Reference { .. }),
+                    value: Box::new(value_local_type),
+                    len: Box::new(array.len.clone()),
+                });
+            }
+            _ => {
+                return Err(syn::Error::new_spanned(
+                    ty,
+                    "Model fields can only have plain types",
+                ))
+            }
         };
 
-        let first = path.path.segments.first().unwrap();
+        let first = path
+            .path
+            .segments
+            .first()
+            .ok_or_else(|| syn::Error::new_spanned(ty, "Empty type"))?;
 
         let child =
             if let PathArguments::AngleBracketed(AngleBracketedGenericArguments { args, .. }) =
@@ -755,18 +1403,40 @@ impl LocalType {
             {
                 args.iter()
                     .map(|x| match x {
-                        GenericArgument::Type(t) => t.clone(),
-                        _ => panic!("Model types can only have type arguments"),
+                        GenericArgument::Type(t) => Ok(t.clone()),
+                        _ => Err(syn::Error::new_spanned(
+                            x,
+                            "Model types can only have type arguments",
+                        )),
                     })
-                    .collect::<Vec<_>>()
+                    .collect::<syn::Result<Vec<_>>>()?
             } else {
                 vec![]
             };
 
         let x = first.ident.clone();
-        match x.to_string().as_str() {
+        let arguments = match x.to_string().as_str() {
+            "ModelVec" | "Vec" | "ModelRef" | "Option" => 1,
+            "BTreeMap" | "HashMap" | "ModelHashMap" | "ModelBTreeMap" => 2,
+            _ if child.is_empty() => 0,
+            _ => {
+                return Err(syn::Error::new_spanned(
+                    ty,
+                    format!("Unsupported type {} on a model field", x),
+                ))
+            }
+        };
+
+        if child.len() != arguments {
+            return Err(syn::Error::new_spanned(
+                ty,
+                format!("{} needs {} type arguments", x, arguments),
+            ));
+        }
+
+        Ok(match x.to_string().as_str() {
             "ModelVec" | "Vec" => {
-                let value_local_type = LocalType::parse(&child[0]);
+                let value_local_type = LocalType::parse(&child[0])?;
                 LocalType::Vector {
                     is_model: x.to_string().as_str() == "ModelVec"
                         || matches!(value_local_type, LocalType::Reference { .. }),
@@ -776,13 +1446,13 @@ impl LocalType {
             }
 
             "BTreeMap" | "HashMap" | "ModelHashMap" | "ModelBTreeMap" => {
-                let value_local_type = LocalType::parse(&child[1]);
+                let value_local_type = LocalType::parse(&child[1])?;
 
                 LocalType::Map {
                     is_model: x.to_string().as_str() == "ModelHashMap"
                         || x.to_string().as_str() == "ModelBTreeMap"
                         || matches!(value_local_type, LocalType::Reference { .. }),
-                    key: Box::new(LocalType::parse(&child[0])),
+                    key: Box::new(LocalType::parse(&child[0])?),
                     value: Box::new(value_local_type),
                     map_type: x,
                 }
@@ -790,7 +1460,12 @@ impl LocalType {
 
             "ModelRef" => LocalType::Reference {
                 ref_type: x,
-                target: Some(Box::new(LocalType::parse(&child[0]))),
+                target: Some(Box::new(LocalType::parse(&child[0])?)),
+            },
+
+            "Option" => LocalType::Optional {
+                value: Box::new(LocalType::parse(&child[0])?),
+                option_type: x,
             },
 
             "Ref" => LocalType::Reference {
@@ -799,28 +1474,46 @@ impl LocalType {
             },
 
             _ => LocalType::Single(x),
-        }
+        })
     }
 }
 
 #[derive(Debug, FromDeriveInput)]
 #[darling(attributes(uncage))]
 struct StructConfig {
-    ident: Ident,
     data: darling::ast::Data<darling::util::Ignored, FieldConfig>,
     #[darling(rename = "type")]
     model_type: usize,
 }
 
+#[derive(Debug, FromDeriveInput)]
+#[darling(attributes(uncage), supports(enum_any))]
+struct EnumConfig {
+    ident: Ident,
+    data: darling::ast::Data<VariantConfig, darling::util::Ignored>,
+}
+
+#[derive(Debug, FromVariant)]
+#[darling(attributes(uncage))]
+struct VariantConfig {
+    ident: Ident,
+    fields: darling::ast::Fields<Type>,
+    value: Option<i64>,
+}
+
 #[derive(Debug, FromField)]
 #[darling(attributes(uncage))]
 struct FieldConfig {
     ident: Option<Ident>,
     ty: Type,
-    #[darling(default)]
-    extends: bool,
-    #[darling(default)]
-    index: usize,
-    #[darling(default)]
-    autofill: bool,
+    extends: Option<bool>,
+    index: Option<usize>,
+    autofill: Option<bool>,
+    #[darling(rename = "enum")]
+    enumeration: Option<String>,
+    unit: Option<String>,
+    description: Option<String>,
+    deprecated: Option<bool>,
+    hidden_info: Option<bool>,
+    interpolatable: Option<bool>,
 }
@@ -1,9 +1,10 @@
+use crate::patcher::PatchAction;
 use crate::{
-    FieldDescription, FieldType, Model, ModelCollection, ModelDescription, ModelExt, ModelRef, Ref,
-    Reference, ValueType,
+    FieldDescription, FieldType, MapKey, Model, ModelCollection, ModelDescription, ModelExt,
+    ModelRef, Ref, Reference, TypedBorrow, ValueType,
 };
 use anyhow::Context;
-use bytes::Buf;
+use bytes::{Buf, BufMut, Bytes, BytesMut};
 use serde_json::{Map, Value};
 use std::any::Any;
 use std::cell::UnsafeCell;
@@ -22,10 +23,11 @@ impl ModelRc {
         ModelRc(Rc::new(UnsafeCell::new(model)))
     }
 
-    pub fn get(&self) -> &Box<dyn ModelDescription> {
-        unsafe { &*self.0.get() }
+    pub fn get(&self) -> &dyn ModelDescription {
+        unsafe { &**self.0.get() }
     }
 
+    #[allow(clippy::mut_from_ref)]
     fn get_mut(&self) -> &mut Box<dyn ModelDescription> {
         unsafe { &mut *self.0.get() }
     }
@@ -49,6 +51,26 @@ pub struct ModelBorrow<'a, R: Model, C: ModelCollection> {
     model: ModelRc,
 }
 
+impl<'a, R: Model, C: ModelCollection> Clone for ModelBorrow<'a, R, C> {
+    fn clone(&self) -> Self {
+        ModelBorrow {
+            document: self.document,
+            id: self.id,
+            model: self.model.clone(),
+        }
+    }
+}
+
+impl<'a, R: Model, C: ModelCollection> ModelBorrow<'a, R, C> {
+    pub fn document_ref(&self) -> &'a Document<R, C> {
+        self.document
+    }
+
+    pub fn cast<T: Model>(self) -> Option<TypedBorrow<'a, T, R, C>> {
+        TypedBorrow::new(self)
+    }
+}
+
 pub struct ModelBorrowOwned<R: Model, C: ModelCollection> {
     document: Document<R, C>,
     id: usize,
@@ -128,6 +150,47 @@ pub trait ModelWithDocument<R: Model, C: ModelCollection> {
         }
     }
 
+    /// Reads the key of an element of a map or list field, list indexes are sent as `i32`
+    fn read_key<B: Buf>(&self, field: usize, buffer: &mut B) -> anyhow::Result<MapKey> {
+        match self.value_type(field) {
+            ValueType::Map { key } => key.read_key(buffer),
+            _ => FieldType::Int32.read_key(buffer),
+        }
+    }
+
+    fn is_optional_field(&self, field: usize) -> bool {
+        self.model()
+            .get()
+            .get_field_description(field)
+            .map(|x| x.optional)
+            .unwrap_or(false)
+    }
+
+    fn clear_field(&mut self, field: usize) {
+        self.model().get_mut().clear_field(field)
+    }
+
+    /// Fails for arrays, their length is fixed
+    fn expect_resizable(&self, field: usize) -> anyhow::Result<()> {
+        if let ValueType::Array { len } = self.value_type(field) {
+            anyhow::bail!(
+                "Can't resize the array of length {} on field {}",
+                len,
+                field
+            );
+        }
+
+        Ok(())
+    }
+
+    fn is_model_field(&self, field: usize) -> bool {
+        self.model()
+            .get()
+            .get_field_description(field)
+            .map(|x| matches!(x.field_type, FieldType::Model | FieldType::TypeModel(_)))
+            .unwrap_or(false)
+    }
+
     fn set_model(&self, field: usize, model: Box<dyn ModelDescription>) -> Option<usize> {
         let model_ref = self.model().get_mut().get_model_ref_mut(field)?;
         Some(self.document().replace_ref(model_ref, model))
@@ -136,14 +199,14 @@ pub trait ModelWithDocument<R: Model, C: ModelCollection> {
     fn map_create_model(
         &self,
         field: usize,
-        key: i32,
+        key: &MapKey,
         model: Box<dyn ModelDescription>,
     ) -> Option<usize> {
         let model_ref = self.model().get_mut().create_map_field_ref(field, key)?;
         Some(self.document().replace_ref(model_ref, model))
     }
 
-    fn map_get_model_id(&self, field: usize, key: i32) -> Option<usize> {
+    fn map_get_model_id(&self, field: usize, key: &MapKey) -> Option<usize> {
         let model_ref = self.model().get().get_map_field_ref(field, key)?;
         model_ref.get()
     }
@@ -151,24 +214,18 @@ pub trait ModelWithDocument<R: Model, C: ModelCollection> {
     fn list_create_model(
         &self,
         field: usize,
-        index: i32,
+        index: usize,
         model: Box<dyn ModelDescription>,
     ) -> Option<usize> {
-        let model_ref = self
-            .model()
-            .get_mut()
-            .get_list_field_ref_mut(field, index as usize);
+        let model_ref = self.model().get_mut().get_list_field_ref_mut(field, index);
         Some(self.document().replace_ref(model_ref, model))
     }
 
-    fn list_get_model_id(&self, field: usize, index: i32) -> Option<usize> {
-        self.model()
-            .get_mut()
-            .get_list_field_ref(field, index as usize)
-            .get()
+    fn list_get_model_id(&self, field: usize, index: usize) -> Option<usize> {
+        self.model().get().get_list_field_ref(field, index).get()
     }
 
-    fn map_assign<B: Buf>(&self, field: usize, index: i32, buffer: &mut B) -> anyhow::Result<()> {
+    fn map_assign<B: Buf>(&self, field: usize, key: &MapKey, buffer: &mut B) -> anyhow::Result<()> {
         let desc = self
             .model()
             .get()
@@ -177,36 +234,40 @@ pub trait ModelWithDocument<R: Model, C: ModelCollection> {
         let item = self
             .model()
             .get_mut()
-            .get_map_field_mut(field, index)
-            .context("key does not exist")?;
+            .create_map_field(field, key)
+            .with_context(|| format!("No map on field {} for key {:?}", field, key))?;
         desc.assign_value(item, buffer)?;
         Ok(())
     }
 
-    fn list_insert<B: Buf>(&self, field: usize, index: i32, buffer: &mut B) -> anyhow::Result<()> {
+    fn list_insert<B: Buf>(
+        &self,
+        field: usize,
+        index: usize,
+        buffer: &mut B,
+    ) -> anyhow::Result<()> {
         let desc = self
             .model()
             .get()
             .get_field_description(field)
             .context("no field")?;
-        let item = self
-            .model()
-            .get_mut()
-            .insert_list_field(field, index as usize);
+        let item = self.model().get_mut().insert_list_field(field, index);
         desc.assign_value(item, buffer)?;
         Ok(())
     }
 
-    fn list_assign<B: Buf>(&self, field: usize, index: i32, buffer: &mut B) -> anyhow::Result<()> {
+    fn list_assign<B: Buf>(
+        &self,
+        field: usize,
+        index: usize,
+        buffer: &mut B,
+    ) -> anyhow::Result<()> {
         let desc = self
             .model()
             .get()
             .get_field_description(field)
             .context("no field")?;
-        let item = self
-            .model()
-            .get_mut()
-            .get_list_field_mut(field, index as usize);
+        let item = self.model().get_mut().get_list_field_mut(field, index);
         desc.assign_value(item, buffer)?;
         Ok(())
     }
@@ -214,16 +275,17 @@ pub trait ModelWithDocument<R: Model, C: ModelCollection> {
     fn list_insert_model(
         &self,
         field: usize,
-        index: i32,
+        index: usize,
         model: Box<dyn ModelDescription>,
     ) -> Option<usize> {
-        let model_ref = self
-            .model()
-            .get_mut()
-            .insert_list_field_ref(field, index as usize);
+        let model_ref = self.model().get_mut().insert_list_field_ref(field, index);
         Some(self.document().replace_ref(model_ref, model))
     }
 
+    fn list_len(&self, field: usize) -> usize {
+        self.model().get().get_list_len(field)
+    }
+
     fn resize_list(&self, field: usize, new_len: usize) {
         self.model().get_mut().resize_list_field(field, new_len);
         let desc = self.model().get().get_field_description(field).unwrap();
@@ -240,30 +302,32 @@ pub trait ModelWithDocument<R: Model, C: ModelCollection> {
         }
     }
 
-    fn list_swap(&mut self, field: usize, lhs: i32, rhs: i32) {
-        self.model()
-            .get_mut()
-            .swap_list_field(field, lhs as usize, rhs as usize);
+    fn list_swap(&mut self, field: usize, lhs: usize, rhs: usize) {
+        self.model().get_mut().swap_list_field(field, lhs, rhs);
+    }
+
+    fn map_swap(&mut self, field: usize, lhs: &MapKey, rhs: &MapKey) -> anyhow::Result<()> {
+        self.model().get_mut().swap_map_field(field, lhs, rhs)
     }
 
     #[must_use = "After removing a key a model id may be returned that should be cleaned up"]
-    fn list_remove(&mut self, field: usize, index: i32) -> Option<usize> {
+    fn list_remove(&mut self, field: usize, index: usize) -> Option<usize> {
         self.model()
             .get_mut()
-            .remove_list_field(field, index as usize)
+            .remove_list_field(field, index)
             .and_then(|x| x.get())
     }
 
     #[must_use = "After reseting a key a model id is returned that should be cleaned up"]
-    fn list_reset_model(&mut self, field: usize, index: i32) -> Option<usize> {
+    fn list_reset_model(&mut self, field: usize, index: usize) -> Option<usize> {
         self.model()
             .get_mut()
-            .get_list_field_ref_mut(field, index as usize)
+            .get_list_field_ref_mut(field, index)
             .reset()
     }
 
     #[must_use = "After reseting a key a model id is returned that should be cleaned up"]
-    fn map_reset_model(&mut self, field: usize, key: i32) -> Option<usize> {
+    fn map_reset_model(&mut self, field: usize, key: &MapKey) -> Option<usize> {
         self.model()
             .get_mut()
             .get_map_field_ref_mut(field, key)?
@@ -271,7 +335,7 @@ pub trait ModelWithDocument<R: Model, C: ModelCollection> {
     }
 
     #[must_use = "After removing a key a model id may be returned that should be cleaned up"]
-    fn map_remove(&mut self, field: usize, key: i32) -> Option<usize> {
+    fn map_remove(&mut self, field: usize, key: &MapKey) -> Option<usize> {
         self.model()
             .get_mut()
             .remove_map_field(field, key)
@@ -371,6 +435,15 @@ impl<R: Model, C: ModelCollection> Document<R, C> {
         let model = item.model.get();
 
         let get_json_value = |desc: &FieldDescription, value: &dyn Any| -> Value {
+            if let Some(enumeration) = &desc.enumeration {
+                return match enumeration.get(value) {
+                    Some(value) => enumeration
+                        .name_of(value)
+                        .map_or_else(|| Value::Number(value.into()), |x| x.into()),
+                    None => Value::Null,
+                };
+            }
+
             match desc.field_type {
                 FieldType::Int8 => value
                     .downcast_ref::<i8>()
@@ -436,6 +509,26 @@ impl<R: Model, C: ModelCollection> Document<R, C> {
             }
         };
 
+        // Nested lists hold plain values, so their elements go through get_json_value as well
+        let get_json = |desc: &FieldDescription, value: &dyn Any| -> Value {
+            if !desc.nested {
+                return get_json_value(desc, value);
+            }
+
+            let element = FieldDescription {
+                nested: false,
+                ..*desc
+            };
+            desc.field_type
+                .list_items(value)
+                .map_or(Value::Null, |items| {
+                    items
+                        .into_iter()
+                        .map(|x| get_json_value(&element, x))
+                        .collect()
+                })
+        };
+
         for field in model.get_fields() {
             let field_value = model.get_field(field.index);
             let field_value = if let Some(field_value) = field_value {
@@ -446,26 +539,26 @@ impl<R: Model, C: ModelCollection> Document<R, C> {
             };
 
             let json_value = match field.value_type {
-                ValueType::Value => get_json_value(&field, field_value),
+                ValueType::Value => get_json(&field, field_value),
                 ValueType::Map { .. } => {
                     let mut object = Map::new();
                     for key in model.get_map_keys(field.index) {
                         let value = match field.field_type {
                             FieldType::Model | FieldType::TypeModel(_) => model
-                                .get_map_field_ref(field.index, key)
+                                .get_map_field_ref(field.index, &key)
                                 .and_then(|x| x.get())
                                 .map(|x| self.to_json(x))
                                 .unwrap_or(serde_json::Value::Null),
                             _ => model
-                                .get_map_field(field.index, key)
-                                .map_or(Value::Null, |x| get_json_value(&field, x)),
+                                .get_map_field(field.index, &key)
+                                .map_or(Value::Null, |x| get_json(&field, x)),
                         };
                         object.insert(key.to_string(), value);
                     }
 
                     Value::Object(object)
                 }
-                ValueType::List => {
+                ValueType::List | ValueType::Array { .. } => {
                     let mut list = vec![];
                     for i in 0..model.get_list_len(field.index) {
                         let value = match field.field_type {
@@ -474,7 +567,7 @@ impl<R: Model, C: ModelCollection> Document<R, C> {
                                 .get()
                                 .map(|x| self.to_json(x))
                                 .unwrap_or(serde_json::Value::Null),
-                            _ => get_json_value(&field, model.get_list_field(field.index, i)),
+                            _ => get_json(&field, model.get_list_field(field.index, i)),
                         };
                         list.push(value)
                     }
@@ -489,18 +582,142 @@ impl<R: Model, C: ModelCollection> Document<R, C> {
         Value::Object(map)
     }
 
+    /// Encodes the model with the given id and everything below it as a patch that recreates it
+    /// when applied to a freshly created model of the same type
+    pub fn to_patch(&self, id: usize) -> anyhow::Result<Bytes> {
+        let mut patch = BytesMut::new();
+        self.write_patch(id, &mut patch)?;
+        Ok(patch.freeze())
+    }
+
+    fn write_patch(&self, id: usize, patch: &mut BytesMut) -> anyhow::Result<()> {
+        let item = self
+            .by_id(id)
+            .with_context(|| format!("No model with id {}", id))?;
+        let model = item.model().get();
+
+        for field in model.get_fields() {
+            let is_model = matches!(field.field_type, FieldType::Model | FieldType::TypeModel(_));
+            match field.value_type {
+                ValueType::Value if is_model => {
+                    if let Some(child) = model.get_model_ref(field.index).and_then(|x| x.get()) {
+                        self.write_child_patch(
+                            PatchAction::PushCreateAndAssignField,
+                            field.index,
+                            None,
+                            child,
+                            patch,
+                        )?;
+                    }
+                }
+                ValueType::Value => {
+                    if let Some(value) = model.get_field(field.index) {
+                        patch.put_u8(PatchAction::AssignField as u8);
+                        patch.put_u8(field.index as u8);
+                        field.write_value(value, patch)?;
+                    }
+                }
+                ValueType::List | ValueType::Array { .. } => {
+                    let len = model.get_list_len(field.index);
+                    // Arrays are created with their length already
+                    if field.value_type == ValueType::List {
+                        patch.put_u8(PatchAction::Resize as u8);
+                        patch.put_u8(field.index as u8);
+                        patch.put_i32_le(len as i32);
+                    }
+
+                    for i in 0..len {
+                        if is_model {
+                            if let Some(child) = model.get_list_field_ref(field.index, i).get() {
+                                self.write_child_patch(
+                                    PatchAction::PushCreateAndAssignKey,
+                                    field.index,
+                                    Some((&FieldType::Int32, &MapKey::Int(i as i64))),
+                                    child,
+                                    patch,
+                                )?;
+                            }
+                        } else {
+                            patch.put_u8(PatchAction::AssignKey as u8);
+                            patch.put_u8(field.index as u8);
+                            patch.put_i32_le(i as i32);
+                            field.write_value(model.get_list_field(field.index, i), patch)?;
+                        }
+                    }
+                }
+                ValueType::Map { key: key_type } => {
+                    for key in model.get_map_keys(field.index) {
+                        if is_model {
+                            if let Some(child) = model
+                                .get_map_field_ref(field.index, &key)
+                                .and_then(|x| x.get())
+                            {
+                                self.write_child_patch(
+                                    PatchAction::PushCreateAndAssignKey,
+                                    field.index,
+                                    Some((&key_type, &key)),
+                                    child,
+                                    patch,
+                                )?;
+                            }
+                        } else if let Some(value) = model.get_map_field(field.index, &key) {
+                            patch.put_u8(PatchAction::AssignKey as u8);
+                            patch.put_u8(field.index as u8);
+                            key_type.write_key(&key, patch)?;
+                            field.write_value(value, patch)?;
+                        }
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn write_child_patch(
+        &self,
+        action: PatchAction,
+        field: usize,
+        key: Option<(&FieldType, &MapKey)>,
+        child: usize,
+        patch: &mut BytesMut,
+    ) -> anyhow::Result<()> {
+        let child_type = self
+            .by_id(child)
+            .with_context(|| format!("No model with id {}", child))?
+            .model()
+            .get()
+            .get_model_type();
+        patch.put_u8(action as u8);
+        patch.put_u8(field as u8);
+        patch.put_u8(child_type as u8);
+        if let Some((key_type, key)) = key {
+            key_type.write_key(key, patch)?;
+        }
+
+        self.write_patch(child, patch)?;
+        patch.put_u8(PatchAction::Pop as u8);
+        Ok(())
+    }
+
     pub fn remove(&self, id: usize) {
         self.get_mut().remove(id)
     }
 
-    pub fn root(&self) -> ModelBorrow<'_, R, C> {
-        self.by_id(0).unwrap()
+    /// Adds a model that isn't referenced by any field, the caller keeps track of its id
+    pub(crate) fn register(&self, model: Box<dyn ModelDescription>) -> usize {
+        self.get_mut().register(model)
+    }
+
+    pub fn root(&self) -> TypedBorrow<'_, R, R, C> {
+        self.by_id(0).and_then(|x| x.cast::<R>()).unwrap()
     }
 
     fn get(&self) -> &InnerDocument<R, C> {
         unsafe { &*self.0.get() }
     }
 
+    #[allow(clippy::mut_from_ref)]
     fn get_mut(&self) -> &mut InnerDocument<R, C> {
         unsafe { &mut *self.0.get() }
     }
@@ -575,7 +792,6 @@ impl<T> ItemStore<T> {
 
 #[derive(Debug)]
 struct InnerDocument<R: Model, C: ModelCollection> {
-    id: usize,
     models: ItemStore<ModelHolder>,
     root: ModelRef<R>,
     remove_queue: Vec<usize>,
@@ -585,7 +801,6 @@ struct InnerDocument<R: Model, C: ModelCollection> {
 impl<R: Model, C: ModelCollection> InnerDocument<R, C> {
     pub fn new() -> InnerDocument<R, C> {
         let mut inner = InnerDocument {
-            id: 0,
             models: Default::default(),
             root: ModelRef::empty(),
             remove_queue: vec![],
@@ -0,0 +1,145 @@
+use std::any::Any;
+use std::fmt::Debug;
+pub use uncage_model_proc_macro::ModelEnum;
+
+/// A numeric game code decoded into a Rust enum, usually derived with `#[derive(ModelEnum)]`.
+/// Codes without a variant are kept in the `Unknown` variant so nothing is lost
+pub trait ModelEnum: Copy + Debug + Any {
+    /// The integer the code is stored as, and what `Unknown` keeps. Fields have to be stored with
+    /// the same type, so an `i8` and a `u8` code decode differently
+    type Repr;
+
+    const NAME: &'static str;
+    const VARIANTS: &'static [(i64, &'static str)];
+
+    fn from_value(value: i64) -> Self;
+    fn value(&self) -> i64;
+
+    fn name(&self) -> Option<&'static str> {
+        let value = self.value();
+        Self::VARIANTS
+            .iter()
+            .find(|(x, _)| *x == value)
+            .map(|(_, name)| *name)
+    }
+}
+
+/// Type erased access to the [ModelEnum] of a field, so values can be read and written through
+/// `dyn Any` like any other field
+#[derive(Copy, Clone)]
+pub struct EnumDescription {
+    pub enum_name: &'static str,
+    pub variants: &'static [(i64, &'static str)],
+    get: fn(&dyn Any) -> Option<i64>,
+    set: fn(&mut dyn Any, i64) -> bool,
+}
+
+impl EnumDescription {
+    pub const fn of<T: ModelEnum>() -> EnumDescription {
+        EnumDescription {
+            enum_name: T::NAME,
+            variants: T::VARIANTS,
+            get: get_value::<T>,
+            set: set_value::<T>,
+        }
+    }
+
+    pub fn get(&self, value: &dyn Any) -> Option<i64> {
+        (self.get)(value)
+    }
+
+    pub fn set(&self, target: &mut dyn Any, value: i64) -> bool {
+        (self.set)(target, value)
+    }
+
+    pub fn name_of(&self, value: i64) -> Option<&'static str> {
+        self.variants
+            .iter()
+            .find(|(x, _)| *x == value)
+            .map(|(_, name)| *name)
+    }
+}
+
+impl Debug for EnumDescription {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("EnumDescription")
+            .field("enum_name", &self.enum_name)
+            .field("variants", &self.variants)
+            .finish()
+    }
+}
+
+fn get_value<T: ModelEnum>(value: &dyn Any) -> Option<i64> {
+    value.downcast_ref::<T>().map(|x| x.value())
+}
+
+fn set_value<T: ModelEnum>(target: &mut dyn Any, value: i64) -> bool {
+    if let Some(target) = target.downcast_mut::<T>() {
+        *target = T::from_value(value);
+        return true;
+    }
+
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::patcher::{PatchAction, Patcher};
+    use crate::{Document, Model, ModelCollection, ModelEnum};
+    use bytes::{Buf, BufMut, BytesMut};
+
+    #[derive(ModelEnum, Debug, Copy, Clone, Eq, PartialEq)]
+    enum Status {
+        #[uncage(value = -1)]
+        Disabled,
+        #[uncage(value = 2)]
+        Ready,
+        Unknown(i8),
+    }
+
+    #[derive(Debug, Default, Model)]
+    #[uncage(type = 0)]
+    struct Root {
+        #[uncage(index = 0, enum = "i8")]
+        status: Status,
+        #[uncage(index = 1, enum = "i8")]
+        history: Vec<Status>,
+    }
+
+    #[derive(Debug, ModelCollection)]
+    enum Models {
+        Root(Root),
+    }
+
+    #[test]
+    pub fn test_enum_fields() {
+        assert_eq!(Status::default(), Status::Unknown(0));
+        assert_eq!(Status::from_value(-1), Status::Disabled);
+        assert_eq!(Status::Ready.name(), Some("Ready"));
+
+        let mut patcher: Patcher<Root, Models> = Patcher::new(Document::new());
+        let mut patch = BytesMut::new();
+        patch.put_u8(PatchAction::AssignField as u8);
+        patch.put_u8(0);
+        patch.put_i8(2);
+        patch.put_u8(PatchAction::Insert as u8);
+        patch.put_u8(1);
+        patch.put_i32_le(0);
+        patch.put_i8(7);
+        patcher.apply_patch(patch.freeze()).unwrap();
+
+        let document = patcher.document();
+        let root = document.root();
+        assert_eq!(root.status, Status::Ready);
+        assert_eq!(root.history, vec![Status::Unknown(7)]);
+
+        let json = document.to_json(0);
+        assert_eq!(json["status"], "Ready");
+        assert_eq!(json["history"][0], 7);
+
+        let mut snapshot = document.to_patch(0).unwrap();
+        assert_eq!(snapshot.get_u8(), PatchAction::AssignField as u8);
+        assert_eq!(snapshot.get_u8(), 0);
+        assert_eq!(snapshot.get_i8(), 2);
+    }
+}
@@ -0,0 +1,393 @@
+use crate::schema::DynamicSchema;
+use crate::{FieldDescription, FieldType, Model, ModelCollection, ValueType};
+use serde_json::{json, Map, Value};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+/// Name of the base type every exported model extends, it holds the `model_type` and
+/// `model_name` that [crate::Document::to_json] adds to every object
+pub const BASE_MODEL: &str = "Model";
+
+#[derive(Debug, Clone)]
+pub struct ExportModel {
+    pub name: &'static str,
+    pub model_type: usize,
+    pub parent: Option<usize>,
+    /// Only the fields the model defines itself, inherited fields are found on the parent
+    pub fields: Vec<FieldDescription>,
+}
+
+/// Describes the shape of [crate::Document::to_json] output for a set of models, so it can be
+/// written out as JSON Schema or TypeScript definitions
+#[derive(Debug, Clone)]
+pub struct Export {
+    root: usize,
+    models: BTreeMap<usize, ExportModel>,
+}
+
+impl Export {
+    pub fn from_collection<R: Model, C: ModelCollection>() -> Export {
+        let models = C::model_types()
+            .into_iter()
+            .map(|model_type| {
+                let model = C::create_model(model_type).boxed();
+                let export = ExportModel {
+                    name: model.get_model_name(),
+                    model_type,
+                    parent: model.get_parent().map(|x| x.get_model_type()),
+                    fields: model
+                        .get_fields()
+                        .into_iter()
+                        .filter(|x| x.model_type == model_type)
+                        .collect(),
+                };
+
+                (model_type, export)
+            })
+            .collect();
+
+        Export {
+            root: R::model_type(),
+            models,
+        }
+    }
+
+    pub fn from_schema(schema: &DynamicSchema) -> Export {
+        let models = schema
+            .models()
+            .map(|model| {
+                let export = ExportModel {
+                    name: model.name(),
+                    model_type: model.model_type(),
+                    parent: model.parent(),
+                    fields: model
+                        .fields()
+                        .iter()
+                        .filter(|x| x.model_type == model.model_type())
+                        .copied()
+                        .collect(),
+                };
+
+                (model.model_type(), export)
+            })
+            .collect();
+
+        Export {
+            root: schema.root(),
+            models,
+        }
+    }
+
+    pub fn root(&self) -> Option<&ExportModel> {
+        self.models.get(&self.root)
+    }
+
+    pub fn models(&self) -> impl Iterator<Item = &ExportModel> {
+        self.models.values()
+    }
+
+    fn model_name(&self, model_type: usize) -> &str {
+        self.models.get(&model_type).map_or(BASE_MODEL, |x| x.name)
+    }
+
+    /// A JSON Schema (draft 2020-12) document with a definition per model, inheritance is
+    /// expressed with `allOf`
+    pub fn json_schema(&self) -> Value {
+        let mut definitions = Map::new();
+        definitions.insert(
+            BASE_MODEL.to_string(),
+            json!({
+                "type": "object",
+                "properties": {
+                    "model_type": { "type": "integer" },
+                    "model_name": { "type": "string" },
+                },
+                "required": ["model_type", "model_name"],
+            }),
+        );
+
+        for model in self.models.values() {
+            let mut properties = Map::new();
+            for field in &model.fields {
+                properties.insert(field.field_name.to_string(), self.json_field(field));
+            }
+
+            let parent = model.parent.map_or(BASE_MODEL, |x| self.model_name(x));
+            definitions.insert(
+                model.name.to_string(),
+                json!({
+                    "allOf": [
+                        { "$ref": format!("#/$defs/{}", parent) },
+                        {
+                            "type": "object",
+                            "properties": properties,
+                            "required": model.fields.iter().map(|x| x.field_name).collect::<Vec<_>>(),
+                        },
+                    ],
+                }),
+            );
+        }
+
+        json!({
+            "$schema": "https://json-schema.org/draft/2020-12/schema",
+            "$ref": format!("#/$defs/{}", self.model_name(self.root)),
+            "$defs": definitions,
+        })
+    }
+
+    fn json_field(&self, field: &FieldDescription) -> Value {
+        let value = match field.field_type {
+            // Enums are written by name, unknown values stay numbers
+            _ if field.enumeration.is_some() => {
+                let names = field
+                    .enumeration
+                    .iter()
+                    .flat_map(|x| x.variants)
+                    .map(|(_, name)| *name);
+                json!({ "anyOf": [{ "enum": names.collect::<Vec<_>>() }, { "type": "integer" }] })
+            }
+            FieldType::Int8
+            | FieldType::UInt8
+            | FieldType::Int16
+            | FieldType::UInt16
+            | FieldType::Int32
+            | FieldType::UInt32 => json!({ "type": "integer" }),
+            // Written as strings so they survive JavaScript numbers
+            FieldType::Int64 | FieldType::UInt64 | FieldType::Int128 | FieldType::UInt128 => {
+                json!({ "type": "string", "pattern": "^-?[0-9]+$" })
+            }
+            FieldType::Float | FieldType::Double => json!({ "type": "number" }),
+            FieldType::String => json!({ "type": "string" }),
+            FieldType::Boolean => json!({ "type": "boolean" }),
+            FieldType::Model => json!({
+                "anyOf": [{ "$ref": format!("#/$defs/{}", BASE_MODEL) }, { "type": "null" }],
+            }),
+            FieldType::TypeModel(model_type) => json!({
+                "anyOf": [
+                    { "$ref": format!("#/$defs/{}", self.model_name(model_type)) },
+                    { "type": "null" },
+                ],
+            }),
+        };
+
+        let value = match field.nested {
+            true => json!({ "type": "array", "items": value }),
+            false => value,
+        };
+
+        let mut value = match field.value_type {
+            ValueType::Value if field.optional => json!({ "anyOf": [value, { "type": "null" }] }),
+            ValueType::Value => value,
+            ValueType::List => json!({ "type": "array", "items": value }),
+            ValueType::Array { len } => json!({
+                "type": "array",
+                "items": value,
+                "minItems": len,
+                "maxItems": len,
+            }),
+            ValueType::Map {
+                key: FieldType::String,
+            } => json!({
+                "type": "object",
+                "additionalProperties": value,
+            }),
+            ValueType::Map { .. } => json!({
+                "type": "object",
+                "propertyNames": { "pattern": "^-?[0-9]+$" },
+                "additionalProperties": value,
+            }),
+        };
+
+        let metadata = &field.metadata;
+        let object = value.as_object_mut().unwrap();
+        if let Some(description) = metadata.description {
+            object.insert("description".to_string(), json!(description));
+        }
+
+        if metadata.deprecated {
+            object.insert("deprecated".to_string(), json!(true));
+        }
+
+        // Not part of JSON Schema, kept as extensions for tools that work from the schema
+        if let Some(unit) = metadata.unit {
+            object.insert("x-unit".to_string(), json!(unit));
+        }
+
+        if metadata.hidden_info {
+            object.insert("x-hidden-info".to_string(), json!(true));
+        }
+
+        if metadata.interpolatable {
+            object.insert("x-interpolatable".to_string(), json!(true));
+        }
+
+        value
+    }
+
+    /// TypeScript interfaces for every model, inheritance is expressed with `extends`
+    pub fn typescript(&self) -> String {
+        let mut output = String::new();
+        writeln!(output, "export interface {} {{", BASE_MODEL).unwrap();
+        writeln!(output, "  model_type: number;").unwrap();
+        writeln!(output, "  model_name: string;").unwrap();
+        writeln!(output, "}}").unwrap();
+
+        for model in self.models.values() {
+            let parent = model.parent.map_or(BASE_MODEL, |x| self.model_name(x));
+            writeln!(output).unwrap();
+            writeln!(
+                output,
+                "export interface {} extends {} {{",
+                model.name, parent
+            )
+            .unwrap();
+            for field in &model.fields {
+                write_typescript_comment(&mut output, field);
+                writeln!(
+                    output,
+                    "  {}: {};",
+                    field.field_name,
+                    self.typescript_field(field)
+                )
+                .unwrap();
+            }
+            writeln!(output, "}}").unwrap();
+        }
+
+        output
+    }
+
+    fn typescript_field(&self, field: &FieldDescription) -> String {
+        let value = match field.field_type {
+            _ if field.enumeration.is_some() => field
+                .enumeration
+                .iter()
+                .flat_map(|x| x.variants)
+                .map(|(_, name)| format!("\"{}\"", name))
+                .chain(std::iter::once("number".to_string()))
+                .collect::<Vec<_>>()
+                .join(" | "),
+            FieldType::Int8
+            | FieldType::UInt8
+            | FieldType::Int16
+            | FieldType::UInt16
+            | FieldType::Int32
+            | FieldType::UInt32
+            | FieldType::Float
+            | FieldType::Double => "number".to_string(),
+            FieldType::Int64
+            | FieldType::UInt64
+            | FieldType::Int128
+            | FieldType::UInt128
+            | FieldType::String => "string".to_string(),
+            FieldType::Boolean => "boolean".to_string(),
+            FieldType::Model => format!("{} | null", BASE_MODEL),
+            FieldType::TypeModel(model_type) => format!("{} | null", self.model_name(model_type)),
+        };
+
+        let list = |value: String| match value.contains('|') {
+            true => format!("({})[]", value),
+            false => format!("{}[]", value),
+        };
+
+        let value = match field.nested {
+            true => list(value),
+            false => value,
+        };
+
+        match field.value_type {
+            ValueType::Value if field.optional => format!("{} | null", value),
+            ValueType::Value => value,
+            ValueType::List | ValueType::Array { .. } => list(value),
+            ValueType::Map { .. } => format!("Record<string, {}>", value),
+        }
+    }
+}
+
+fn write_typescript_comment(output: &mut String, field: &FieldDescription) {
+    let metadata = &field.metadata;
+    let mut lines = vec![];
+    lines.extend(metadata.description.map(|x| x.to_string()));
+    lines.extend(metadata.unit.map(|x| format!("@unit {}", x)));
+    if metadata.deprecated {
+        lines.push("@deprecated".to_string());
+    }
+
+    if lines.is_empty() {
+        return;
+    }
+
+    writeln!(output, "  /**").unwrap();
+    for line in lines {
+        writeln!(output, "   * {}", line).unwrap();
+    }
+    writeln!(output, "   */").unwrap();
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::export::Export;
+    use crate::{Model, ModelCollection, ModelRef, ModelVec, Ref};
+    use std::collections::BTreeMap;
+
+    #[derive(Debug, Default, Model)]
+    #[uncage(type = 0)]
+    struct Root {
+        #[uncage(index = 0)]
+        items: ModelVec<Unit>,
+        #[uncage(index = 1)]
+        owner: ModelRef<Entity>,
+    }
+
+    #[derive(Debug, Default, Model)]
+    #[uncage(type = 1)]
+    struct Entity {
+        #[uncage(index = 0)]
+        id: u64,
+        #[uncage(index = 1)]
+        target: Ref,
+    }
+
+    #[derive(Debug, Default, Model)]
+    #[uncage(type = 2)]
+    struct Unit {
+        #[uncage(extends)]
+        parent: Entity,
+        #[uncage(
+            index = 2,
+            unit = "hp",
+            description = "Hit points per player",
+            deprecated
+        )]
+        hit_points: BTreeMap<i32, f32>,
+    }
+
+    #[derive(Debug, ModelCollection)]
+    enum Models {
+        Root(Root),
+        Entity(Entity),
+        Unit(Unit),
+    }
+
+    #[test]
+    pub fn test_export() {
+        let export = Export::from_collection::<Root, Models>();
+
+        let typescript = export.typescript();
+        assert!(typescript.contains("export interface Root extends Model {\n  items: (Unit | null)[];\n  owner: Entity | null;\n}"));
+        assert!(typescript.contains(
+            "export interface Unit extends Entity {\n  /**\n   * Hit points per player\n   * @unit hp\n   * @deprecated\n   */\n  hit_points: Record<string, number>;\n}"
+        ));
+        assert!(typescript.contains("  id: string;\n  target: Model | null;\n"));
+
+        let schema = export.json_schema();
+        assert_eq!(schema["$ref"], "#/$defs/Root");
+        let unit = &schema["$defs"]["Unit"]["allOf"];
+        assert_eq!(unit[0]["$ref"], "#/$defs/Entity");
+        let hit_points = &unit[1]["properties"]["hit_points"];
+        assert_eq!(hit_points["additionalProperties"]["type"], "number");
+        assert_eq!(hit_points["description"], "Hit points per player");
+        assert_eq!(hit_points["deprecated"], true);
+        assert_eq!(hit_points["x-unit"], "hp");
+    }
+}
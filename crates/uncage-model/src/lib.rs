@@ -1,12 +1,33 @@
+extern crate self as uncage_model;
+
 mod document;
+mod enums;
+pub mod export;
+pub mod migration;
 mod model;
+mod navigation;
+mod opaque;
 pub mod patcher;
 mod path;
 mod references;
+pub mod registry;
+pub mod schema;
 pub mod selector;
+#[cfg(feature = "serde")]
+mod serialize;
 
 pub use document::*;
+pub use enums::*;
 pub use model::*;
+pub use navigation::*;
+pub use opaque::OpaqueModel;
 pub use path::*;
 pub use references::*;
 pub use selector::Selector;
+#[cfg(feature = "serde")]
+pub use serialize::*;
+
+#[doc(hidden)]
+pub use anyhow;
+#[doc(hidden)]
+pub use bytes;
@@ -0,0 +1,868 @@
+use crate::patcher::PatchAction;
+use crate::schema::{DynamicModelSchema, DynamicSchema, DynamicValue};
+use crate::{Document, FieldDescription, FieldType, MapKey, Model, ModelCollection, ValueType};
+use anyhow::{bail, Context};
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use num_traits::FromPrimitive;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct MigrationRules {
+    #[serde(default)]
+    pub models: Vec<ModelRule>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ModelRule {
+    pub model: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub to: Option<String>,
+    #[serde(default)]
+    pub drop: bool,
+    #[serde(default)]
+    pub fields: Vec<FieldRule>,
+    #[serde(default)]
+    pub defaults: Vec<DefaultRule>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FieldRule {
+    pub field: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub to: Option<String>,
+    #[serde(default)]
+    pub drop: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scale: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub offset: Option<f64>,
+    /// Pairs of old and new values, used to convert enum like fields
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub values: Vec<(i64, i64)>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DefaultRule {
+    pub field: String,
+    pub value: serde_json::Value,
+}
+
+impl MigrationRules {
+    pub fn load<P: AsRef<std::path::Path>>(path: P) -> anyhow::Result<MigrationRules> {
+        let path = path.as_ref();
+        let input = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read migration rules {}", path.display()))?;
+
+        match path.extension().and_then(|x| x.to_str()) {
+            Some("toml") => toml::from_str(&input).context("Failed to parse TOML migration rules"),
+            _ => serde_json::from_str(&input).context("Failed to parse JSON migration rules"),
+        }
+    }
+
+    fn model(&self, name: &str) -> Option<&ModelRule> {
+        self.models.iter().find(|x| x.model == name)
+    }
+}
+
+#[derive(Debug)]
+struct Conversion {
+    scale: Option<f64>,
+    offset: Option<f64>,
+    values: HashMap<i128, i128>,
+}
+
+impl Conversion {
+    fn apply(&self, value: DynamicValue, target: &FieldType) -> Option<DynamicValue> {
+        let value = match value.as_i128().and_then(|x| self.values.get(&x)) {
+            Some(mapped) => DynamicValue::Int128(*mapped),
+            None => value,
+        };
+
+        if self.scale.is_none() && self.offset.is_none() {
+            return value.cast(target);
+        }
+
+        let value = value.as_f64()? * self.scale.unwrap_or(1.0) + self.offset.unwrap_or(0.0);
+        DynamicValue::from_f64(target, value)
+    }
+}
+
+#[derive(Debug)]
+struct FieldMapping {
+    target: &'static FieldDescription,
+    conversion: Conversion,
+}
+
+#[derive(Debug)]
+struct ModelMapping {
+    target: Option<usize>,
+    fields: HashMap<usize, FieldMapping>,
+    defaults: Vec<(usize, DynamicValue)>,
+}
+
+/// Converts patches made against one schema into patches for another, fields and models are
+/// matched by name unless a rule says otherwise
+#[derive(Debug)]
+pub struct Migration {
+    source: &'static DynamicSchema,
+    target: &'static DynamicSchema,
+    models: HashMap<usize, ModelMapping>,
+}
+
+impl Migration {
+    pub fn new(
+        source: &'static DynamicSchema,
+        target: &'static DynamicSchema,
+        rules: &MigrationRules,
+    ) -> anyhow::Result<Migration> {
+        let mut models = HashMap::new();
+        for model in source.models() {
+            let mapping = Migration::map_model(source, target, rules, model)
+                .with_context(|| format!("Failed to migrate model {}", model.name()))?;
+            models.insert(model.model_type(), mapping);
+        }
+
+        if models.get(&source.root()).and_then(|x| x.target) != Some(target.root()) {
+            bail!("The source root model doesn't migrate to the target root model");
+        }
+
+        Ok(Migration {
+            source,
+            target,
+            models,
+        })
+    }
+
+    /// Migrates into a collection of Rust models, the rewritten patches apply to a
+    /// `Patcher<R, C>`. This is how recordings of older builds are read with the current models
+    pub fn to_models<R: Model, C: ModelCollection + 'static>(
+        source: &'static DynamicSchema,
+        rules: &MigrationRules,
+    ) -> anyhow::Result<Migration> {
+        Migration::new(source, DynamicSchema::from_collection::<R, C>(), rules)
+    }
+
+    fn map_model(
+        source: &'static DynamicSchema,
+        target: &'static DynamicSchema,
+        rules: &MigrationRules,
+        model: &'static DynamicModelSchema,
+    ) -> anyhow::Result<ModelMapping> {
+        let rule = rules.model(model.name());
+        let target_model = match rule {
+            Some(rule) if rule.drop => None,
+            Some(ModelRule { to: Some(to), .. }) => Some(
+                target
+                    .model_by_name(to)
+                    .with_context(|| format!("Unknown target model {}", to))?,
+            ),
+            _ => target.model_by_name(model.name()),
+        };
+
+        let target_model = match target_model {
+            Some(x) => x,
+            None => {
+                return Ok(ModelMapping {
+                    target: None,
+                    fields: HashMap::new(),
+                    defaults: vec![],
+                })
+            }
+        };
+
+        let mut fields = HashMap::new();
+        for field in model.fields() {
+            // Inherited fields are configured on the model that declares them
+            let field_rule = rules
+                .model(field.model_name)
+                .and_then(|x| x.fields.iter().find(|x| x.field == field.field_name));
+            if field_rule.is_some_and(|x| x.drop) {
+                continue;
+            }
+
+            let target_name = field_rule
+                .and_then(|x| x.to.as_deref())
+                .unwrap_or(field.field_name);
+            let target_field = match target_model.field_by_name(target_name) {
+                Some(x) => x,
+                None if field_rule.and_then(|x| x.to.as_ref()).is_some() => {
+                    bail!(
+                        "Unknown target field {}.{}",
+                        target_model.name(),
+                        target_name
+                    )
+                }
+                None => continue,
+            };
+
+            if !same_container(&field.value_type, &target_field.value_type) || target_field.nested {
+                bail!(
+                    "Field {} can't be migrated to {} since the container differs",
+                    field.field_name,
+                    target_field.field_name
+                );
+            }
+
+            fields.insert(
+                field.index,
+                FieldMapping {
+                    target: target_field,
+                    conversion: Conversion {
+                        scale: field_rule.and_then(|x| x.scale),
+                        offset: field_rule.and_then(|x| x.offset),
+                        values: field_rule
+                            .map(|x| {
+                                x.values
+                                    .iter()
+                                    .map(|(from, to)| (*from as i128, *to as i128))
+                                    .collect()
+                            })
+                            .unwrap_or_default(),
+                    },
+                },
+            );
+        }
+
+        let mut defaults = vec![];
+        let mut current = Some(model);
+        while let Some(model) = current {
+            for default in rules.model(model.name()).map_or(&[][..], |x| &x.defaults) {
+                let field = target_model
+                    .field_by_name(&default.field)
+                    .with_context(|| {
+                        format!(
+                            "Unknown target field {}.{}",
+                            target_model.name(),
+                            default.field
+                        )
+                    })?;
+                let value = DynamicValue::from_json(&field.field_type, &default.value)
+                    .with_context(|| format!("Invalid default for {}", default.field))?;
+                defaults.push((field.index, value));
+            }
+
+            current = model.parent().and_then(|x| source.model(x));
+        }
+
+        Ok(ModelMapping {
+            target: Some(target_model.model_type()),
+            fields,
+            defaults,
+        })
+    }
+
+    pub fn source(&self) -> &'static DynamicSchema {
+        self.source
+    }
+
+    pub fn target(&self) -> &'static DynamicSchema {
+        self.target
+    }
+
+    pub fn rewriter(&self) -> PatchRewriter<'_> {
+        PatchRewriter::new(self)
+    }
+}
+
+fn same_container(lhs: &ValueType, rhs: &ValueType) -> bool {
+    match (lhs, rhs) {
+        (ValueType::Array { len: lhs }, ValueType::Array { len: rhs }) => lhs == rhs,
+        // Integer keys can change their width, but not turn into strings
+        (ValueType::Map { key: lhs }, ValueType::Map { key: rhs }) => {
+            (*lhs == FieldType::String) == (*rhs == FieldType::String)
+        }
+        _ => matches!(
+            (lhs, rhs),
+            (ValueType::Value, ValueType::Value) | (ValueType::List, ValueType::List)
+        ),
+    }
+}
+
+/// Lists are addressed with `i32` indexes, maps with their own key type
+fn key_type(field: &FieldDescription) -> FieldType {
+    match field.value_type {
+        ValueType::Map { key } => key,
+        _ => FieldType::Int32,
+    }
+}
+
+fn list_index(key: &MapKey) -> anyhow::Result<usize> {
+    key.index()
+        .with_context(|| format!("{} is not a valid list index", key))
+}
+
+fn is_model(field_type: &FieldType) -> bool {
+    matches!(field_type, FieldType::Model | FieldType::TypeModel(_))
+}
+
+#[derive(Debug, Default)]
+struct Node {
+    model_type: usize,
+    fields: HashMap<usize, usize>,
+    maps: HashMap<usize, BTreeMap<MapKey, usize>>,
+    lists: HashMap<usize, Vec<Option<usize>>>,
+}
+
+#[derive(Debug, Copy, Clone)]
+struct Frame {
+    node: usize,
+    emitted: bool,
+}
+
+/// Keeps track of the shape of the source document, which is needed to know the model type of
+/// every object a patch pushes
+#[derive(Debug)]
+pub struct PatchRewriter<'m> {
+    migration: &'m Migration,
+    nodes: Vec<Option<Node>>,
+    free: Vec<usize>,
+    stack: Vec<Frame>,
+}
+
+impl<'m> PatchRewriter<'m> {
+    fn new(migration: &'m Migration) -> PatchRewriter<'m> {
+        let mut rewriter = PatchRewriter {
+            migration,
+            nodes: vec![],
+            free: vec![],
+            stack: vec![],
+        };
+
+        rewriter.reset();
+        rewriter
+    }
+
+    pub fn reset(&mut self) {
+        self.nodes.clear();
+        self.free.clear();
+        self.stack.clear();
+        let root = self.migration.source.root();
+        self.create(root);
+    }
+
+    /// Rewrites a full copy of the given source document and continues from its state
+    pub fn snapshot<R: Model, C: ModelCollection>(
+        &mut self,
+        document: &Document<R, C>,
+    ) -> anyhow::Result<Bytes> {
+        self.reset();
+        let root = document.root();
+        let patch = document.to_patch(crate::ModelWithDocument::object(&root))?;
+        self.rewrite(patch)
+    }
+
+    pub fn rewrite(&mut self, mut patch: Bytes) -> anyhow::Result<Bytes> {
+        let mut output = BytesMut::with_capacity(patch.len());
+        while patch.has_remaining() {
+            let action = patch.get_u8();
+            let action = PatchAction::from_u8(action)
+                .with_context(|| format!("{} is not a valid patch action", action))?;
+            self.rewrite_action(action, &mut patch, &mut output)
+                .with_context(|| format!("Failed to rewrite {:?}", action))?;
+        }
+
+        Ok(output.freeze())
+    }
+
+    fn rewrite_action(
+        &mut self,
+        action: PatchAction,
+        patch: &mut Bytes,
+        output: &mut BytesMut,
+    ) -> anyhow::Result<()> {
+        if action == PatchAction::Pop {
+            if let Some(frame) = self.stack.pop() {
+                if frame.emitted {
+                    output.put_u8(PatchAction::Pop as u8);
+                }
+            }
+
+            return Ok(());
+        }
+
+        let migration = self.migration;
+        let top = self.top();
+        let node = top.node;
+        let field = patch.get_u8() as usize;
+        let model_type = self.node(node).model_type;
+        let source = migration
+            .source
+            .model(model_type)
+            .with_context(|| format!("Unknown source model {}", model_type))?
+            .field(field)
+            .with_context(|| format!("No field {} on source model {}", field, model_type))?;
+        let mapping = if top.emitted {
+            migration.models[&model_type].fields.get(&field)
+        } else {
+            None
+        };
+
+        let source_key = key_type(source);
+        let emit = |output: &mut BytesMut, keys: &[&MapKey]| -> anyhow::Result<()> {
+            if let Some(mapping) = mapping {
+                output.put_u8(action as u8);
+                output.put_u8(mapping.target.index as u8);
+                for key in keys {
+                    key_type(mapping.target).write_key(key, output)?;
+                }
+            }
+
+            Ok(())
+        };
+
+        match action {
+            PatchAction::Pop => unreachable!(),
+            PatchAction::AssignField => {
+                let value = DynamicValue::read(&source.field_type, patch)?;
+                emit(output, &[])?;
+                self.write_value(mapping, value, output)?;
+            }
+            PatchAction::PushField => {
+                let child = *self
+                    .node(node)
+                    .fields
+                    .get(&field)
+                    .context("No model to push")?;
+                emit(output, &[])?;
+                self.push(child, mapping.is_some());
+            }
+            PatchAction::PushCreateAndAssignField => {
+                let child_type = patch.get_u8() as usize;
+                let child = self.create(child_type);
+                if let Some(old) = self.node_mut(node).fields.insert(field, child) {
+                    self.remove(old);
+                }
+
+                self.push_created(action, child, mapping, None, output)?;
+            }
+            PatchAction::ResetField => {
+                if let Some(old) = self.node_mut(node).fields.remove(&field) {
+                    self.remove(old);
+                }
+
+                emit(output, &[])?;
+            }
+            PatchAction::AssignKey | PatchAction::Insert => {
+                let key = source_key.read_key(patch)?;
+                let value = DynamicValue::read(&source.field_type, patch)?;
+                if action == PatchAction::Insert && is_model(&source.field_type) {
+                    self.list_mut(node, field).insert(list_index(&key)?, None);
+                }
+
+                emit(output, &[&key])?;
+                self.write_value(mapping, value, output)?;
+            }
+            PatchAction::PushKey => {
+                let key = source_key.read_key(patch)?;
+                let child = match source.value_type {
+                    ValueType::Map { .. } => self
+                        .node(node)
+                        .maps
+                        .get(&field)
+                        .and_then(|x| x.get(&key))
+                        .copied(),
+                    _ => self
+                        .node(node)
+                        .lists
+                        .get(&field)
+                        .and_then(|x| x.get(key.index()?).copied().flatten()),
+                };
+
+                let child = child.context("No model to push")?;
+                emit(output, &[&key])?;
+                self.push(child, mapping.is_some());
+            }
+            PatchAction::PushCreateAndAssignKey | PatchAction::PushCreateAndInsert => {
+                let child_type = patch.get_u8() as usize;
+                let key = source_key.read_key(patch)?;
+                let child = self.create(child_type);
+                let old = match source.value_type {
+                    ValueType::Map { .. } => self
+                        .node_mut(node)
+                        .maps
+                        .entry(field)
+                        .or_default()
+                        .insert(key.clone(), child),
+                    _ => {
+                        let index = list_index(&key)?;
+                        let list = self.list_mut(node, field);
+                        if action == PatchAction::PushCreateAndInsert {
+                            list.insert(index, Some(child));
+                            None
+                        } else {
+                            if list.len() <= index {
+                                list.resize(index + 1, None);
+                            }
+
+                            list[index].replace(child)
+                        }
+                    }
+                };
+
+                if let Some(old) = old {
+                    self.remove(old);
+                }
+
+                self.push_created(action, child, mapping, Some(&key), output)?;
+            }
+            PatchAction::ResetKey | PatchAction::Remove => {
+                let key = source_key.read_key(patch)?;
+                let old = match source.value_type {
+                    ValueType::Map { .. } => self
+                        .node_mut(node)
+                        .maps
+                        .get_mut(&field)
+                        .and_then(|x| x.remove(&key)),
+                    _ if !is_model(&source.field_type) => None,
+                    _ => {
+                        let index = list_index(&key)?;
+                        let list = self.list_mut(node, field);
+                        if action == PatchAction::Remove {
+                            (list.len() > index).then(|| list.remove(index)).flatten()
+                        } else {
+                            list.get_mut(index).and_then(|x| x.take())
+                        }
+                    }
+                };
+
+                if let Some(old) = old {
+                    self.remove(old);
+                }
+
+                emit(output, &[&key])?;
+            }
+            PatchAction::Swap => {
+                let lhs = source_key.read_key(patch)?;
+                let rhs = source_key.read_key(patch)?;
+                match source.value_type {
+                    ValueType::Map { .. } => {
+                        if let Some(map) = self.node_mut(node).maps.get_mut(&field) {
+                            let old_lhs = map.remove(&lhs);
+                            let old_rhs = map.remove(&rhs);
+                            if let Some(x) = old_lhs {
+                                map.insert(rhs.clone(), x);
+                            }
+
+                            if let Some(x) = old_rhs {
+                                map.insert(lhs.clone(), x);
+                            }
+                        }
+                    }
+                    _ if is_model(&source.field_type) => self
+                        .list_mut(node, field)
+                        .swap(list_index(&lhs)?, list_index(&rhs)?),
+                    _ => {}
+                }
+
+                emit(output, &[&lhs, &rhs])?;
+            }
+            PatchAction::Resize => {
+                let len = patch.get_i32_le();
+                if is_model(&source.field_type) {
+                    let autofill = match (source.autofill, source.field_type) {
+                        (true, FieldType::TypeModel(x)) => Some(x),
+                        _ => None,
+                    };
+
+                    let list = self.list_mut(node, field);
+                    let removed = if list.len() > len as usize {
+                        list.split_off(len as usize)
+                    } else {
+                        vec![]
+                    };
+
+                    for old in removed.into_iter().flatten() {
+                        self.remove(old);
+                    }
+
+                    while self.list_mut(node, field).len() < len as usize {
+                        let child = autofill.map(|x| self.create(x));
+                        self.list_mut(node, field).push(child);
+                    }
+                }
+
+                // The new length is written like a list index
+                emit(output, &[&MapKey::Int(len as i64)])?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn write_value(
+        &self,
+        mapping: Option<&FieldMapping>,
+        value: DynamicValue,
+        output: &mut BytesMut,
+    ) -> anyhow::Result<()> {
+        if let Some(mapping) = mapping {
+            let target_type = &mapping.target.field_type;
+            mapping
+                .conversion
+                .apply(value, target_type)
+                .with_context(|| {
+                    format!(
+                        "Can't convert value for {} to {:?}",
+                        mapping.target.field_name, target_type
+                    )
+                })?
+                .write(output)?;
+        }
+
+        Ok(())
+    }
+
+    fn push_created(
+        &mut self,
+        action: PatchAction,
+        child: usize,
+        mapping: Option<&FieldMapping>,
+        key: Option<&MapKey>,
+        output: &mut BytesMut,
+    ) -> anyhow::Result<()> {
+        let child_type = self.node(child).model_type;
+        let child_mapping = self
+            .migration
+            .models
+            .get(&child_type)
+            .with_context(|| format!("Unknown source model {}", child_type))?;
+        let target_type = match (mapping, child_mapping.target) {
+            (Some(mapping), Some(target_type)) => {
+                output.put_u8(action as u8);
+                output.put_u8(mapping.target.index as u8);
+                output.put_u8(target_type as u8);
+                if let Some(key) = key {
+                    key_type(mapping.target).write_key(key, output)?;
+                }
+
+                Some(target_type)
+            }
+            _ => None,
+        };
+
+        if target_type.is_some() {
+            for (field, value) in &child_mapping.defaults {
+                output.put_u8(PatchAction::AssignField as u8);
+                output.put_u8(*field as u8);
+                value.write(output)?;
+            }
+        }
+
+        self.push(child, target_type.is_some());
+        Ok(())
+    }
+
+    fn top(&self) -> Frame {
+        self.stack.last().copied().unwrap_or(Frame {
+            node: 0,
+            emitted: true,
+        })
+    }
+
+    fn push(&mut self, node: usize, emitted: bool) {
+        let emitted = emitted && self.top().emitted;
+        let child_type = self.node(node).model_type;
+        self.stack.push(Frame {
+            node,
+            emitted: emitted
+                && self
+                    .migration
+                    .models
+                    .get(&child_type)
+                    .is_some_and(|x| x.target.is_some()),
+        })
+    }
+
+    fn node(&self, id: usize) -> &Node {
+        self.nodes[id].as_ref().unwrap()
+    }
+
+    fn node_mut(&mut self, id: usize) -> &mut Node {
+        self.nodes[id].as_mut().unwrap()
+    }
+
+    fn list_mut(&mut self, id: usize, field: usize) -> &mut Vec<Option<usize>> {
+        self.node_mut(id).lists.entry(field).or_default()
+    }
+
+    fn create(&mut self, model_type: usize) -> usize {
+        let node = Node {
+            model_type,
+            ..Default::default()
+        };
+
+        if let Some(id) = self.free.pop() {
+            self.nodes[id] = Some(node);
+            id
+        } else {
+            self.nodes.push(Some(node));
+            self.nodes.len() - 1
+        }
+    }
+
+    fn remove(&mut self, id: usize) {
+        let node = match self.nodes[id].take() {
+            Some(x) => x,
+            None => return,
+        };
+
+        self.free.push(id);
+        let children = node
+            .fields
+            .into_values()
+            .chain(node.maps.into_values().flat_map(|x| x.into_values()))
+            .chain(node.lists.into_values().flatten().flatten())
+            .collect::<Vec<_>>();
+        for child in children {
+            self.remove(child);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::migration::{Migration, MigrationRules};
+    use crate::patcher::Patcher;
+    use crate::schema::{DynamicModel, DynamicModels, Schema};
+    use crate::{Document, Model, ModelCollection, ModelVec};
+    use bytes::{BufMut, BytesMut};
+
+    const OLD: &str = r#"{
+        "root": "Root",
+        "models": [
+            { "name": "Root", "type": 0, "fields": [
+                { "index": 0, "name": "players", "type": "Player", "container": "list" }
+            ] },
+            { "name": "Player", "type": 1, "fields": [
+                { "index": 0, "name": "food_amount", "type": "i32" },
+                { "index": 1, "name": "speed", "type": "i32" },
+                { "index": 2, "name": "civ", "type": "u8" },
+                { "index": 3, "name": "debug", "type": "string" }
+            ] }
+        ]
+    }"#;
+
+    const NEW: &str = r#"{
+        "root": "Root",
+        "models": [
+            { "name": "Root", "type": 0, "fields": [
+                { "index": 0, "name": "players", "type": "Player", "container": "list" }
+            ] },
+            { "name": "Player", "type": 3, "fields": [
+                { "index": 0, "name": "civ", "type": "i32" },
+                { "index": 1, "name": "food", "type": "f32" },
+                { "index": 2, "name": "speed", "type": "f32" },
+                { "index": 3, "name": "ready", "type": "bool" }
+            ] }
+        ]
+    }"#;
+
+    const RULES: &str = r#"{
+        "models": [
+            { "model": "Player",
+              "fields": [
+                  { "field": "food_amount", "to": "food" },
+                  { "field": "speed", "scale": 0.5 },
+                  { "field": "civ", "values": [[1, 10], [2, 20]] },
+                  { "field": "debug", "drop": true }
+              ],
+              "defaults": [{ "field": "ready", "value": true }] }
+        ]
+    }"#;
+
+    #[derive(Debug, Default, Model)]
+    #[uncage(type = 0)]
+    struct Root {
+        #[uncage(index = 0)]
+        players: ModelVec<Player>,
+    }
+
+    /// `Player` of the new schema
+    #[derive(Debug, Default, Model)]
+    #[uncage(type = 3)]
+    struct Player {
+        #[uncage(index = 0)]
+        civ: i32,
+        #[uncage(index = 1)]
+        food: f32,
+        #[uncage(index = 2)]
+        speed: f32,
+        #[uncage(index = 3)]
+        ready: bool,
+    }
+
+    #[derive(Debug, ModelCollection)]
+    enum Models {
+        Root(Root),
+        Player(Player),
+    }
+
+    #[test]
+    pub fn test_patches_and_snapshots_migrate() {
+        let old = Schema::from_json(OLD).unwrap().compile().unwrap();
+        let new = Schema::from_json(NEW).unwrap().compile().unwrap();
+        let rules: MigrationRules = serde_json::from_str(RULES).unwrap();
+        let migration = Migration::new(old, new, &rules).unwrap();
+
+        let mut patch = BytesMut::new();
+        // resize: players to 1 entry
+        patch.put_slice(&[14, 0]);
+        patch.put_i32_le(1);
+        // push, create and assign key: players[0] = Player
+        patch.put_slice(&[8, 0, 1]);
+        patch.put_i32_le(0);
+        patch.put_slice(&[2, 0]);
+        patch.put_i32_le(200);
+        patch.put_slice(&[2, 1]);
+        patch.put_i32_le(3);
+        patch.put_slice(&[2, 2, 2]);
+        patch.put_slice(&[2, 3]);
+        patch.put_i32_le(2);
+        patch.put_slice(b"hi");
+        patch.put_u8(1);
+        let patch = patch.freeze();
+
+        let expected = serde_json::json!({
+            "model_type": 3,
+            "model_name": "Player",
+            "civ": 20,
+            "food": 200.0,
+            "speed": 1.5,
+            "ready": true,
+        });
+
+        let mut rewriter = migration.rewriter();
+        let migrated = rewriter.rewrite(patch.clone()).unwrap();
+        let guard = new.enter();
+        let mut patcher: Patcher<DynamicModel, DynamicModels> = Patcher::new(Document::new());
+        patcher.apply_patch(migrated).unwrap();
+        let player = patcher.document().to_json(0)["players"][0].clone();
+        assert_eq!(player, expected);
+        drop(guard);
+
+        let guard = old.enter();
+        let mut source: Patcher<DynamicModel, DynamicModels> = Patcher::new(Document::new());
+        source.apply_patch(patch.clone()).unwrap();
+        drop(guard);
+
+        let snapshot = migration.rewriter().snapshot(source.document()).unwrap();
+        let guard = new.enter();
+        let mut patcher: Patcher<DynamicModel, DynamicModels> = Patcher::new(Document::new());
+        patcher.apply_patch(snapshot).unwrap();
+        let player = patcher.document().to_json(0)["players"][0].clone();
+        assert_eq!(player, expected);
+        drop(guard);
+
+        let migration = Migration::to_models::<Root, Models>(old, &rules).unwrap();
+        let migrated = migration.rewriter().rewrite(patch).unwrap();
+        let mut patcher: Patcher<Root, Models> = Patcher::new(Document::new());
+        patcher.apply_patch(migrated).unwrap();
+        let root = patcher.document().root();
+        let player = root.players().get(0).unwrap();
+        assert_eq!(
+            (player.civ, player.food, player.speed, player.ready),
+            (20, 200.0, 1.5, true)
+        );
+    }
+}
@@ -1,13 +1,15 @@
-use crate::Ref;
+use crate::{EnumDescription, Ref};
 use anyhow::Context;
-use bytes::Buf;
+use bytes::{Buf, BufMut};
 use std::any::Any;
-use std::fmt::Debug;
-pub use uncage_model_proc_macro::Model as ModelProc;
+use std::fmt::{Debug, Display, Formatter};
+pub use uncage_model_proc_macro::{Model, ModelCollection};
 
 pub trait ModelCollection: Debug {
     fn create_model(id: usize) -> Self;
     fn has_model(id: usize) -> bool;
+    /// Every model type in the collection, in declaration order
+    fn model_types() -> Vec<usize>;
     fn boxed(self) -> Box<dyn ModelDescription>;
 }
 
@@ -27,15 +29,18 @@ pub trait ModelDescription: Debug + Any + 'static {
     fn get_model_ref(&self, field: usize) -> Option<&Ref>;
     fn get_model_ref_mut(&mut self, field: usize) -> Option<&mut Ref>;
 
-    fn get_map_keys(&self, field: usize) -> Vec<i32>;
-    fn create_map_field(&mut self, field: usize, key: i32) -> Option<&mut dyn Any>;
-    fn create_map_field_ref(&mut self, field: usize, key: i32) -> Option<&mut Ref>;
-    fn get_map_field_ref(&self, field: usize, key: i32) -> Option<&Ref>;
-    fn get_map_field_ref_mut(&mut self, field: usize, key: i32) -> Option<&mut Ref>;
-    fn get_map_field(&self, field: usize, key: i32) -> Option<&dyn Any>;
-    fn get_map_field_mut(&mut self, field: usize, key: i32) -> Option<&mut dyn Any>;
-    fn remove_map_field(&mut self, field: usize, key: i32) -> Option<Ref>;
-    fn swap_map_field(&mut self, field: usize, lhs: i32, rhs: i32);
+    fn get_map_keys(&self, field: usize) -> Vec<MapKey>;
+    fn create_map_field(&mut self, field: usize, key: &MapKey) -> Option<&mut dyn Any>;
+    fn create_map_field_ref(&mut self, field: usize, key: &MapKey) -> Option<&mut Ref>;
+    fn get_map_field_ref(&self, field: usize, key: &MapKey) -> Option<&Ref>;
+    fn get_map_field_ref_mut(&mut self, field: usize, key: &MapKey) -> Option<&mut Ref>;
+    fn get_map_field(&self, field: usize, key: &MapKey) -> Option<&dyn Any>;
+    fn get_map_field_mut(&mut self, field: usize, key: &MapKey) -> Option<&mut dyn Any>;
+    fn remove_map_field(&mut self, field: usize, key: &MapKey) -> Option<Ref>;
+    fn swap_map_field(&mut self, field: usize, lhs: &MapKey, rhs: &MapKey) -> anyhow::Result<()>;
+
+    /// Sets an `Option` field back to `None`
+    fn clear_field(&mut self, field: usize);
 
     fn get_list_len(&self, field: usize) -> usize;
     fn resize_list_field(&mut self, field: usize, new_len: usize);
@@ -83,8 +88,14 @@ pub trait ModelExt: Sized {
 #[derive(Copy, Clone, Debug, Eq, PartialEq)]
 pub enum ValueType {
     Value,
-    Map { key: FieldType },
+    Map {
+        key: FieldType,
+    },
     List,
+    /// A list with a fixed length, elements can be assigned and swapped but not inserted or removed
+    Array {
+        len: usize,
+    },
 }
 
 #[derive(Copy, Clone, Debug)]
@@ -96,6 +107,38 @@ pub struct FieldDescription {
     pub model_name: &'static str,
     pub field_name: &'static str,
     pub autofill: bool,
+    /// Set when the integer on the wire is decoded into a [crate::ModelEnum]
+    pub enumeration: Option<EnumDescription>,
+    /// The value is an `Option`, assigning sets it and resetting the field clears it again
+    pub optional: bool,
+    /// Every element is a list itself, written as its length followed by the values
+    pub nested: bool,
+    pub metadata: FieldMetadata,
+}
+
+/// Optional information about a field given with `#[uncage(unit = "seconds", hidden_info, ...)]`,
+/// it doesn't change how the field is decoded
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct FieldMetadata {
+    /// Unit of the value, e.g. `seconds` or `tiles`
+    pub unit: Option<&'static str>,
+    pub description: Option<&'static str>,
+    /// The game doesn't send the field anymore or it was replaced by another one
+    pub deprecated: bool,
+    /// The field reveals information a player can't see, e.g. behind the fog of war
+    pub hidden_info: bool,
+    /// Values can be interpolated between two patches when playing back smoothly
+    pub interpolatable: bool,
+}
+
+impl FieldMetadata {
+    pub const NONE: FieldMetadata = FieldMetadata {
+        unit: None,
+        description: None,
+        deprecated: false,
+        hidden_info: false,
+        interpolatable: false,
+    };
 }
 
 pub trait Fields {
@@ -113,13 +156,38 @@ impl FieldDescription {
     }
 
     pub fn assign_value<B: Buf>(&self, target: &mut dyn Any, from: &mut B) -> anyhow::Result<()> {
-        self.field_type.read(target, from).with_context(|| {
+        let result = match &self.enumeration {
+            _ if self.nested => self.field_type.read_list(target, from),
+            Some(enumeration) => self.field_type.read_integer(from).and_then(|value| {
+                if !enumeration.set(target, value) {
+                    anyhow::bail!("Field is not a {}", enumeration.enum_name);
+                }
+
+                Ok(())
+            }),
+            None => self.field_type.read(target, from),
+        };
+
+        result.with_context(|| {
             format!(
                 "Can't assign field {} (index {}) on model {} (type {})",
                 self.field_name, self.index, self.model_name, self.model_type
             )
         })
     }
+
+    pub fn write_value<B: BufMut>(&self, value: &dyn Any, to: &mut B) -> anyhow::Result<()> {
+        match &self.enumeration {
+            _ if self.nested => self.field_type.write_list(value, to),
+            Some(enumeration) => {
+                let value = enumeration
+                    .get(value)
+                    .with_context(|| format!("Field is not a {}", enumeration.enum_name))?;
+                self.field_type.write_integer(value, to)
+            }
+            None => self.field_type.write(value, to),
+        }
+    }
 }
 
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
@@ -265,6 +333,324 @@ impl FieldType {
 
         anyhow::bail!("No way to read field type {:?}", self)
     }
+
+    /// Reads a `Vec` of this field type, prefixed with its length
+    pub fn read_list<B: Buf>(&self, target: &mut dyn Any, from: &mut B) -> anyhow::Result<()> {
+        fn read_items<T: Default + Any, B: Buf>(
+            field_type: &FieldType,
+            target: &mut dyn Any,
+            from: &mut B,
+        ) -> Option<anyhow::Result<()>> {
+            let target = target.downcast_mut::<Vec<T>>()?;
+            let len = from.get_i32_le() as usize;
+            target.clear();
+            target.resize_with(len, T::default);
+            Some(
+                target
+                    .iter_mut()
+                    .try_for_each(|item| field_type.read(item, from)),
+            )
+        }
+
+        let result = match self {
+            FieldType::Int8 => read_items::<i8, B>(self, target, from),
+            FieldType::UInt8 => read_items::<u8, B>(self, target, from),
+            FieldType::Int16 => read_items::<i16, B>(self, target, from),
+            FieldType::UInt16 => read_items::<u16, B>(self, target, from),
+            FieldType::Int32 => read_items::<i32, B>(self, target, from),
+            FieldType::UInt32 => read_items::<u32, B>(self, target, from),
+            FieldType::Int64 => read_items::<i64, B>(self, target, from),
+            FieldType::UInt64 => read_items::<u64, B>(self, target, from),
+            FieldType::Int128 => read_items::<i128, B>(self, target, from),
+            FieldType::UInt128 => read_items::<u128, B>(self, target, from),
+            FieldType::Float => read_items::<f32, B>(self, target, from),
+            FieldType::Double => read_items::<f64, B>(self, target, from),
+            FieldType::String => read_items::<String, B>(self, target, from),
+            FieldType::Boolean => read_items::<bool, B>(self, target, from),
+            FieldType::Model | FieldType::TypeModel(_) => None,
+        };
+
+        result.unwrap_or_else(|| anyhow::bail!("No way to read a list of field type {:?}", self))
+    }
+
+    pub fn write_list<B: BufMut>(&self, value: &dyn Any, to: &mut B) -> anyhow::Result<()> {
+        let items = self
+            .list_items(value)
+            .with_context(|| format!("No way to write a list of field type {:?}", self))?;
+        to.put_i32_le(items.len() as i32);
+        items.into_iter().try_for_each(|item| self.write(item, to))
+    }
+
+    /// The elements of a `Vec` of this field type
+    pub fn list_items<'a>(&self, value: &'a dyn Any) -> Option<Vec<&'a dyn Any>> {
+        fn items<T: Any>(value: &dyn Any) -> Option<Vec<&dyn Any>> {
+            let value = value.downcast_ref::<Vec<T>>()?;
+            Some(value.iter().map(|x| x as &dyn Any).collect())
+        }
+
+        match self {
+            FieldType::Int8 => items::<i8>(value),
+            FieldType::UInt8 => items::<u8>(value),
+            FieldType::Int16 => items::<i16>(value),
+            FieldType::UInt16 => items::<u16>(value),
+            FieldType::Int32 => items::<i32>(value),
+            FieldType::UInt32 => items::<u32>(value),
+            FieldType::Int64 => items::<i64>(value),
+            FieldType::UInt64 => items::<u64>(value),
+            FieldType::Int128 => items::<i128>(value),
+            FieldType::UInt128 => items::<u128>(value),
+            FieldType::Float => items::<f32>(value),
+            FieldType::Double => items::<f64>(value),
+            FieldType::String => items::<String>(value),
+            FieldType::Boolean => items::<bool>(value),
+            FieldType::Model | FieldType::TypeModel(_) => None,
+        }
+    }
+
+    /// Reads an integer field type widened to an `i64`, used for enums
+    pub fn read_integer<B: Buf>(&self, from: &mut B) -> anyhow::Result<i64> {
+        Ok(match self {
+            FieldType::Int8 => from.get_i8() as i64,
+            FieldType::UInt8 => from.get_u8() as i64,
+            FieldType::Int16 => from.get_i16_le() as i64,
+            FieldType::UInt16 => from.get_u16_le() as i64,
+            FieldType::Int32 => from.get_i32_le() as i64,
+            FieldType::UInt32 => from.get_u32_le() as i64,
+            FieldType::Int64 => from.get_i64_le(),
+            _ => anyhow::bail!("Field type {:?} can't be read as an integer", self),
+        })
+    }
+
+    pub fn write_integer<B: BufMut>(&self, value: i64, to: &mut B) -> anyhow::Result<()> {
+        match self {
+            FieldType::Int8 => to.put_i8(value as i8),
+            FieldType::UInt8 => to.put_u8(value as u8),
+            FieldType::Int16 => to.put_i16_le(value as i16),
+            FieldType::UInt16 => to.put_u16_le(value as u16),
+            FieldType::Int32 => to.put_i32_le(value as i32),
+            FieldType::UInt32 => to.put_u32_le(value as u32),
+            FieldType::Int64 => to.put_i64_le(value),
+            _ => anyhow::bail!("Field type {:?} can't be written as an integer", self),
+        }
+
+        Ok(())
+    }
+
+    /// Integers up to 64 bits (except `u64`) and strings can key a map
+    pub fn is_map_key(&self) -> bool {
+        matches!(
+            self,
+            FieldType::Int8
+                | FieldType::UInt8
+                | FieldType::Int16
+                | FieldType::UInt16
+                | FieldType::Int32
+                | FieldType::UInt32
+                | FieldType::Int64
+                | FieldType::String
+        )
+    }
+
+    /// Reads the key of a map entry, lists use `Int32` keys for their indexes
+    pub fn read_key<B: Buf>(&self, from: &mut B) -> anyhow::Result<MapKey> {
+        match self {
+            FieldType::String => {
+                let mut key = String::new();
+                self.read(&mut key, from)?;
+                Ok(MapKey::String(key))
+            }
+            _ => self
+                .read_integer(from)
+                .map(MapKey::Int)
+                .with_context(|| format!("Field type {:?} can't be used as a map key", self)),
+        }
+    }
+
+    pub fn write_key<B: BufMut>(&self, key: &MapKey, to: &mut B) -> anyhow::Result<()> {
+        match (self, key) {
+            (FieldType::String, MapKey::String(key)) => self.write(key, to),
+            (_, MapKey::Int(key)) => self.write_integer(*key, to),
+            _ => anyhow::bail!("Can't write {:?} as a map key of type {:?}", key, self),
+        }
+    }
+
+    pub fn write<B: BufMut>(&self, value: &dyn Any, to: &mut B) -> anyhow::Result<()> {
+        match self {
+            FieldType::Int8 => {
+                if let Some(value) = value.downcast_ref::<i8>() {
+                    to.put_i8(*value);
+                    return Ok(());
+                }
+            }
+            FieldType::UInt8 => {
+                if let Some(value) = value.downcast_ref::<u8>() {
+                    to.put_u8(*value);
+                    return Ok(());
+                }
+            }
+            FieldType::Int16 => {
+                if let Some(value) = value.downcast_ref::<i16>() {
+                    to.put_i16_le(*value);
+                    return Ok(());
+                }
+            }
+            FieldType::UInt16 => {
+                if let Some(value) = value.downcast_ref::<u16>() {
+                    to.put_u16_le(*value);
+                    return Ok(());
+                }
+            }
+            FieldType::Int32 => {
+                if let Some(value) = value.downcast_ref::<i32>() {
+                    to.put_i32_le(*value);
+                    return Ok(());
+                }
+            }
+            FieldType::UInt32 => {
+                if let Some(value) = value.downcast_ref::<u32>() {
+                    to.put_u32_le(*value);
+                    return Ok(());
+                }
+            }
+            FieldType::Int64 => {
+                if let Some(value) = value.downcast_ref::<i64>() {
+                    to.put_i64_le(*value);
+                    return Ok(());
+                }
+            }
+            FieldType::UInt64 => {
+                if let Some(value) = value.downcast_ref::<u64>() {
+                    to.put_u64_le(*value);
+                    return Ok(());
+                }
+            }
+            FieldType::Int128 => {
+                if let Some(value) = value.downcast_ref::<i128>() {
+                    to.put_i128_le(*value);
+                    return Ok(());
+                }
+            }
+            FieldType::UInt128 => {
+                if let Some(value) = value.downcast_ref::<u128>() {
+                    to.put_u128_le(*value);
+                    return Ok(());
+                }
+            }
+            FieldType::Float => {
+                if let Some(value) = value.downcast_ref::<f32>() {
+                    to.put_f32_le(*value);
+                    return Ok(());
+                }
+            }
+            FieldType::Double => {
+                if let Some(value) = value.downcast_ref::<f64>() {
+                    to.put_f64_le(*value);
+                    return Ok(());
+                }
+            }
+            FieldType::String => {
+                if let Some(value) = value.downcast_ref::<String>() {
+                    to.put_i32_le(value.len() as i32);
+                    to.put_slice(value.as_bytes());
+                    return Ok(());
+                }
+            }
+            FieldType::Boolean => {
+                if let Some(value) = value.downcast_ref::<bool>() {
+                    to.put_u8(*value as u8);
+                    return Ok(());
+                }
+            }
+
+            _ => {}
+        };
+
+        anyhow::bail!("No way to write field type {:?}", self)
+    }
+}
+
+/// The key of a map field, integer keys of every width are widened to an `i64`
+#[derive(Clone, Debug, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub enum MapKey {
+    Int(i64),
+    String(String),
+}
+
+impl MapKey {
+    /// The key as a list index, lists are addressed like maps with `i32` keys
+    pub fn index(&self) -> Option<usize> {
+        match self {
+            MapKey::Int(x) => usize::try_from(*x).ok(),
+            MapKey::String(_) => None,
+        }
+    }
+}
+
+impl Display for MapKey {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MapKey::Int(x) => write!(f, "{}", x),
+            MapKey::String(x) => write!(f, "{}", x),
+        }
+    }
+}
+
+impl From<String> for MapKey {
+    fn from(value: String) -> Self {
+        MapKey::String(value)
+    }
+}
+
+impl From<&str> for MapKey {
+    fn from(value: &str) -> Self {
+        MapKey::String(value.to_string())
+    }
+}
+
+/// Rust types that can be used as keys of map fields
+pub trait MapKeyType: Sized {
+    fn to_map_key(&self) -> MapKey;
+    fn from_map_key(key: &MapKey) -> Option<Self>;
+}
+
+macro_rules! integer_map_key {
+    ($($ty:ty),*) => {
+        $(
+            impl From<$ty> for MapKey {
+                fn from(value: $ty) -> Self {
+                    MapKey::Int(value as i64)
+                }
+            }
+
+            impl MapKeyType for $ty {
+                fn to_map_key(&self) -> MapKey {
+                    MapKey::Int(*self as i64)
+                }
+
+                fn from_map_key(key: &MapKey) -> Option<Self> {
+                    match key {
+                        MapKey::Int(x) => <$ty>::try_from(*x).ok(),
+                        MapKey::String(_) => None,
+                    }
+                }
+            }
+        )*
+    };
+}
+
+integer_map_key!(i8, u8, i16, u16, i32, u32, i64);
+
+impl MapKeyType for String {
+    fn to_map_key(&self) -> MapKey {
+        MapKey::String(self.clone())
+    }
+
+    fn from_map_key(key: &MapKey) -> Option<Self> {
+        match key {
+            MapKey::String(x) => Some(x.clone()),
+            MapKey::Int(_) => None,
+        }
+    }
 }
 
 pub trait Model: Debug + Default + ModelDescription + Any {
@@ -274,18 +660,12 @@ pub trait Model: Debug + Default + ModelDescription + Any {
 
 #[cfg(test)]
 mod tests {
-    use crate::{
-        FieldDescription, FieldType, Model, ModelBTreeMap, ModelDescription, ModelExt, ModelRef,
-        ModelVec, Ref, ValueType,
-    };
-    use bytes::{Buf, Bytes};
-    use std::any::Any;
-    use std::collections::{BTreeMap, HashMap};
-    pub use uncage_model_proc_macro::Model as ModelProc;
+    use crate::{Model, ModelExt};
+    use bytes::Bytes;
 
     #[test]
     pub fn test_works() {
-        #[derive(Debug, Default, ModelProc)]
+        #[derive(Debug, Default, Model)]
         #[uncage(type = 4)]
         struct Game {
             #[uncage(index = 0)]
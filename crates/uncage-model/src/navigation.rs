@@ -0,0 +1,278 @@
+use crate::{
+    Document, MapKey, Model, ModelBorrow, ModelCollection, ModelRc, ModelRef, ModelWithDocument,
+    Ref,
+};
+use std::fmt;
+use std::fmt::{Debug, Formatter};
+use std::marker::PhantomData;
+use std::ops::Deref;
+
+pub trait Resolve<'a, R: Model, C: ModelCollection> {
+    type Output;
+
+    fn resolve(document: &'a Document<R, C>, id: usize) -> Option<Self::Output>;
+}
+
+impl<'a, R: Model, C: ModelCollection + 'a> Resolve<'a, R, C> for Ref {
+    type Output = ModelBorrow<'a, R, C>;
+
+    fn resolve(document: &'a Document<R, C>, id: usize) -> Option<Self::Output> {
+        document.by_id(id)
+    }
+}
+
+impl<'a, T: Model, R: Model, C: ModelCollection + 'a> Resolve<'a, R, C> for ModelRef<T> {
+    type Output = TypedBorrow<'a, T, R, C>;
+
+    fn resolve(document: &'a Document<R, C>, id: usize) -> Option<Self::Output> {
+        document.by_id(id)?.cast::<T>()
+    }
+}
+
+pub struct TypedBorrow<'a, T: Model, R: Model, C: ModelCollection> {
+    inner: ModelBorrow<'a, R, C>,
+    _pd: PhantomData<T>,
+}
+
+impl<'a, T: Model, R: Model, C: ModelCollection> TypedBorrow<'a, T, R, C> {
+    pub(crate) fn new(inner: ModelBorrow<'a, R, C>) -> Option<TypedBorrow<'a, T, R, C>> {
+        inner.cast_ref::<T>()?;
+        Some(TypedBorrow {
+            inner,
+            _pd: PhantomData,
+        })
+    }
+
+    pub fn untyped(&self) -> &ModelBorrow<'a, R, C> {
+        &self.inner
+    }
+
+    pub fn into_untyped(self) -> ModelBorrow<'a, R, C> {
+        self.inner
+    }
+
+    pub fn cast<U: Model>(&self) -> Option<TypedBorrow<'a, U, R, C>> {
+        self.inner.clone().cast::<U>()
+    }
+
+    pub fn follow<E: Resolve<'a, R, C>>(&self, field: usize) -> Option<E::Output> {
+        let id = self.get_model_id(field)?;
+        E::resolve(self.inner.document_ref(), id)
+    }
+
+    pub fn list<E: Resolve<'a, R, C>>(&self, field: usize) -> RefList<'a, E, R, C> {
+        RefList {
+            owner: self.inner.clone(),
+            field,
+            _pd: PhantomData,
+        }
+    }
+
+    pub fn map<E: Resolve<'a, R, C>>(&self, field: usize) -> RefMap<'a, E, R, C> {
+        RefMap {
+            owner: self.inner.clone(),
+            field,
+            _pd: PhantomData,
+        }
+    }
+}
+
+impl<'a, T: Model, R: Model, C: ModelCollection> Clone for TypedBorrow<'a, T, R, C> {
+    fn clone(&self) -> Self {
+        TypedBorrow {
+            inner: self.inner.clone(),
+            _pd: PhantomData,
+        }
+    }
+}
+
+impl<'a, T: Model, R: Model, C: ModelCollection> Deref for TypedBorrow<'a, T, R, C> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        // Checked when the borrow was created
+        self.inner.cast_ref::<T>().unwrap()
+    }
+}
+
+impl<'a, T: Model, R: Model, C: ModelCollection> Debug for TypedBorrow<'a, T, R, C> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TypedBorrow")
+            .field("id", &self.inner.object())
+            .field("model", self.inner.model().get())
+            .finish()
+    }
+}
+
+impl<'a, T: Model, R: Model, C: ModelCollection> ModelWithDocument<R, C>
+    for TypedBorrow<'a, T, R, C>
+{
+    fn document(&self) -> &Document<R, C> {
+        self.inner.document()
+    }
+
+    fn object(&self) -> usize {
+        self.inner.object()
+    }
+
+    fn model(&self) -> &ModelRc {
+        self.inner.model()
+    }
+}
+
+pub struct RefList<'a, E: Resolve<'a, R, C>, R: Model, C: ModelCollection> {
+    owner: ModelBorrow<'a, R, C>,
+    field: usize,
+    _pd: PhantomData<E>,
+}
+
+impl<'a, E: Resolve<'a, R, C>, R: Model, C: ModelCollection> RefList<'a, E, R, C> {
+    pub fn len(&self) -> usize {
+        self.owner.list_len(self.field)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn get(&self, index: usize) -> Option<E::Output> {
+        if index >= self.len() {
+            return None;
+        }
+
+        let id = self.owner.list_get_model_id(self.field, index)?;
+        E::resolve(self.owner.document_ref(), id)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = E::Output> + '_ {
+        (0..self.len()).filter_map(move |index| self.get(index))
+    }
+}
+
+pub struct RefMap<'a, E: Resolve<'a, R, C>, R: Model, C: ModelCollection> {
+    owner: ModelBorrow<'a, R, C>,
+    field: usize,
+    _pd: PhantomData<E>,
+}
+
+impl<'a, E: Resolve<'a, R, C>, R: Model, C: ModelCollection> RefMap<'a, E, R, C> {
+    pub fn keys(&self) -> Vec<MapKey> {
+        self.owner.model().get().get_map_keys(self.field)
+    }
+
+    pub fn len(&self) -> usize {
+        self.keys().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn get<K: Into<MapKey>>(&self, key: K) -> Option<E::Output> {
+        let id = self.owner.map_get_model_id(self.field, &key.into())?;
+        E::resolve(self.owner.document_ref(), id)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (MapKey, E::Output)> + '_ {
+        self.keys()
+            .into_iter()
+            .filter_map(move |key| Some((key.clone(), self.get(key)?)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        Document, MapKey, Model, ModelBTreeMap, ModelCollection, ModelRef, ModelVec,
+        ModelWithDocument, Ref,
+    };
+
+    #[derive(Debug, Default, Model)]
+    #[uncage(type = 0)]
+    struct Root {
+        #[uncage(index = 0)]
+        leader: ModelRef<Unit>,
+        #[uncage(index = 1)]
+        units: ModelVec<Unit>,
+        #[uncage(index = 2)]
+        named: ModelBTreeMap<i32, Unit>,
+        #[uncage(index = 3)]
+        any: Ref,
+    }
+
+    #[derive(Debug, Default, Model)]
+    #[uncage(type = 1)]
+    struct Unit {
+        #[uncage(index = 0)]
+        hp: i32,
+    }
+
+    #[derive(Debug, ModelCollection)]
+    enum Models {
+        Root(Root),
+        Unit(Unit),
+    }
+
+    fn unit(hp: i32) -> Box<Unit> {
+        Box::new(Unit { hp })
+    }
+
+    #[test]
+    pub fn test_missing_refs() {
+        let document: Document<Root, Models> = Document::new();
+        let root = document.root();
+
+        assert!(root.leader().is_none());
+        assert!(root.any().is_none());
+        assert!(root.units().is_empty());
+        assert!(root.units().get(0).is_none());
+        assert!(root.named().is_empty());
+        assert!(root.named().get(1).is_none());
+
+        root.resize_list(RootFields::Units as usize, 2);
+        root.list_create_model(RootFields::Units as usize, 1, unit(3));
+        let units = root.units();
+        assert_eq!(units.len(), 2);
+        assert!(units.get(0).is_none());
+        assert_eq!(units.get(1).unwrap().hp, 3);
+        assert!(units.get(2).is_none());
+        assert_eq!(units.iter().map(|x| x.hp).collect::<Vec<_>>(), vec![3]);
+
+        let id = root
+            .set_model(RootFields::Leader as usize, unit(5))
+            .unwrap();
+        assert_eq!(root.leader().unwrap().hp, 5);
+        document.remove(id);
+        document.flush();
+        assert!(root.leader().is_none());
+    }
+
+    #[test]
+    pub fn test_follow_refs() {
+        let document: Document<Root, Models> = Document::new();
+        let root = document.root();
+        root.set_model(RootFields::Leader as usize, unit(10));
+        root.set_model(RootFields::Any as usize, unit(20));
+        root.map_create_model(RootFields::Named as usize, &MapKey::Int(7), unit(30));
+        root.map_create_model(RootFields::Named as usize, &MapKey::Int(3), unit(40));
+
+        let leader = root.leader().unwrap();
+        assert_eq!(leader.hp, 10);
+        assert_eq!(leader.untyped().cast_ref::<Unit>().unwrap().hp, 10);
+        assert!(leader.cast::<Root>().is_none());
+
+        let any = root.any().unwrap();
+        assert!(any.clone().cast::<Root>().is_none());
+        assert_eq!(any.cast::<Unit>().unwrap().hp, 20);
+
+        let named = root.named();
+        assert_eq!(named.keys(), vec![MapKey::Int(3), MapKey::Int(7)]);
+        assert_eq!(named.len(), 2);
+        assert_eq!(named.get(7).unwrap().hp, 30);
+        assert!(named.get(8).is_none());
+        assert_eq!(
+            named.iter().map(|(key, x)| (key, x.hp)).collect::<Vec<_>>(),
+            vec![(MapKey::Int(3), 40), (MapKey::Int(7), 30)]
+        );
+    }
+}
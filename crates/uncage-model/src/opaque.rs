@@ -0,0 +1,153 @@
+use crate::{FieldDescription, FieldMetadata, FieldType, MapKey, ModelDescription, Ref, ValueType};
+use std::any::Any;
+
+/// Stands in for fields a tolerant [crate::patcher::Patcher] doesn't know in the paths it reports
+pub(crate) static UNKNOWN_FIELD: FieldDescription = FieldDescription {
+    value_type: ValueType::Value,
+    field_type: FieldType::Model,
+    index: usize::MAX,
+    model_type: usize::MAX,
+    model_name: "Opaque",
+    field_name: "unknown",
+    autofill: false,
+    enumeration: None,
+    optional: false,
+    nested: false,
+    metadata: FieldMetadata::NONE,
+};
+
+/// Placeholder for a model type the collection doesn't know, created by a tolerant
+/// [crate::patcher::Patcher] so pushes and pops into it still balance. It has no fields, every
+/// value sent to it is skipped
+#[derive(Debug)]
+pub struct OpaqueModel {
+    model_type: usize,
+}
+
+impl OpaqueModel {
+    pub fn new(model_type: usize) -> OpaqueModel {
+        OpaqueModel { model_type }
+    }
+}
+
+impl ModelDescription for OpaqueModel {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn get_parent(&self) -> Option<&dyn ModelDescription> {
+        None
+    }
+
+    fn get_model_type(&self) -> usize {
+        self.model_type
+    }
+
+    fn get_model_name(&self) -> &'static str {
+        "Opaque"
+    }
+
+    fn get_fields(&self) -> Vec<FieldDescription> {
+        vec![]
+    }
+
+    fn get_field_description(&self, _: usize) -> Option<&'static FieldDescription> {
+        None
+    }
+
+    fn get_field(&self, _: usize) -> Option<&dyn Any> {
+        None
+    }
+
+    fn get_field_mut(&mut self, _: usize) -> Option<&mut dyn Any> {
+        None
+    }
+
+    fn get_model_ref(&self, _: usize) -> Option<&Ref> {
+        None
+    }
+
+    fn get_model_ref_mut(&mut self, _: usize) -> Option<&mut Ref> {
+        None
+    }
+
+    fn get_map_keys(&self, _: usize) -> Vec<MapKey> {
+        vec![]
+    }
+
+    fn create_map_field(&mut self, _: usize, _: &MapKey) -> Option<&mut dyn Any> {
+        None
+    }
+
+    fn create_map_field_ref(&mut self, _: usize, _: &MapKey) -> Option<&mut Ref> {
+        None
+    }
+
+    fn get_map_field_ref(&self, _: usize, _: &MapKey) -> Option<&Ref> {
+        None
+    }
+
+    fn get_map_field_ref_mut(&mut self, _: usize, _: &MapKey) -> Option<&mut Ref> {
+        None
+    }
+
+    fn get_map_field(&self, _: usize, _: &MapKey) -> Option<&dyn Any> {
+        None
+    }
+
+    fn get_map_field_mut(&mut self, _: usize, _: &MapKey) -> Option<&mut dyn Any> {
+        None
+    }
+
+    fn remove_map_field(&mut self, _: usize, _: &MapKey) -> Option<Ref> {
+        None
+    }
+
+    fn swap_map_field(&mut self, field: usize, _: &MapKey, _: &MapKey) -> anyhow::Result<()> {
+        anyhow::bail!("No map on field {}", field)
+    }
+
+    fn clear_field(&mut self, field: usize) {
+        panic!("No optional value on field {}", field)
+    }
+
+    fn get_list_len(&self, _: usize) -> usize {
+        0
+    }
+
+    fn resize_list_field(&mut self, field: usize, _: usize) {
+        panic!("No list on field {}", field)
+    }
+
+    fn remove_list_field(&mut self, field: usize, _: usize) -> Option<Ref> {
+        panic!("No list on field {}", field)
+    }
+
+    fn swap_list_field(&mut self, field: usize, _: usize, _: usize) {
+        panic!("No list on field {}", field)
+    }
+
+    fn get_list_field(&self, field: usize, _: usize) -> &dyn Any {
+        panic!("No list on field {}", field)
+    }
+
+    fn get_list_field_mut(&mut self, field: usize, _: usize) -> &mut dyn Any {
+        panic!("No list on field {}", field)
+    }
+
+    fn insert_list_field(&mut self, field: usize, _: usize) -> &mut dyn Any {
+        panic!("No list on field {}", field)
+    }
+
+    fn get_list_field_ref(&self, field: usize, _: usize) -> &Ref {
+        panic!("No list on field {}", field)
+    }
+
+    fn get_list_field_ref_mut(&mut self, field: usize, _: usize) -> &mut Ref {
+        panic!("No list on field {}", field)
+    }
+
+    fn insert_list_field_ref(&mut self, field: usize, _: usize) -> &mut Ref {
+        panic!("No list on field {}", field)
+    }
+}
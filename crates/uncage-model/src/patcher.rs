@@ -1,12 +1,19 @@
+use crate::opaque::UNKNOWN_FIELD;
 use crate::selector::SelectorCollection;
 use crate::{
-    Document, Model, ModelBorrowOwned, ModelCollection, ModelWithDocument, Path, PathAction,
-    PathSegment, Selector, ValueType,
+    Document, FieldDescription, FieldType, MapKey, Model, ModelBorrowOwned, ModelCollection,
+    ModelDescription, ModelWithDocument, OpaqueModel, Path, PathAction, PathSegment, Selector,
+    ValueType,
 };
 use anyhow::Context;
 use bytes::{Buf, Bytes};
 use num_derive::FromPrimitive;
 use num_traits::FromPrimitive;
+use std::collections::{BTreeMap, HashMap};
+use std::rc::Rc;
+
+/// How many unknown values in a row are guessed ahead when inferring a value width
+const MAX_GUESSED_VALUES: usize = 4;
 
 #[derive(Debug)]
 pub struct Patcher<R: Model, C: ModelCollection> {
@@ -14,12 +21,36 @@ pub struct Patcher<R: Model, C: ModelCollection> {
     stack: Vec<usize>,
     path: Path,
     selectors: SelectorCollection,
+    selector_keys: Vec<usize>,
+    tolerant: bool,
+    skipped: SkipSummary,
+    /// Children of unknown fields by their parent, field and the key as it was on the wire
+    unknown_children: HashMap<(usize, usize, Option<Bytes>), usize>,
+}
+
+/// Everything a tolerant [Patcher] skipped because the models don't know it
+#[derive(Debug, Default, Clone)]
+pub struct SkipSummary {
+    /// Actions on unknown fields, by model type and field index
+    pub unknown_fields: BTreeMap<(usize, usize), usize>,
+    /// Opaque placeholders created, by unknown model type
+    pub unknown_models: BTreeMap<usize, usize>,
+    /// Patches whose rest was dropped because the width of a value or key couldn't be inferred
+    pub truncated_patches: usize,
+}
+
+impl SkipSummary {
+    pub fn is_empty(&self) -> bool {
+        self.unknown_fields.is_empty()
+            && self.unknown_models.is_empty()
+            && self.truncated_patches == 0
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct PatcherSelectorMatch {
     pub object_id: usize,
-    pub path: Path,
+    pub path: Rc<Path>,
     pub selector_key: usize,
 }
 
@@ -30,8 +61,25 @@ impl<R: 'static + Model, C: ModelCollection> Patcher<R, C> {
             stack: Vec::with_capacity(10),
             path: Path::new(),
             selectors: SelectorCollection::new(),
+            selector_keys: vec![],
+            tolerant: false,
+            skipped: SkipSummary::default(),
+            unknown_children: HashMap::new(),
         }
     }
+
+    /// In tolerant mode unknown fields are skipped and unknown model types are replaced by an
+    /// [OpaqueModel] instead of failing the patch, see [Patcher::skipped] for what was left out.
+    /// Children pushed through unknown fields are tracked by the patcher, indexes of unknown
+    /// lists aren't shifted on inserts
+    pub fn set_tolerant(&mut self, tolerant: bool) {
+        self.tolerant = tolerant;
+    }
+
+    pub fn skipped(&self) -> &SkipSummary {
+        &self.skipped
+    }
+
     pub fn add_selector(&mut self, key: usize, selector: Selector) {
         self.selectors.add_selector(key, selector)
     }
@@ -52,6 +100,46 @@ impl<R: 'static + Model, C: ModelCollection> Patcher<R, C> {
         self.get_top_of_stack()
     }
 
+    fn emit_matches(&mut self, object_id: usize, matches: &mut Vec<PatcherSelectorMatch>) {
+        self.selectors
+            .matches_into(&self.path, &mut self.selector_keys);
+        if self.selector_keys.is_empty() {
+            return;
+        }
+
+        let path = Rc::new(self.path.clone());
+        for key in &self.selector_keys {
+            matches.push(PatcherSelectorMatch {
+                object_id,
+                path: Rc::clone(&path),
+                selector_key: *key,
+            })
+        }
+    }
+
+    fn emit_element_matches(
+        &mut self,
+        top: &ModelBorrowOwned<R, C>,
+        field: usize,
+        key: MapKey,
+        action: PathAction,
+        object_id: usize,
+        matches: &mut Vec<PatcherSelectorMatch>,
+    ) {
+        let model_type = top.model().get().get_model_type();
+        let description = top.model().get().get_field_description(field).unwrap();
+        match (description.value_type, key.index()) {
+            (ValueType::Map { .. }, _) | (_, None) => {
+                self.path.goto_map_field(model_type, description, key)
+            }
+            (_, Some(index)) => self.path.goto_list_field(model_type, description, index),
+        }
+
+        self.path.set_action(action);
+        self.emit_matches(object_id, matches);
+        self.path.goto_parent();
+    }
+
     fn get_top_of_stack(&self) -> ModelBorrowOwned<R, C> {
         if let Some(id) = self.stack.last() {
             self.document.by_id(*id).unwrap().owned()
@@ -60,17 +148,200 @@ impl<R: 'static + Model, C: ModelCollection> Patcher<R, C> {
         }
     }
 
+    fn create_model(&mut self, model_type: usize) -> anyhow::Result<Box<dyn ModelDescription>> {
+        if C::has_model(model_type) {
+            return Ok(C::create_model(model_type).boxed());
+        }
+
+        if !self.tolerant {
+            anyhow::bail!("No model with model type {}", model_type);
+        }
+
+        *self.skipped.unknown_models.entry(model_type).or_default() += 1;
+        Ok(Box::new(OpaqueModel::new(model_type)))
+    }
+
+    /// The field the next action works on, if the patcher is tolerant and the model doesn't know it
+    fn unknown_field(
+        &self,
+        top: &ModelBorrowOwned<R, C>,
+        action: PatchAction,
+        buffer: &Bytes,
+    ) -> Option<usize> {
+        if !self.tolerant || action == PatchAction::Pop {
+            return None;
+        }
+
+        let field = *buffer.first()? as usize;
+        let model = top.model().get();
+        model
+            .get_field_description(field)
+            .is_none()
+            .then_some(field)
+    }
+
+    /// Consumes an action on an unknown field, returns false when the width of its value or key
+    /// can't be inferred
+    fn skip_unknown_field(
+        &mut self,
+        top: &mut ModelBorrowOwned<R, C>,
+        action: PatchAction,
+        field: usize,
+        buffer: &mut Bytes,
+    ) -> anyhow::Result<bool> {
+        let object = top.object();
+        let model_type = top.model().get().get_model_type();
+        *self
+            .skipped
+            .unknown_fields
+            .entry((model_type, field))
+            .or_default() += 1;
+
+        match action {
+            PatchAction::Pop => unreachable!(),
+            PatchAction::AssignField | PatchAction::AssignKey | PatchAction::Insert => {
+                let model = top.model().get();
+                if action != PatchAction::AssignField {
+                    match infer_key_width(buffer, |_, rest| infer_width(model, rest).is_some()) {
+                        Some(width) => buffer.advance(width),
+                        None => return Ok(false),
+                    }
+                }
+
+                match infer_width(model, buffer) {
+                    Some(width) => buffer.advance(width),
+                    None => return Ok(false),
+                }
+            }
+            PatchAction::PushField => {
+                let id = *self
+                    .unknown_children
+                    .get(&(object, field, None))
+                    .context("No model to push")?;
+                *top = self.push_top(PathSegment::field(model_type, &UNKNOWN_FIELD), id);
+            }
+            PatchAction::PushKey => {
+                // Only a key of a child that was created before can be pushed
+                let width = infer_key_width(buffer, |key, _| {
+                    let key = Some(Bytes::copy_from_slice(key));
+                    self.unknown_children.contains_key(&(object, field, key))
+                });
+                let key = match width {
+                    Some(width) => buffer.split_to(width),
+                    None => return Ok(false),
+                };
+
+                let id = self.unknown_children[&(object, field, Some(key))];
+                *top = self.push_top(PathSegment::field(model_type, &UNKNOWN_FIELD), id);
+            }
+            PatchAction::PushCreateAndAssignField
+            | PatchAction::PushCreateAndAssignKey
+            | PatchAction::PushCreateAndInsert => {
+                let child_type = buffer.get_u8() as usize;
+                let model = self.create_model(child_type)?;
+                let key = if action == PatchAction::PushCreateAndAssignField {
+                    None
+                } else {
+                    // The actions after the key work on the new model
+                    let width = infer_key_width(buffer, |_, rest| {
+                        fits_action(&*model, rest, MAX_GUESSED_VALUES)
+                    });
+                    match width {
+                        Some(width) => Some(buffer.split_to(width)),
+                        None => return Ok(false),
+                    }
+                };
+
+                let id = self.document.register(model);
+                if let Some(old) = self.unknown_children.insert((object, field, key), id) {
+                    self.document.remove(old);
+                }
+
+                *top = self.push_top(PathSegment::field(model_type, &UNKNOWN_FIELD), id);
+                self.path.set_action(PathAction::Created);
+            }
+            PatchAction::ResetField | PatchAction::ResetKey | PatchAction::Remove => {
+                let model = top.model().get();
+                let key = if action == PatchAction::ResetField {
+                    None
+                } else {
+                    let width = infer_key_width(buffer, |_, rest| {
+                        fits_action(model, rest, MAX_GUESSED_VALUES)
+                    });
+                    match width {
+                        Some(width) => Some(buffer.split_to(width)),
+                        None => return Ok(false),
+                    }
+                };
+
+                if let Some(old) = self.unknown_children.remove(&(object, field, key)) {
+                    self.document.remove(old);
+                }
+            }
+            PatchAction::Swap => {
+                let model = top.model().get();
+                let width = infer_key_width(buffer, |_, rest| {
+                    infer_key_width(rest, |_, rest| fits_action(model, rest, MAX_GUESSED_VALUES))
+                        .is_some()
+                });
+                let lhs = match width {
+                    Some(width) => buffer.split_to(width),
+                    None => return Ok(false),
+                };
+
+                let width = infer_key_width(buffer, |_, rest| {
+                    fits_action(model, rest, MAX_GUESSED_VALUES)
+                });
+                let rhs = match width {
+                    Some(width) => buffer.split_to(width),
+                    None => return Ok(false),
+                };
+
+                let lhs = (object, field, Some(lhs));
+                let rhs = (object, field, Some(rhs));
+                let old_lhs = self.unknown_children.remove(&lhs);
+                let old_rhs = self.unknown_children.remove(&rhs);
+                if let Some(x) = old_lhs {
+                    self.unknown_children.insert(rhs, x);
+                }
+
+                if let Some(x) = old_rhs {
+                    self.unknown_children.insert(lhs, x);
+                }
+            }
+            PatchAction::Resize => buffer.advance(4),
+        }
+
+        Ok(true)
+    }
+
     pub fn apply_patch(&mut self, mut buffer: Bytes) -> anyhow::Result<Vec<PatcherSelectorMatch>> {
         self.document.flush();
 
         let mut top = self.get_top_of_stack();
         let mut matches = vec![];
+        let depth = self.stack.len();
 
         while buffer.has_remaining() {
             let action = buffer.get_u8();
             let action: PatchAction = PatchAction::from_u8(action)
                 .with_context(|| format!("{} is not a valid patch action", action))?;
 
+            if let Some(field) = self.unknown_field(&top, action, &buffer) {
+                buffer.advance(1);
+                if !self.skip_unknown_field(&mut top, action, field, &mut buffer)? {
+                    // The rest of the patch can't be read, drop it and what it pushed
+                    self.skipped.truncated_patches += 1;
+                    while self.stack.len() > depth {
+                        self.pop_top();
+                    }
+
+                    break;
+                }
+
+                continue;
+            }
+
             match action {
                 PatchAction::Pop => {
                     top = self.pop_top();
@@ -80,27 +351,19 @@ impl<R: 'static + Model, C: ModelCollection> Patcher<R, C> {
                     let field = buffer.get_u8() as usize;
                     top.assign(field, &mut buffer)?;
 
-                    {
-                        let mut path = self.path.clone();
-                        path.goto_field(
-                            top.model().get().get_model_type(),
-                            top.model().get().get_field_description(field).unwrap(),
-                        );
-                        path.set_action(PathAction::Mutated);
-                        for key in self.selectors.matches(&path) {
-                            matches.push(PatcherSelectorMatch {
-                                object_id: top.object(),
-                                path: path.clone(),
-                                selector_key: key,
-                            })
-                        }
-                    }
+                    self.path.goto_field(
+                        top.model().get().get_model_type(),
+                        top.model().get().get_field_description(field).unwrap(),
+                    );
+                    self.path.set_action(PathAction::Mutated);
+                    self.emit_matches(top.object(), &mut matches);
+                    self.path.goto_parent();
                 }
 
                 PatchAction::PushCreateAndAssignField => {
                     let field = buffer.get_u8() as usize;
                     let model_type = buffer.get_u8() as usize;
-                    let model = C::create_model(model_type).boxed();
+                    let model = self.create_model(model_type)?;
 
                     let id = top
                         .set_model(field, model)
@@ -134,90 +397,79 @@ impl<R: 'static + Model, C: ModelCollection> Patcher<R, C> {
 
                 PatchAction::ResetField => {
                     let field = buffer.get_u8() as usize;
-                    if let Some(id) = top.reset_model_field(field) {
+                    if top.is_optional_field(field) {
+                        top.clear_field(field);
+
+                        self.path.goto_field(
+                            top.model().get().get_model_type(),
+                            top.model().get().get_field_description(field).unwrap(),
+                        );
+                        self.path.set_action(PathAction::Removed);
+                        self.emit_matches(top.object(), &mut matches);
+                        self.path.goto_parent();
+                    } else if let Some(id) = top.reset_model_field(field) {
                         self.document.remove(id);
 
-                        let mut path = self.path.clone();
-                        path.goto_field(
+                        self.path.goto_field(
                             top.model().get().get_model_type(),
                             top.model().get().get_field_description(field).unwrap(),
                         );
-                        path.set_action(PathAction::Removed);
-                        for key in self.selectors.matches(&path) {
-                            matches.push(PatcherSelectorMatch {
-                                object_id: top.object(),
-                                path: path.clone(),
-                                selector_key: key,
-                            })
-                        }
+                        self.path.set_action(PathAction::Removed);
+                        self.emit_matches(top.object(), &mut matches);
+                        self.path.goto_parent();
                     }
                     continue;
                 }
 
                 PatchAction::AssignKey => {
                     let field = buffer.get_u8() as usize;
-                    let index = buffer.get_i32_le();
-
-                    self.path.set_action(PathAction::Mutated);
+                    let key = top.read_key(field, &mut buffer)?;
 
-                    let mut path = self.path.clone();
                     match top.value_type(field) {
                         ValueType::Value => {
-                            unreachable!()
+                            anyhow::bail!("Field {} is not a map or list", field);
                         }
-                        ValueType::Map { .. } => {
-                            top.map_assign(field, index, &mut buffer)?;
-                            path.goto_map_field(
-                                top.model().get().get_model_type(),
-                                top.model().get().get_field_description(field).unwrap(),
-                                index,
-                            );
-                        }
-                        ValueType::List => {
-                            top.list_assign(field, index, &mut buffer)?;
-                            path.goto_list_field(
-                                top.model().get().get_model_type(),
-                                top.model().get().get_field_description(field).unwrap(),
-                                index as usize,
-                            );
+                        ValueType::Map { .. } => top.map_assign(field, &key, &mut buffer)?,
+                        ValueType::List | ValueType::Array { .. } => {
+                            top.list_assign(field, list_index(&key)?, &mut buffer)?
                         }
                     }
 
-                    path.set_action(PathAction::Mutated);
-                    for key in self.selectors.matches(&path) {
-                        matches.push(PatcherSelectorMatch {
-                            object_id: top.object(),
-                            path: path.clone(),
-                            selector_key: key,
-                        })
-                    }
+                    let object_id = top.object();
+                    self.emit_element_matches(
+                        &top,
+                        field,
+                        key,
+                        PathAction::Mutated,
+                        object_id,
+                        &mut matches,
+                    );
                 }
                 PatchAction::PushKey => {
                     let field = buffer.get_u8() as usize;
-                    let index = buffer.get_i32_le();
+                    let key = top.read_key(field, &mut buffer)?;
 
                     match top.value_type(field) {
                         ValueType::Value => {}
                         ValueType::Map { .. } => {
-                            let id = top
-                                .map_get_model_id(field, index)
-                                .context("Couldn't find")?;
+                            let id = top.map_get_model_id(field, &key).context("Couldn't find")?;
                             top = self.push_top(
                                 PathSegment::map_field(
                                     top.model().get().get_model_type(),
                                     top.model().get().get_field_description(field).unwrap(),
-                                    index,
+                                    key,
                                 ),
                                 id,
                             );
                         }
-                        ValueType::List => {
+                        ValueType::List | ValueType::Array { .. } => {
+                            let index = list_index(&key)?;
                             let id = top.list_get_model_id(field, index).context("failed")?;
                             top = self.push_top(
                                 PathSegment::list_field(
                                     top.model().get().get_model_type(),
                                     top.model().get().get_field_description(field).unwrap(),
-                                    index as usize,
+                                    index,
                                 ),
                                 id,
                             );
@@ -229,25 +481,24 @@ impl<R: 'static + Model, C: ModelCollection> Patcher<R, C> {
                 PatchAction::PushCreateAndAssignKey => {
                     let field = buffer.get_u8() as usize;
                     let model_type = buffer.get_u8() as usize;
-                    let model = C::create_model(model_type as usize).boxed();
-                    let index = buffer.get_i32_le();
+                    let model = self.create_model(model_type)?;
+                    let key = top.read_key(field, &mut buffer)?;
 
                     match top.value_type(field) {
                         ValueType::Value => {}
                         ValueType::Map { .. } => {
-                            let id = top
-                                .map_create_model(field, index, model)
-                                .context("failed")?;
+                            let id = top.map_create_model(field, &key, model).context("failed")?;
                             top = self.push_top(
                                 PathSegment::map_field(
                                     top.model().get().get_model_type(),
                                     top.model().get().get_field_description(field).unwrap(),
-                                    index,
+                                    key,
                                 ),
                                 id,
                             );
                         }
-                        ValueType::List => {
+                        ValueType::List | ValueType::Array { .. } => {
+                            let index = list_index(&key)?;
                             let id = top
                                 .list_create_model(field, index, model)
                                 .context("failed")?;
@@ -255,7 +506,7 @@ impl<R: 'static + Model, C: ModelCollection> Patcher<R, C> {
                                 PathSegment::list_field(
                                     top.model().get().get_model_type(),
                                     top.model().get().get_field_description(field).unwrap(),
-                                    index as usize,
+                                    index,
                                 ),
                                 id,
                             );
@@ -266,52 +517,51 @@ impl<R: 'static + Model, C: ModelCollection> Patcher<R, C> {
                 }
                 PatchAction::ResetKey => {
                     let field = buffer.get_u8() as usize;
-                    let index = buffer.get_i32_le();
-                    let mut path = self.path.clone();
+                    let key = top.read_key(field, &mut buffer)?;
                     let id = match top.value_type(field) {
                         ValueType::Value => {
-                            unreachable!();
+                            anyhow::bail!("Field {} is not a map or list", field);
                         }
                         ValueType::Map { .. } => {
-                            path.goto_map_field(
-                                top.model().get().get_model_type(),
-                                top.model().get().get_field_description(field).unwrap(),
-                                index,
-                            );
-                            top.map_reset_model(field, index).context("failed")?
-                        }
-                        ValueType::List => {
-                            path.goto_map_field(
-                                top.model().get().get_model_type(),
-                                top.model().get().get_field_description(field).unwrap(),
-                                index,
-                            );
-                            top.list_reset_model(field, index).context("failed")?
+                            top.map_reset_model(field, &key).context("failed")?
                         }
+                        ValueType::List | ValueType::Array { .. } => top
+                            .list_reset_model(field, list_index(&key)?)
+                            .context("failed")?,
                     };
 
                     self.document.remove(id);
-
-                    path.set_action(PathAction::Removed);
-                    for key in self.selectors.matches(&path) {
-                        matches.push(PatcherSelectorMatch {
-                            object_id: id,
-                            path: path.clone(),
-                            selector_key: key,
-                        })
-                    }
+                    self.emit_element_matches(
+                        &top,
+                        field,
+                        key,
+                        PathAction::Removed,
+                        id,
+                        &mut matches,
+                    );
                 }
                 PatchAction::Insert => {
                     let field = buffer.get_u8() as usize;
-                    let index = buffer.get_i32_le();
-                    self.path.set_action(PathAction::Mutated);
+                    let index = buffer.get_i32_le() as usize;
+                    top.expect_resizable(field)?;
                     top.list_insert(field, index, &mut buffer)?;
+
+                    let object_id = top.object();
+                    self.emit_element_matches(
+                        &top,
+                        field,
+                        MapKey::Int(index as i64),
+                        PathAction::Created,
+                        object_id,
+                        &mut matches,
+                    );
                 }
                 PatchAction::PushCreateAndInsert => {
                     let field = buffer.get_u8() as usize;
                     let model_type = buffer.get_u8() as usize;
-                    let index = buffer.get_i32_le();
-                    let model = C::create_model(model_type as usize).boxed();
+                    let index = buffer.get_i32_le() as usize;
+                    top.expect_resizable(field)?;
+                    let model = self.create_model(model_type)?;
                     let id = top
                         .list_insert_model(field, index, model)
                         .context("Couldn't find field")?;
@@ -319,7 +569,7 @@ impl<R: 'static + Model, C: ModelCollection> Patcher<R, C> {
                         PathSegment::list_field(
                             top.model().get().get_model_type(),
                             top.model().get().get_field_description(field).unwrap(),
-                            index as usize,
+                            index,
                         ),
                         id,
                     );
@@ -328,42 +578,250 @@ impl<R: 'static + Model, C: ModelCollection> Patcher<R, C> {
                 }
                 PatchAction::Remove => {
                     let field = buffer.get_u8() as usize;
-                    let index = buffer.get_i32_le();
-                    if let Some(id) = top.list_remove(field, index) {
+                    let key = top.read_key(field, &mut buffer)?;
+                    let removed = match top.value_type(field) {
+                        ValueType::Value => {
+                            anyhow::bail!("Field {} is not a map or list", field);
+                        }
+                        ValueType::Map { .. } => top.map_remove(field, &key),
+                        ValueType::List => top.list_remove(field, list_index(&key)?),
+                        ValueType::Array { .. } => {
+                            anyhow::bail!("Can't remove from the array on field {}", field)
+                        }
+                    };
+
+                    if let Some(id) = removed {
                         self.document.remove(id);
                     }
+
+                    let object_id = removed.unwrap_or_else(|| top.object());
+                    self.emit_element_matches(
+                        &top,
+                        field,
+                        key,
+                        PathAction::Removed,
+                        object_id,
+                        &mut matches,
+                    );
                 }
                 PatchAction::Swap => {
                     let field = buffer.get_u8() as usize;
-                    let index_a = buffer.get_i32_le();
-                    let index_b = buffer.get_i32_le();
-                    self.path.set_action(PathAction::Mutated);
-                    top.list_swap(field, index_a, index_b);
+                    let key_a = top.read_key(field, &mut buffer)?;
+                    let key_b = top.read_key(field, &mut buffer)?;
+                    match top.value_type(field) {
+                        ValueType::Value => {
+                            anyhow::bail!("Field {} is not a map or list", field);
+                        }
+                        ValueType::Map { .. } => top.map_swap(field, &key_a, &key_b)?,
+                        ValueType::List | ValueType::Array { .. } => {
+                            top.list_swap(field, list_index(&key_a)?, list_index(&key_b)?)
+                        }
+                    }
+
+                    let object_id = top.object();
+                    for key in [key_a, key_b] {
+                        self.emit_element_matches(
+                            &top,
+                            field,
+                            key,
+                            PathAction::Mutated,
+                            object_id,
+                            &mut matches,
+                        );
+                    }
                 }
                 PatchAction::Resize => {
                     let field = buffer.get_u8() as usize;
-                    let new_size = buffer.get_i32_le();
+                    let new_size = buffer.get_i32_le() as usize;
+                    let old_size = top.list_len(field);
+                    if new_size == old_size {
+                        // Arrays are sent with their length like any other list
+                        if let ValueType::Array { .. } = top.value_type(field) {
+                            continue;
+                        }
+                    }
+
+                    top.expect_resizable(field)?;
+                    let is_model = top.is_model_field(field);
+
+                    let mut removed = vec![];
+                    if is_model {
+                        for index in new_size..old_size {
+                            if let Some(id) = top.list_get_model_id(field, index) {
+                                removed.push((index, id));
+                            }
+                        }
+                    }
+
+                    top.resize_list(field, new_size);
+
+                    for (index, id) in removed {
+                        self.document.remove(id);
+                        self.emit_element_matches(
+                            &top,
+                            field,
+                            MapKey::Int(index as i64),
+                            PathAction::Removed,
+                            id,
+                            &mut matches,
+                        );
+                    }
+
+                    if is_model {
+                        for index in old_size..new_size {
+                            if let Some(id) = top.list_get_model_id(field, index) {
+                                self.emit_element_matches(
+                                    &top,
+                                    field,
+                                    MapKey::Int(index as i64),
+                                    PathAction::Created,
+                                    id,
+                                    &mut matches,
+                                );
+                            }
+                        }
+                    }
+
+                    self.path.goto_field(
+                        top.model().get().get_model_type(),
+                        top.model().get().get_field_description(field).unwrap(),
+                    );
                     self.path.set_action(PathAction::Mutated);
-                    top.resize_list(field, new_size as usize)
+                    self.emit_matches(top.object(), &mut matches);
+                    self.path.goto_parent();
                 }
             }
 
-            for key in self.selectors.matches(&self.path) {
-                matches.push(PatcherSelectorMatch {
-                    object_id: top.object(),
-                    path: self.path.clone(),
-                    selector_key: key,
-                })
-            }
+            self.emit_matches(top.object(), &mut matches);
         }
 
         Ok(matches)
     }
 }
 
+/// Guesses the width of the value of an unknown field. The value has to run to the end of the
+/// patch or be followed by a pop or another action on the same model, further values of unknown
+/// fields are guessed the same way. Only a single fitting width is trusted
+fn infer_width(model: &dyn ModelDescription, buffer: &[u8]) -> Option<usize> {
+    single(
+        value_widths(buffer)
+            .into_iter()
+            .filter(|x| fits_action(model, &buffer[*x..], MAX_GUESSED_VALUES)),
+    )
+}
+
+/// Guesses the width of the key of an unknown field, `fits` checks a candidate key and what
+/// follows it. Like values only a single fitting width is trusted
+fn infer_key_width(buffer: &[u8], fits: impl Fn(&[u8], &[u8]) -> bool) -> Option<usize> {
+    single(
+        key_widths(buffer)
+            .into_iter()
+            .filter(|x| fits(&buffer[..*x], &buffer[*x..])),
+    )
+}
+
+fn single(mut widths: impl Iterator<Item = usize>) -> Option<usize> {
+    let width = widths.next()?;
+    widths.next().is_none().then_some(width)
+}
+
+fn value_widths(buffer: &[u8]) -> Vec<usize> {
+    widths(buffer, &[1, 2, 4, 8, 16])
+}
+
+/// Map keys are integers of up to 8 bytes or strings, list indexes are `i32`
+fn key_widths(buffer: &[u8]) -> Vec<usize> {
+    widths(buffer, &[1, 2, 4, 8])
+}
+
+fn widths(buffer: &[u8], fixed: &[usize]) -> Vec<usize> {
+    let mut widths = fixed.to_vec();
+    // Strings are prefixed with their length
+    if let Some(len) = buffer.get(..4) {
+        let len = i32::from_le_bytes(len.try_into().unwrap());
+        if len > 0 {
+            widths.push(4 + len as usize);
+        }
+    }
+
+    widths.sort_unstable();
+    widths.dedup();
+    widths.retain(|x| *x <= buffer.len());
+    widths
+}
+
+fn fits_action(model: &dyn ModelDescription, rest: &[u8], guesses: usize) -> bool {
+    let (action, field) = match rest {
+        [] => return true,
+        [action, field @ ..] => (*action, field.first()),
+    };
+
+    let action = match PatchAction::from_u8(action) {
+        Some(PatchAction::Pop) => return true,
+        Some(action) => action,
+        None => return false,
+    };
+
+    let field = match field {
+        Some(field) => *field as usize,
+        None => return false,
+    };
+
+    if let Some(description) = model.get_field_description(field) {
+        return accepts_action(action, description);
+    }
+
+    match action {
+        PatchAction::AssignField if guesses > 0 => {
+            let value = &rest[2..];
+            value_widths(value)
+                .into_iter()
+                .any(|x| fits_action(model, &value[x..], guesses - 1))
+        }
+        PatchAction::AssignField => false,
+        // Other actions on unknown fields can't be checked any further
+        _ => true,
+    }
+}
+
+/// Whether the action can be sent for a field of this shape
+fn accepts_action(action: PatchAction, field: &FieldDescription) -> bool {
+    let is_model = matches!(field.field_type, FieldType::Model | FieldType::TypeModel(_));
+    match (action, field.value_type) {
+        (PatchAction::Pop, _) => true,
+        (PatchAction::AssignField, ValueType::Value) => !is_model,
+        (PatchAction::PushField | PatchAction::PushCreateAndAssignField, ValueType::Value) => {
+            is_model
+        }
+        (PatchAction::ResetField, ValueType::Value) => is_model || field.optional,
+        (
+            PatchAction::AssignKey,
+            ValueType::Map { .. } | ValueType::List | ValueType::Array { .. },
+        ) => !is_model,
+        (
+            PatchAction::PushKey | PatchAction::PushCreateAndAssignKey | PatchAction::ResetKey,
+            ValueType::Map { .. } | ValueType::List | ValueType::Array { .. },
+        ) => is_model,
+        (PatchAction::Insert, ValueType::List) => !is_model,
+        (PatchAction::PushCreateAndInsert, ValueType::List) => is_model,
+        (PatchAction::Remove, ValueType::Map { .. } | ValueType::List) => true,
+        (PatchAction::Swap, ValueType::Map { .. } | ValueType::List | ValueType::Array { .. }) => {
+            true
+        }
+        (PatchAction::Resize, ValueType::List | ValueType::Array { .. }) => true,
+        _ => false,
+    }
+}
+
+fn list_index(key: &MapKey) -> anyhow::Result<usize> {
+    key.index()
+        .with_context(|| format!("{} is not a valid list index", key))
+}
+
+/// The operations a patch is made of, each starts with this byte followed by the field index
 #[derive(FromPrimitive, Copy, Clone, Debug, Ord, PartialOrd, Eq, PartialEq)]
 #[repr(u8)]
-enum PatchAction {
+pub enum PatchAction {
     Pop = 1,
     AssignField = 2,
     PushField = 3,
@@ -379,3 +837,330 @@ enum PatchAction {
     Swap = 13,
     Resize = 14,
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::patcher::{PatchAction, Patcher};
+    use crate::{
+        Document, MapKey, Model, ModelBTreeMap, ModelCollection, ModelDescription, ModelHashMap,
+        ModelVec, PathAction, PathSubSegment, Selector,
+    };
+    use bytes::{BufMut, BytesMut};
+    use std::collections::BTreeMap;
+
+    #[derive(Debug, Default, Model)]
+    #[uncage(type = 0)]
+    struct Root {
+        #[uncage(index = 0)]
+        items: ModelVec<Item>,
+        #[uncage(index = 1)]
+        values: Vec<i32>,
+        #[uncage(index = 2)]
+        named: ModelBTreeMap<i32, Item>,
+    }
+
+    #[derive(Debug, Default, Model)]
+    #[uncage(type = 1)]
+    struct Item {
+        #[uncage(index = 0)]
+        value: i32,
+    }
+
+    #[derive(Debug, Default, Model)]
+    #[uncage(type = 2)]
+    struct Shapes {
+        #[uncage(index = 0)]
+        position: [f32; 2],
+        #[uncage(index = 1)]
+        grid: Vec<Vec<i32>>,
+        #[uncage(index = 2)]
+        owner: Option<u8>,
+    }
+
+    #[derive(Debug, Default, Model)]
+    #[uncage(type = 3)]
+    struct Keyed {
+        #[uncage(index = 0)]
+        names: BTreeMap<String, i32>,
+        #[uncage(index = 1)]
+        slots: ModelHashMap<u8, Item>,
+    }
+
+    #[derive(Debug, ModelCollection)]
+    enum Models {
+        Root(Root),
+        Item(Item),
+        Shapes(Shapes),
+        Keyed(Keyed),
+    }
+
+    fn op(buffer: &mut BytesMut, action: PatchAction, field: u8) {
+        buffer.put_u8(action as u8);
+        buffer.put_u8(field);
+    }
+
+    #[test]
+    pub fn test_list_operations_report_paths() {
+        let mut patcher: Patcher<Root, Models> = Patcher::new(Document::new());
+        patcher.add_selector(1, Selector::new().field(RootFields::Items).removed());
+        patcher.add_selector(2, Selector::new().field(RootFields::Values).created());
+        patcher.add_selector(3, Selector::new().field(RootFields::Items).mutated());
+        patcher.add_selector(4, Selector::new().field(RootFields::Named).removed());
+
+        let mut patch = BytesMut::new();
+        for index in 0..2 {
+            op(&mut patch, PatchAction::PushCreateAndInsert, 0);
+            patch.put_u8(1);
+            patch.put_i32_le(index);
+            patch.put_u8(PatchAction::Pop as u8);
+        }
+        op(&mut patch, PatchAction::PushCreateAndAssignKey, 2);
+        patch.put_u8(1);
+        patch.put_i32_le(42);
+        patch.put_u8(PatchAction::Pop as u8);
+        op(&mut patch, PatchAction::Resize, 1);
+        patch.put_i32_le(2);
+        op(&mut patch, PatchAction::Insert, 1);
+        patch.put_i32_le(1);
+        patch.put_i32_le(5);
+        op(&mut patch, PatchAction::Swap, 0);
+        patch.put_i32_le(0);
+        patch.put_i32_le(1);
+        op(&mut patch, PatchAction::Remove, 0);
+        patch.put_i32_le(0);
+        op(&mut patch, PatchAction::ResetKey, 2);
+        patch.put_i32_le(42);
+        op(&mut patch, PatchAction::Resize, 0);
+        patch.put_i32_le(0);
+
+        let matches = patcher.apply_patch(patch.freeze()).unwrap();
+        let found = matches
+            .iter()
+            .map(|x| {
+                let last = x.path.last().unwrap();
+                (x.selector_key, last.sub().clone(), last.action())
+            })
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            found,
+            vec![
+                (2, PathSubSegment::Index(1), PathAction::Created),
+                (3, PathSubSegment::Index(0), PathAction::Mutated),
+                (3, PathSubSegment::Index(1), PathAction::Mutated),
+                (1, PathSubSegment::Index(0), PathAction::Removed),
+                (4, PathSubSegment::Key(42.into()), PathAction::Removed),
+                (1, PathSubSegment::Index(0), PathAction::Removed),
+                (3, PathSubSegment::None, PathAction::Mutated),
+            ]
+        );
+
+        let root = patcher.document().root();
+        assert_eq!(root.values, vec![0, 5, 0]);
+        assert!(root.items().is_empty());
+        assert!(root.named().get(42).is_none());
+    }
+
+    #[test]
+    pub fn test_invalid_key_operations_fail() {
+        let mut patcher: Patcher<Root, Models> = Patcher::new(Document::new());
+        let mut patch = BytesMut::new();
+        op(&mut patch, PatchAction::PushCreateAndAssignKey, 2);
+        patch.put_u8(1);
+        patch.put_i32_le(1);
+        patch.put_u8(PatchAction::Pop as u8);
+        patcher.apply_patch(patch.freeze()).unwrap();
+
+        let mut patch = BytesMut::new();
+        op(&mut patch, PatchAction::Swap, 2);
+        patch.put_i32_le(1);
+        patch.put_i32_le(2);
+        assert!(patcher.apply_patch(patch.freeze()).is_err());
+
+        let root = patcher.document().root();
+        assert!(root.named().get(1).is_some());
+
+        let mut root = Root::default();
+        assert!(root
+            .swap_map_field(2, &MapKey::from("a"), &MapKey::Int(1))
+            .is_err());
+        assert!(root
+            .swap_map_field(2, &MapKey::Int(1), &MapKey::Int(i64::MAX))
+            .is_err());
+
+        for action in [PatchAction::Remove, PatchAction::Swap] {
+            let mut patcher: Patcher<Root, Models> = Patcher::new(Document::new());
+            let mut patch = BytesMut::new();
+            op(&mut patch, PatchAction::PushCreateAndInsert, 0);
+            patch.put_u8(1);
+            patch.put_i32_le(0);
+            op(&mut patch, action, 0);
+            patch.put_i32_le(0);
+            patch.put_i32_le(0);
+            assert!(patcher.apply_patch(patch.freeze()).is_err());
+        }
+    }
+
+    #[test]
+    pub fn test_arrays_nested_lists_and_options() {
+        let mut patcher: Patcher<Shapes, Models> = Patcher::new(Document::new());
+
+        let mut patch = BytesMut::new();
+        op(&mut patch, PatchAction::AssignKey, 0);
+        patch.put_i32_le(1);
+        patch.put_f32_le(2.5);
+        op(&mut patch, PatchAction::Insert, 1);
+        patch.put_i32_le(0);
+        patch.put_i32_le(2);
+        patch.put_i32_le(3);
+        patch.put_i32_le(4);
+        op(&mut patch, PatchAction::AssignField, 2);
+        patch.put_u8(7);
+        patcher.apply_patch(patch.freeze()).unwrap();
+
+        let shapes = patcher.document().root();
+        assert_eq!(shapes.position, [0.0, 2.5]);
+        assert_eq!(shapes.grid, vec![vec![3, 4]]);
+        assert_eq!(shapes.owner, Some(7));
+
+        let json = patcher.document().to_json(0);
+        assert_eq!(json["grid"][0][1], 4);
+        assert_eq!(json["owner"], 7);
+
+        let mut patch = BytesMut::new();
+        op(&mut patch, PatchAction::ResetField, 2);
+        patcher.apply_patch(patch.freeze()).unwrap();
+        assert_eq!(patcher.document().root().owner, None);
+
+        let mut patch = BytesMut::new();
+        op(&mut patch, PatchAction::Insert, 0);
+        patch.put_i32_le(0);
+        patch.put_f32_le(1.0);
+        assert!(patcher.apply_patch(patch.freeze()).is_err());
+    }
+
+    #[test]
+    pub fn test_string_and_u8_map_keys() {
+        let mut patcher: Patcher<Keyed, Models> = Patcher::new(Document::new());
+        patcher.add_selector(1, Selector::new().field(KeyedFields::Names).key("wood"));
+        patcher.add_selector(2, Selector::new().field(KeyedFields::Slots).key(3u8));
+
+        let mut patch = BytesMut::new();
+        for (name, value) in [("wood", 5), ("gold", 7)] {
+            op(&mut patch, PatchAction::AssignKey, 0);
+            patch.put_i32_le(name.len() as i32);
+            patch.put_slice(name.as_bytes());
+            patch.put_i32_le(value);
+        }
+        op(&mut patch, PatchAction::PushCreateAndAssignKey, 1);
+        patch.put_u8(1);
+        patch.put_u8(3);
+        op(&mut patch, PatchAction::AssignField, 0);
+        patch.put_i32_le(9);
+        patch.put_u8(PatchAction::Pop as u8);
+
+        let matches = patcher.apply_patch(patch.freeze()).unwrap();
+        let keys = matches.iter().map(|x| x.selector_key).collect::<Vec<_>>();
+        assert_eq!(keys, vec![1, 2, 2, 2]);
+
+        let document = patcher.document();
+        let keyed = document.root();
+        assert_eq!(keyed.names["gold"], 7);
+        assert_eq!(keyed.slots().get(3u8).unwrap().value, 9);
+
+        let json = document.to_json(0);
+        assert_eq!(json["names"]["wood"], 5);
+        assert_eq!(json["slots"]["3"]["value"], 9);
+
+        let mut copy: Patcher<Keyed, Models> = Patcher::new(Document::new());
+        copy.apply_patch(document.to_patch(0).unwrap()).unwrap();
+        assert_eq!(copy.document().to_json(0), json);
+    }
+
+    #[test]
+    pub fn test_tolerant_mode_skips_unknown_fields_and_models() {
+        let mut patch = BytesMut::new();
+        op(&mut patch, PatchAction::AssignField, 9);
+        patch.put_i32_le(1234);
+        op(&mut patch, PatchAction::Resize, 1);
+        patch.put_i32_le(1);
+        op(&mut patch, PatchAction::PushCreateAndAssignKey, 2);
+        patch.put_u8(42);
+        patch.put_i32_le(7);
+        op(&mut patch, PatchAction::AssignField, 0);
+        patch.put_f32_le(1.5);
+        patch.put_u8(PatchAction::Pop as u8);
+        op(&mut patch, PatchAction::AssignKey, 1);
+        patch.put_i32_le(0);
+        patch.put_i32_le(5);
+        let patch = patch.freeze();
+
+        let mut strict: Patcher<Root, Models> = Patcher::new(Document::new());
+        assert!(strict.apply_patch(patch.clone()).is_err());
+
+        let mut patcher: Patcher<Root, Models> = Patcher::new(Document::new());
+        patcher.set_tolerant(true);
+        patcher.apply_patch(patch).unwrap();
+        assert_eq!(patcher.document().root().values, vec![5]);
+        assert!(patcher.document().root().named.contains_key(&7));
+
+        // A single byte could be followed by either action, so the rest of the patch is dropped
+        let mut patch = BytesMut::new();
+        op(&mut patch, PatchAction::AssignField, 9);
+        patch.put_u8(1);
+        op(&mut patch, PatchAction::AssignField, 9);
+        patch.put_u8(1);
+        patcher.apply_patch(patch.freeze()).unwrap();
+
+        let skipped = patcher.skipped();
+        assert_eq!(skipped.unknown_fields[&(0, 9)], 2);
+        assert_eq!(skipped.unknown_fields[&(42, 0)], 1);
+        assert_eq!(skipped.unknown_models[&42], 1);
+        assert_eq!(skipped.truncated_patches, 1);
+    }
+
+    #[test]
+    pub fn test_tolerant_mode_skips_string_keys_of_unknown_maps() {
+        fn key(buffer: &mut BytesMut, key: &str) {
+            buffer.put_i32_le(key.len() as i32);
+            buffer.put_slice(key.as_bytes());
+        }
+
+        let mut patch = BytesMut::new();
+        op(&mut patch, PatchAction::PushCreateAndAssignKey, 8);
+        patch.put_u8(1);
+        key(&mut patch, "scout");
+        op(&mut patch, PatchAction::AssignField, 0);
+        patch.put_i32_le(3);
+        patch.put_u8(PatchAction::Pop as u8);
+        op(&mut patch, PatchAction::PushKey, 8);
+        key(&mut patch, "scout");
+        op(&mut patch, PatchAction::AssignField, 0);
+        patch.put_i32_le(4);
+        patch.put_u8(PatchAction::Pop as u8);
+        op(&mut patch, PatchAction::AssignKey, 9);
+        key(&mut patch, "speed");
+        patch.put_f32_le(1.5);
+        op(&mut patch, PatchAction::Swap, 8);
+        key(&mut patch, "scout");
+        key(&mut patch, "archer");
+        op(&mut patch, PatchAction::Remove, 8);
+        key(&mut patch, "archer");
+        op(&mut patch, PatchAction::Resize, 1);
+        patch.put_i32_le(1);
+        op(&mut patch, PatchAction::AssignKey, 1);
+        patch.put_i32_le(0);
+        patch.put_i32_le(5);
+
+        let mut patcher: Patcher<Root, Models> = Patcher::new(Document::new());
+        patcher.set_tolerant(true);
+        patcher.apply_patch(patch.freeze()).unwrap();
+        assert_eq!(patcher.document().root().values, vec![5]);
+
+        let skipped = patcher.skipped();
+        assert_eq!(skipped.truncated_patches, 0);
+        assert_eq!(skipped.unknown_fields[&(0, 8)], 4);
+        assert_eq!(skipped.unknown_fields[&(0, 9)], 1);
+        assert!(patcher.unknown_children.is_empty());
+    }
+}
@@ -1,4 +1,4 @@
-use crate::FieldDescription;
+use crate::{FieldDescription, MapKey};
 
 #[derive(Clone, Debug)]
 pub struct Path {
@@ -28,7 +28,7 @@ impl Path {
         &mut self,
         model_type: usize,
         field: &'static FieldDescription,
-        key: i32,
+        key: MapKey,
     ) {
         self.items
             .push(PathSegment::map_field(model_type, field, key));
@@ -49,9 +49,23 @@ impl Path {
             x.action = action
         }
     }
+
+    pub fn segments(&self) -> &[PathSegment] {
+        &self.items
+    }
+
+    pub fn last(&self) -> Option<&PathSegment> {
+        self.items.last()
+    }
+}
+
+impl Default for Path {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Clone, Debug)]
 pub struct PathSegment {
     pub(crate) model_type: usize,
     pub(crate) field: &'static FieldDescription,
@@ -69,7 +83,11 @@ impl PathSegment {
         }
     }
 
-    pub fn map_field(model_type: usize, field: &'static FieldDescription, key: i32) -> PathSegment {
+    pub fn map_field(
+        model_type: usize,
+        field: &'static FieldDescription,
+        key: MapKey,
+    ) -> PathSegment {
         PathSegment {
             model_type,
             field,
@@ -90,16 +108,32 @@ impl PathSegment {
             action: PathAction::None,
         }
     }
+
+    pub fn model_type(&self) -> usize {
+        self.model_type
+    }
+
+    pub fn field_description(&self) -> &'static FieldDescription {
+        self.field
+    }
+
+    pub fn sub(&self) -> &PathSubSegment {
+        &self.sub
+    }
+
+    pub fn action(&self) -> PathAction {
+        self.action
+    }
 }
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Clone, Debug, Eq, PartialEq)]
 pub enum PathSubSegment {
     None,
-    Key(i32),
+    Key(MapKey),
     Index(usize),
 }
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
 pub enum PathAction {
     None,
     Created,
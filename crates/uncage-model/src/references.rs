@@ -75,14 +75,14 @@ impl<T: Model> Reference for ModelRef<T> {
     fn empty() -> Self {
         ModelRef {
             inner: Ref::empty(),
-            _pd: PhantomData::default(),
+            _pd: PhantomData,
         }
     }
 
     fn from_id(id: usize) -> Self {
         ModelRef {
             inner: Ref::from_id(id),
-            _pd: PhantomData::default(),
+            _pd: PhantomData,
         }
     }
 
@@ -0,0 +1,413 @@
+use crate::patcher::{Patcher, PatcherSelectorMatch};
+use crate::schema::{
+    Container, DynamicModel, DynamicModels, DynamicSchema, FieldSchema, ModelSchema, Schema,
+};
+use crate::{Document, Selector};
+use anyhow::{bail, Context};
+use bytes::Bytes;
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RegistryDefinition {
+    pub base: BaseDefinition,
+    #[serde(default)]
+    pub versions: Vec<VersionDefinition>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BaseDefinition {
+    pub build: u32,
+    pub schema: Schema,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VersionDefinition {
+    pub build: u32,
+    #[serde(default)]
+    pub changes: Vec<SchemaChange>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "change", rename_all = "snake_case")]
+pub enum SchemaChange {
+    AddModel {
+        model: ModelSchema,
+    },
+    RemoveModel {
+        model: String,
+    },
+    AddField {
+        model: String,
+        field: FieldSchema,
+    },
+    RemoveField {
+        model: String,
+        field: String,
+    },
+    RenameField {
+        model: String,
+        field: String,
+        to: String,
+    },
+    MoveField {
+        model: String,
+        field: String,
+        index: usize,
+    },
+    RetypeField {
+        model: String,
+        field: String,
+        #[serde(rename = "type")]
+        field_type: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        container: Option<Container>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        key: Option<String>,
+    },
+}
+
+impl SchemaChange {
+    pub fn apply(&self, schema: &mut Schema) -> anyhow::Result<()> {
+        match self {
+            SchemaChange::AddModel { model } => {
+                if schema.model(&model.name).is_some() {
+                    bail!("Model {} already exists", model.name);
+                }
+
+                schema.models.push(model.clone());
+            }
+            SchemaChange::RemoveModel { model } => {
+                let len = schema.models.len();
+                schema.models.retain(|x| &x.name != model);
+                if len == schema.models.len() {
+                    bail!("Can't remove unknown model {}", model);
+                }
+            }
+            SchemaChange::AddField { model, field } => {
+                let target = model_mut(schema, model)?;
+                if target.fields.iter().any(|x| x.name == field.name) {
+                    bail!("Field {}.{} already exists", model, field.name);
+                }
+
+                target.fields.push(field.clone());
+            }
+            SchemaChange::RemoveField { model, field } => {
+                let target = model_mut(schema, model)?;
+                let len = target.fields.len();
+                target.fields.retain(|x| &x.name != field);
+                if len == target.fields.len() {
+                    bail!("Can't remove unknown field {}.{}", model, field);
+                }
+            }
+            SchemaChange::RenameField { model, field, to } => {
+                field_mut(schema, model, field)?.name = to.clone();
+            }
+            SchemaChange::MoveField {
+                model,
+                field,
+                index,
+            } => {
+                field_mut(schema, model, field)?.index = *index;
+            }
+            SchemaChange::RetypeField {
+                model,
+                field,
+                field_type,
+                container,
+                key,
+            } => {
+                let target = field_mut(schema, model, field)?;
+                target.field_type = field_type.clone();
+                if let Some(container) = container {
+                    target.container = *container;
+                }
+
+                if key.is_some() {
+                    target.key = key.clone();
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn model_mut<'s>(schema: &'s mut Schema, model: &str) -> anyhow::Result<&'s mut ModelSchema> {
+    schema
+        .models
+        .iter_mut()
+        .find(|x| x.name == model)
+        .with_context(|| format!("Unknown model {}", model))
+}
+
+fn field_mut<'s>(
+    schema: &'s mut Schema,
+    model: &str,
+    field: &str,
+) -> anyhow::Result<&'s mut FieldSchema> {
+    model_mut(schema, model)?
+        .fields
+        .iter_mut()
+        .find(|x| x.name == field)
+        .with_context(|| format!("Unknown field {}.{}", model, field))
+}
+
+/// Maps game builds to schemas, a build uses the base schema with the changes of every version up
+/// to and including its own build applied
+#[derive(Debug)]
+pub struct SchemaRegistry {
+    base_build: u32,
+    base: Schema,
+    versions: BTreeMap<u32, Vec<SchemaChange>>,
+    compiled: RefCell<BTreeMap<u32, &'static DynamicSchema>>,
+}
+
+impl SchemaRegistry {
+    pub fn new(base_build: u32, base: Schema) -> SchemaRegistry {
+        SchemaRegistry {
+            base_build,
+            base,
+            versions: BTreeMap::new(),
+            compiled: RefCell::new(BTreeMap::new()),
+        }
+    }
+
+    pub fn from_definition(definition: RegistryDefinition) -> anyhow::Result<SchemaRegistry> {
+        let mut registry = SchemaRegistry::new(definition.base.build, definition.base.schema);
+        for version in definition.versions {
+            registry.add_version(version.build, version.changes)?;
+        }
+
+        Ok(registry)
+    }
+
+    pub fn from_json(input: &str) -> anyhow::Result<SchemaRegistry> {
+        SchemaRegistry::from_definition(
+            serde_json::from_str(input).context("Failed to parse JSON registry")?,
+        )
+    }
+
+    pub fn from_toml(input: &str) -> anyhow::Result<SchemaRegistry> {
+        SchemaRegistry::from_definition(
+            toml::from_str(input).context("Failed to parse TOML registry")?,
+        )
+    }
+
+    pub fn load<P: AsRef<std::path::Path>>(path: P) -> anyhow::Result<SchemaRegistry> {
+        let path = path.as_ref();
+        let input = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read schema registry {}", path.display()))?;
+
+        match path.extension().and_then(|x| x.to_str()) {
+            Some("toml") => SchemaRegistry::from_toml(&input),
+            _ => SchemaRegistry::from_json(&input),
+        }
+    }
+
+    pub fn add_version(&mut self, build: u32, changes: Vec<SchemaChange>) -> anyhow::Result<()> {
+        if build <= self.base_build {
+            bail!(
+                "Version {} has to be newer than the base build {}",
+                build,
+                self.base_build
+            );
+        }
+
+        if self.versions.insert(build, changes).is_some() {
+            bail!("Version {} is defined twice", build);
+        }
+
+        self.compiled.borrow_mut().clear();
+        Ok(())
+    }
+
+    pub fn base_build(&self) -> u32 {
+        self.base_build
+    }
+
+    pub fn builds(&self) -> Vec<u32> {
+        std::iter::once(self.base_build)
+            .chain(self.versions.keys().copied())
+            .collect()
+    }
+
+    /// The build of the newest version that applies to the given build
+    pub fn effective_build(&self, build: u32) -> anyhow::Result<u32> {
+        if build < self.base_build {
+            bail!(
+                "Build {} predates the oldest known schema (build {})",
+                build,
+                self.base_build
+            );
+        }
+
+        Ok(self
+            .versions
+            .range(..=build)
+            .next_back()
+            .map_or(self.base_build, |(x, _)| *x))
+    }
+
+    pub fn schema_for(&self, build: u32) -> anyhow::Result<Schema> {
+        let effective = self.effective_build(build)?;
+        let mut schema = self.base.clone();
+        for (version, changes) in self.versions.range(..=effective) {
+            for change in changes {
+                change
+                    .apply(&mut schema)
+                    .with_context(|| format!("Failed to apply changes of build {}", version))?;
+            }
+        }
+
+        Ok(schema)
+    }
+
+    /// Compiled schema for the given build, compiled once per version
+    pub fn resolve(&self, build: u32) -> anyhow::Result<&'static DynamicSchema> {
+        let effective = self.effective_build(build)?;
+        if let Some(schema) = self.compiled.borrow().get(&effective) {
+            return Ok(schema);
+        }
+
+        let schema = self
+            .schema_for(effective)?
+            .compile()
+            .with_context(|| format!("Invalid schema for build {}", effective))?;
+        self.compiled.borrow_mut().insert(effective, schema);
+        Ok(schema)
+    }
+}
+
+/// A patcher for dynamic models that keeps the schema of its recording active while patching
+#[derive(Debug)]
+pub struct VersionedPatcher {
+    build: u32,
+    schema: &'static DynamicSchema,
+    patcher: Patcher<DynamicModel, DynamicModels>,
+}
+
+impl VersionedPatcher {
+    pub fn new(registry: &SchemaRegistry, build: u32) -> anyhow::Result<VersionedPatcher> {
+        let schema = registry.resolve(build)?;
+        let _guard = schema.enter();
+
+        Ok(VersionedPatcher {
+            build,
+            schema,
+            patcher: Patcher::new(Document::new()),
+        })
+    }
+
+    pub fn build(&self) -> u32 {
+        self.build
+    }
+
+    pub fn schema(&self) -> &'static DynamicSchema {
+        self.schema
+    }
+
+    pub fn add_selector(&mut self, key: usize, selector: Selector) {
+        self.patcher.add_selector(key, selector)
+    }
+
+    pub fn document(&self) -> &Document<DynamicModel, DynamicModels> {
+        self.patcher.document()
+    }
+
+    pub fn to_json(&self, id: usize) -> serde_json::Value {
+        let _guard = self.schema.enter();
+        self.patcher.document().to_json(id)
+    }
+
+    pub fn apply_patch(&mut self, buffer: Bytes) -> anyhow::Result<Vec<PatcherSelectorMatch>> {
+        let _guard = self.schema.enter();
+        self.patcher.apply_patch(buffer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::registry::{SchemaRegistry, VersionedPatcher};
+    use crate::FieldType;
+    use bytes::{BufMut, BytesMut};
+
+    const REGISTRY: &str = r#"{
+        "base": {
+            "build": 100,
+            "schema": {
+                "root": "Root",
+                "models": [
+                    { "name": "Root", "type": 0, "fields": [
+                        { "index": 0, "name": "time", "type": "i32" },
+                        { "index": 1, "name": "speed", "type": "i32" }
+                    ] }
+                ]
+            }
+        },
+        "versions": [
+            { "build": 200, "changes": [
+                { "change": "retype_field", "model": "Root", "field": "speed", "type": "f32" },
+                { "change": "add_field", "model": "Root", "field": { "index": 2, "name": "paused", "type": "bool" } }
+            ] },
+            { "build": 300, "changes": [
+                { "change": "remove_field", "model": "Root", "field": "time" }
+            ] }
+        ]
+    }"#;
+
+    #[test]
+    pub fn test_registry_resolves_builds() {
+        let registry = SchemaRegistry::from_json(REGISTRY).unwrap();
+
+        assert!(registry.resolve(99).is_err());
+        assert_eq!(registry.effective_build(250).unwrap(), 200);
+
+        let base = registry.resolve(150).unwrap().model(0).unwrap();
+        assert_eq!(base.fields().len(), 2);
+        assert_eq!(base.field(1).unwrap().field_type, FieldType::Int32);
+
+        let middle = registry.resolve(250).unwrap().model(0).unwrap();
+        assert_eq!(middle.field(1).unwrap().field_type, FieldType::Float);
+        assert!(middle.field(2).is_some());
+        assert!(std::ptr::eq(
+            middle,
+            registry.resolve(200).unwrap().model(0).unwrap()
+        ));
+
+        let latest = registry.resolve(u32::MAX).unwrap().model(0).unwrap();
+        assert!(latest.field(0).is_none());
+    }
+
+    #[test]
+    pub fn test_versioned_patcher_reads_payloads_of_their_build() {
+        let registry = SchemaRegistry::from_json(REGISTRY).unwrap();
+
+        // Before build 200 speed is an i32 and paused doesn't exist yet
+        let mut old = VersionedPatcher::new(&registry, 150).unwrap();
+        let mut patch = BytesMut::new();
+        patch.put_slice(&[2, 1]);
+        patch.put_i32_le(3);
+        patch.put_slice(&[2, 0]);
+        patch.put_i32_le(1200);
+        old.apply_patch(patch.freeze()).unwrap();
+        let root = old.to_json(0);
+        assert_eq!(root["time"], 1200);
+        assert_eq!(root["speed"], 3);
+
+        let mut patch = BytesMut::new();
+        patch.put_slice(&[2, 2, 1]);
+        assert!(old.apply_patch(patch.freeze()).is_err());
+
+        let mut new = VersionedPatcher::new(&registry, 250).unwrap();
+        let mut patch = BytesMut::new();
+        patch.put_slice(&[2, 1]);
+        patch.put_f32_le(1.5);
+        patch.put_slice(&[2, 2, 1]);
+        new.apply_patch(patch.freeze()).unwrap();
+        let root = new.to_json(0);
+        assert_eq!(root["speed"], 1.5);
+        assert_eq!(root["paused"], true);
+    }
+}
@@ -0,0 +1,1105 @@
+use crate::{
+    FieldDescription, FieldMetadata, FieldType, MapKey, Model, ModelCollection, ModelDescription,
+    Ref, ValueType,
+};
+use anyhow::{bail, Context};
+use bytes::{Buf, BufMut};
+use serde::{Deserialize, Serialize};
+use std::any::{Any, TypeId};
+use std::cell::Cell;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::fmt;
+use std::fmt::{Debug, Formatter};
+use std::sync::{LazyLock, Mutex};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Schema {
+    pub root: String,
+    pub models: Vec<ModelSchema>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ModelSchema {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub model_type: usize,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub extends: Option<String>,
+    #[serde(default)]
+    pub fields: Vec<FieldSchema>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FieldSchema {
+    pub index: usize,
+    pub name: String,
+    #[serde(rename = "type")]
+    pub field_type: String,
+    #[serde(default)]
+    pub container: Container,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key: Option<String>,
+    /// Length of an `array` container
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub len: Option<usize>,
+    #[serde(default)]
+    pub autofill: bool,
+    /// Name of the Rust enum the integer decodes into, dynamic models keep the raw integer
+    #[serde(default, rename = "enum", skip_serializing_if = "Option::is_none")]
+    pub enumeration: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub unit: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub deprecated: bool,
+    #[serde(default)]
+    pub hidden_info: bool,
+    #[serde(default)]
+    pub interpolatable: bool,
+}
+
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Container {
+    #[default]
+    Value,
+    List,
+    Array,
+    Map,
+}
+
+impl Schema {
+    pub fn from_json(input: &str) -> anyhow::Result<Schema> {
+        serde_json::from_str(input).context("Failed to parse JSON schema")
+    }
+
+    pub fn from_toml(input: &str) -> anyhow::Result<Schema> {
+        toml::from_str(input).context("Failed to parse TOML schema")
+    }
+
+    pub fn load<P: AsRef<std::path::Path>>(path: P) -> anyhow::Result<Schema> {
+        let path = path.as_ref();
+        let input = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read schema {}", path.display()))?;
+
+        match path.extension().and_then(|x| x.to_str()) {
+            Some("toml") => Schema::from_toml(&input),
+            _ => Schema::from_json(&input),
+        }
+    }
+
+    pub fn model(&self, name: &str) -> Option<&ModelSchema> {
+        self.models.iter().find(|x| x.name == name)
+    }
+
+    /// Validates the schema and turns it into a [DynamicSchema]. Field descriptions are handed out
+    /// as `&'static`, so the result lives for the rest of the program, compiling an equal schema
+    /// again returns the one compiled before
+    pub fn compile(&self) -> anyhow::Result<&'static DynamicSchema> {
+        let mut compiled = COMPILED.lock().unwrap();
+        if let Some((_, schema)) = compiled.iter().find(|(x, _)| x == self) {
+            return Ok(schema);
+        }
+
+        let schema: &'static DynamicSchema = Box::leak(Box::new(self.build()?));
+        compiled.push((self.clone(), schema));
+        Ok(schema)
+    }
+
+    fn build(&self) -> anyhow::Result<DynamicSchema> {
+        let mut types = HashMap::new();
+        for model in &self.models {
+            if let Some(other) = types.insert(model.name.as_str(), model.model_type) {
+                bail!(
+                    "Model {} is defined twice (types {} and {})",
+                    model.name,
+                    other,
+                    model.model_type
+                );
+            }
+        }
+
+        let mut seen_types = HashSet::new();
+        for model in &self.models {
+            if !seen_types.insert(model.model_type) {
+                bail!(
+                    "Model type {} is used by more than one model",
+                    model.model_type
+                );
+            }
+        }
+
+        let root = *types
+            .get(self.root.as_str())
+            .with_context(|| format!("Root model {} is not defined", self.root))?;
+
+        let mut models = HashMap::new();
+        for model in &self.models {
+            let mut fields = vec![];
+            self.collect_fields(model, &types, &mut fields, &mut vec![])?;
+
+            let mut slots = HashMap::new();
+            for (slot, field) in fields.iter().enumerate() {
+                if slots.insert(field.index, slot).is_some() {
+                    bail!(
+                        "Field index {} is used twice in model {}",
+                        field.index,
+                        model.name
+                    );
+                }
+            }
+
+            let parent = match &model.extends {
+                Some(parent) => Some(*types.get(parent.as_str()).with_context(|| {
+                    format!("Model {} extends unknown model {}", model.name, parent)
+                })?),
+                None => None,
+            };
+
+            models.insert(
+                model.model_type,
+                DynamicModelSchema {
+                    model_type: model.model_type,
+                    name: intern(&model.name),
+                    parent,
+                    fields,
+                    slots,
+                },
+            );
+        }
+
+        Ok(DynamicSchema { root, models })
+    }
+
+    fn collect_fields<'s>(
+        &'s self,
+        model: &'s ModelSchema,
+        types: &HashMap<&str, usize>,
+        fields: &mut Vec<FieldDescription>,
+        visited: &mut Vec<&'s str>,
+    ) -> anyhow::Result<()> {
+        if visited.contains(&model.name.as_str()) {
+            bail!("Model {} extends itself", model.name);
+        }
+
+        visited.push(&model.name);
+
+        if let Some(parent) = &model.extends {
+            let parent = self.model(parent).with_context(|| {
+                format!("Model {} extends unknown model {}", model.name, parent)
+            })?;
+            self.collect_fields(parent, types, fields, visited)?;
+        }
+
+        let model_name = intern(&model.name);
+        for field in &model.fields {
+            let field_type = parse_field_type(&field.field_type, types).with_context(|| {
+                format!(
+                    "Unknown type {} on {}.{}",
+                    field.field_type, model.name, field.name
+                )
+            })?;
+
+            let value_type = match field.container {
+                Container::Value => ValueType::Value,
+                Container::List => ValueType::List,
+                Container::Array => ValueType::Array {
+                    len: field.len.with_context(|| {
+                        format!("Array {}.{} needs a len", model.name, field.name)
+                    })?,
+                },
+                Container::Map => {
+                    let key = field.key.as_deref().unwrap_or("i32");
+                    let key = parse_field_type(key, types)
+                        .filter(FieldType::is_map_key)
+                        .with_context(|| {
+                            format!("Unknown key type {} on {}.{}", key, model.name, field.name)
+                        })?;
+                    ValueType::Map { key }
+                }
+            };
+
+            fields.push(FieldDescription {
+                value_type,
+                field_type,
+                index: field.index,
+                model_type: model.model_type,
+                model_name,
+                field_name: intern(&field.name),
+                autofill: field.autofill,
+                enumeration: None,
+                optional: false,
+                nested: false,
+                metadata: FieldMetadata {
+                    unit: field.unit.as_deref().map(intern),
+                    description: field.description.as_deref().map(intern),
+                    deprecated: field.deprecated,
+                    hidden_info: field.hidden_info,
+                    interpolatable: field.interpolatable,
+                },
+            });
+        }
+
+        Ok(())
+    }
+}
+
+/// Schemas compiled so far with what they compiled to
+static COMPILED: Mutex<Vec<(Schema, &'static DynamicSchema)>> = Mutex::new(vec![]);
+
+/// Schemas of Rust model collections, by the type of the collection
+static COLLECTIONS: Mutex<Vec<(TypeId, &'static DynamicSchema)>> = Mutex::new(vec![]);
+
+/// Names of models and fields, and field metadata, of compiled schemas
+static NAMES: Mutex<BTreeSet<&'static str>> = Mutex::new(BTreeSet::new());
+
+/// Leaks every distinct string once, schemas of different builds share most of their names
+fn intern(input: &str) -> &'static str {
+    let mut names = NAMES.lock().unwrap();
+    if let Some(name) = names.get(input) {
+        return name;
+    }
+
+    let name = Box::leak(input.to_string().into_boxed_str());
+    names.insert(name);
+    name
+}
+
+fn parse_field_type(name: &str, types: &HashMap<&str, usize>) -> Option<FieldType> {
+    Some(match name {
+        "bool" => FieldType::Boolean,
+        "u8" => FieldType::UInt8,
+        "i8" => FieldType::Int8,
+        "u16" => FieldType::UInt16,
+        "i16" => FieldType::Int16,
+        "u32" => FieldType::UInt32,
+        "i32" => FieldType::Int32,
+        "u64" => FieldType::UInt64,
+        "i64" => FieldType::Int64,
+        "u128" => FieldType::UInt128,
+        "i128" => FieldType::Int128,
+        "f32" => FieldType::Float,
+        "f64" => FieldType::Double,
+        "string" | "String" => FieldType::String,
+        "ref" | "Ref" => FieldType::Model,
+        model => FieldType::TypeModel(*types.get(model)?),
+    })
+}
+
+#[derive(Debug)]
+pub struct DynamicSchema {
+    root: usize,
+    models: HashMap<usize, DynamicModelSchema>,
+}
+
+#[derive(Debug)]
+pub struct DynamicModelSchema {
+    model_type: usize,
+    name: &'static str,
+    parent: Option<usize>,
+    fields: Vec<FieldDescription>,
+    slots: HashMap<usize, usize>,
+}
+
+/// What models are created from when no schema is active or the active one doesn't know the model
+/// type. Without fields, patching them fails in the patcher instead of panicking
+static UNKNOWN_MODEL: LazyLock<DynamicModelSchema> = LazyLock::new(|| DynamicModelSchema {
+    model_type: usize::MAX,
+    name: "Unknown",
+    parent: None,
+    fields: vec![],
+    slots: HashMap::new(),
+});
+
+thread_local! {
+    static ACTIVE_SCHEMA: Cell<Option<&'static DynamicSchema>> = const { Cell::new(None) };
+}
+
+/// Restores the previously active schema when dropped
+pub struct SchemaGuard {
+    previous: Option<&'static DynamicSchema>,
+}
+
+impl Drop for SchemaGuard {
+    fn drop(&mut self) {
+        ACTIVE_SCHEMA.with(|x| x.set(self.previous));
+    }
+}
+
+impl DynamicSchema {
+    pub fn active() -> Option<&'static DynamicSchema> {
+        ACTIVE_SCHEMA.with(|x| x.get())
+    }
+
+    /// Makes this schema the one [DynamicModels] creates models from on the current thread, it has
+    /// to stay active while a `Document` or `Patcher` using dynamic models is created or patched.
+    /// Models created without an active schema have no fields
+    pub fn enter(&'static self) -> SchemaGuard {
+        SchemaGuard {
+            previous: ACTIVE_SCHEMA.with(|x| x.replace(Some(self))),
+        }
+    }
+
+    pub fn root(&self) -> usize {
+        self.root
+    }
+
+    pub fn model(&self, model_type: usize) -> Option<&DynamicModelSchema> {
+        self.models.get(&model_type)
+    }
+
+    pub fn model_by_name(&self, name: &str) -> Option<&DynamicModelSchema> {
+        self.models.values().find(|x| x.name == name)
+    }
+
+    pub fn models(&self) -> impl Iterator<Item = &DynamicModelSchema> {
+        self.models.values()
+    }
+
+    pub fn create_model(&'static self, model_type: usize) -> Option<DynamicModel> {
+        self.models.get(&model_type).map(DynamicModel::new)
+    }
+
+    /// Describes a collection of Rust models with their own field descriptions, so their layout
+    /// can be used where a schema is expected, like as the target of a migration
+    pub fn from_collection<R: Model, C: ModelCollection + 'static>() -> &'static DynamicSchema {
+        let mut collections = COLLECTIONS.lock().unwrap();
+        if let Some((_, schema)) = collections.iter().find(|(x, _)| *x == TypeId::of::<C>()) {
+            return schema;
+        }
+
+        let mut models = HashMap::new();
+        for model_type in C::model_types() {
+            let model = C::create_model(model_type).boxed();
+            let mut chain = vec![];
+            let mut current = Some(model.as_ref());
+            while let Some(model) = current {
+                chain.push(model);
+                current = model.get_parent();
+            }
+
+            // Parents first, like the fields of a compiled schema
+            let fields = chain
+                .iter()
+                .rev()
+                .flat_map(|model| {
+                    let model_type = model.get_model_type();
+                    model
+                        .get_fields()
+                        .into_iter()
+                        .filter(move |x| x.model_type == model_type)
+                })
+                .collect::<Vec<_>>();
+            let slots = fields
+                .iter()
+                .enumerate()
+                .map(|(slot, field)| (field.index, slot))
+                .collect();
+
+            models.insert(
+                model_type,
+                DynamicModelSchema {
+                    model_type,
+                    name: model.get_model_name(),
+                    parent: model.get_parent().map(|x| x.get_model_type()),
+                    fields,
+                    slots,
+                },
+            );
+        }
+
+        let schema: &'static DynamicSchema = Box::leak(Box::new(DynamicSchema {
+            root: R::model_type(),
+            models,
+        }));
+        collections.push((TypeId::of::<C>(), schema));
+        schema
+    }
+}
+
+impl DynamicModelSchema {
+    pub fn model_type(&self) -> usize {
+        self.model_type
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    pub fn parent(&self) -> Option<usize> {
+        self.parent
+    }
+
+    pub fn fields(&self) -> &[FieldDescription] {
+        &self.fields
+    }
+
+    pub fn field(&self, index: usize) -> Option<&FieldDescription> {
+        self.slots.get(&index).map(|x| &self.fields[*x])
+    }
+
+    pub fn field_by_name(&self, name: &str) -> Option<&FieldDescription> {
+        self.fields.iter().find(|x| x.field_name == name)
+    }
+}
+
+#[derive(Debug)]
+pub enum DynamicValue {
+    Int8(i8),
+    UInt8(u8),
+    Int16(i16),
+    UInt16(u16),
+    Int32(i32),
+    UInt32(u32),
+    Int64(i64),
+    UInt64(u64),
+    Int128(i128),
+    UInt128(u128),
+    Float(f32),
+    Double(f64),
+    String(String),
+    Boolean(bool),
+    Ref(Ref),
+}
+
+impl DynamicValue {
+    pub fn new(field_type: &FieldType) -> DynamicValue {
+        match field_type {
+            FieldType::Int8 => DynamicValue::Int8(0),
+            FieldType::UInt8 => DynamicValue::UInt8(0),
+            FieldType::Int16 => DynamicValue::Int16(0),
+            FieldType::UInt16 => DynamicValue::UInt16(0),
+            FieldType::Int32 => DynamicValue::Int32(0),
+            FieldType::UInt32 => DynamicValue::UInt32(0),
+            FieldType::Int64 => DynamicValue::Int64(0),
+            FieldType::UInt64 => DynamicValue::UInt64(0),
+            FieldType::Int128 => DynamicValue::Int128(0),
+            FieldType::UInt128 => DynamicValue::UInt128(0),
+            FieldType::Float => DynamicValue::Float(0.0),
+            FieldType::Double => DynamicValue::Double(0.0),
+            FieldType::String => DynamicValue::String(String::new()),
+            FieldType::Boolean => DynamicValue::Boolean(false),
+            FieldType::Model | FieldType::TypeModel(_) => DynamicValue::Ref(Ref::default()),
+        }
+    }
+
+    pub fn read<B: Buf>(field_type: &FieldType, from: &mut B) -> anyhow::Result<DynamicValue> {
+        let mut value = DynamicValue::new(field_type);
+        field_type.read(value.as_any_mut(), from)?;
+        Ok(value)
+    }
+
+    pub fn write<B: BufMut>(&self, to: &mut B) -> anyhow::Result<()> {
+        self.field_type().write(self.as_any(), to)
+    }
+
+    pub fn field_type(&self) -> FieldType {
+        match self {
+            DynamicValue::Int8(_) => FieldType::Int8,
+            DynamicValue::UInt8(_) => FieldType::UInt8,
+            DynamicValue::Int16(_) => FieldType::Int16,
+            DynamicValue::UInt16(_) => FieldType::UInt16,
+            DynamicValue::Int32(_) => FieldType::Int32,
+            DynamicValue::UInt32(_) => FieldType::UInt32,
+            DynamicValue::Int64(_) => FieldType::Int64,
+            DynamicValue::UInt64(_) => FieldType::UInt64,
+            DynamicValue::Int128(_) => FieldType::Int128,
+            DynamicValue::UInt128(_) => FieldType::UInt128,
+            DynamicValue::Float(_) => FieldType::Float,
+            DynamicValue::Double(_) => FieldType::Double,
+            DynamicValue::String(_) => FieldType::String,
+            DynamicValue::Boolean(_) => FieldType::Boolean,
+            DynamicValue::Ref(_) => FieldType::Model,
+        }
+    }
+
+    pub fn as_i128(&self) -> Option<i128> {
+        Some(match self {
+            DynamicValue::Int8(x) => *x as i128,
+            DynamicValue::UInt8(x) => *x as i128,
+            DynamicValue::Int16(x) => *x as i128,
+            DynamicValue::UInt16(x) => *x as i128,
+            DynamicValue::Int32(x) => *x as i128,
+            DynamicValue::UInt32(x) => *x as i128,
+            DynamicValue::Int64(x) => *x as i128,
+            DynamicValue::UInt64(x) => *x as i128,
+            DynamicValue::Int128(x) => *x,
+            DynamicValue::UInt128(x) => *x as i128,
+            DynamicValue::Boolean(x) => *x as i128,
+            _ => return None,
+        })
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            DynamicValue::Float(x) => Some(*x as f64),
+            DynamicValue::Double(x) => Some(*x),
+            x => x.as_i128().map(|x| x as f64),
+        }
+    }
+
+    pub fn from_i128(field_type: &FieldType, value: i128) -> Option<DynamicValue> {
+        Some(match field_type {
+            FieldType::Int8 => DynamicValue::Int8(value as i8),
+            FieldType::UInt8 => DynamicValue::UInt8(value as u8),
+            FieldType::Int16 => DynamicValue::Int16(value as i16),
+            FieldType::UInt16 => DynamicValue::UInt16(value as u16),
+            FieldType::Int32 => DynamicValue::Int32(value as i32),
+            FieldType::UInt32 => DynamicValue::UInt32(value as u32),
+            FieldType::Int64 => DynamicValue::Int64(value as i64),
+            FieldType::UInt64 => DynamicValue::UInt64(value as u64),
+            FieldType::Int128 => DynamicValue::Int128(value),
+            FieldType::UInt128 => DynamicValue::UInt128(value as u128),
+            FieldType::Float => DynamicValue::Float(value as f32),
+            FieldType::Double => DynamicValue::Double(value as f64),
+            FieldType::Boolean => DynamicValue::Boolean(value != 0),
+            _ => return None,
+        })
+    }
+
+    pub fn from_f64(field_type: &FieldType, value: f64) -> Option<DynamicValue> {
+        match field_type {
+            FieldType::Float => Some(DynamicValue::Float(value as f32)),
+            FieldType::Double => Some(DynamicValue::Double(value)),
+            field_type => DynamicValue::from_i128(field_type, value.round() as i128),
+        }
+    }
+
+    /// Converts this value to another field type, numbers are cast like `as` would
+    pub fn cast(self, field_type: &FieldType) -> Option<DynamicValue> {
+        match (self, field_type) {
+            (DynamicValue::String(x), FieldType::String) => Some(DynamicValue::String(x)),
+            (DynamicValue::Float(x), field_type) => DynamicValue::from_f64(field_type, x as f64),
+            (DynamicValue::Double(x), field_type) => DynamicValue::from_f64(field_type, x),
+            (value, field_type) => DynamicValue::from_i128(field_type, value.as_i128()?),
+        }
+    }
+
+    pub fn from_json(field_type: &FieldType, value: &serde_json::Value) -> Option<DynamicValue> {
+        match value {
+            serde_json::Value::Bool(x) => DynamicValue::Boolean(*x).cast(field_type),
+            serde_json::Value::String(x) => DynamicValue::String(x.clone()).cast(field_type),
+            serde_json::Value::Number(x) => match x.as_i64() {
+                Some(x) => DynamicValue::from_i128(field_type, x as i128),
+                None => DynamicValue::from_f64(field_type, x.as_f64()?),
+            },
+            _ => None,
+        }
+    }
+
+    pub fn as_any(&self) -> &dyn Any {
+        match self {
+            DynamicValue::Int8(x) => x,
+            DynamicValue::UInt8(x) => x,
+            DynamicValue::Int16(x) => x,
+            DynamicValue::UInt16(x) => x,
+            DynamicValue::Int32(x) => x,
+            DynamicValue::UInt32(x) => x,
+            DynamicValue::Int64(x) => x,
+            DynamicValue::UInt64(x) => x,
+            DynamicValue::Int128(x) => x,
+            DynamicValue::UInt128(x) => x,
+            DynamicValue::Float(x) => x,
+            DynamicValue::Double(x) => x,
+            DynamicValue::String(x) => x,
+            DynamicValue::Boolean(x) => x,
+            DynamicValue::Ref(x) => x,
+        }
+    }
+
+    pub fn as_any_mut(&mut self) -> &mut dyn Any {
+        match self {
+            DynamicValue::Int8(x) => x,
+            DynamicValue::UInt8(x) => x,
+            DynamicValue::Int16(x) => x,
+            DynamicValue::UInt16(x) => x,
+            DynamicValue::Int32(x) => x,
+            DynamicValue::UInt32(x) => x,
+            DynamicValue::Int64(x) => x,
+            DynamicValue::UInt64(x) => x,
+            DynamicValue::Int128(x) => x,
+            DynamicValue::UInt128(x) => x,
+            DynamicValue::Float(x) => x,
+            DynamicValue::Double(x) => x,
+            DynamicValue::String(x) => x,
+            DynamicValue::Boolean(x) => x,
+            DynamicValue::Ref(x) => x,
+        }
+    }
+
+    pub fn as_ref(&self) -> Option<&Ref> {
+        match self {
+            DynamicValue::Ref(x) => Some(x),
+            _ => None,
+        }
+    }
+
+    pub fn as_ref_mut(&mut self) -> Option<&mut Ref> {
+        match self {
+            DynamicValue::Ref(x) => Some(x),
+            _ => None,
+        }
+    }
+
+    fn into_ref(self) -> Option<Ref> {
+        match self {
+            DynamicValue::Ref(x) => Some(x),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum DynamicField {
+    Value(DynamicValue),
+    List(Vec<DynamicValue>),
+    Map(BTreeMap<MapKey, DynamicValue>),
+}
+
+impl DynamicField {
+    fn new(description: &FieldDescription) -> DynamicField {
+        match description.value_type {
+            ValueType::Value => DynamicField::Value(DynamicValue::new(&description.field_type)),
+            ValueType::List => DynamicField::List(vec![]),
+            ValueType::Array { len } => DynamicField::List(
+                (0..len)
+                    .map(|_| DynamicValue::new(&description.field_type))
+                    .collect(),
+            ),
+            ValueType::Map { .. } => DynamicField::Map(BTreeMap::new()),
+        }
+    }
+}
+
+pub struct DynamicModel {
+    schema: &'static DynamicModelSchema,
+    model_type: usize,
+    values: Vec<DynamicField>,
+}
+
+impl DynamicModel {
+    pub fn new(schema: &'static DynamicModelSchema) -> DynamicModel {
+        DynamicModel {
+            schema,
+            model_type: schema.model_type,
+            values: schema.fields.iter().map(DynamicField::new).collect(),
+        }
+    }
+
+    /// A model without fields for a model type no active schema knows
+    pub fn unknown(model_type: usize) -> DynamicModel {
+        DynamicModel {
+            schema: &UNKNOWN_MODEL,
+            model_type,
+            values: vec![],
+        }
+    }
+
+    pub fn is_unknown(&self) -> bool {
+        std::ptr::eq(self.schema, &*UNKNOWN_MODEL)
+    }
+
+    pub fn schema(&self) -> &'static DynamicModelSchema {
+        self.schema
+    }
+
+    pub fn field(&self, index: usize) -> Option<&DynamicField> {
+        self.schema.slots.get(&index).map(|x| &self.values[*x])
+    }
+
+    pub fn field_by_name(&self, name: &str) -> Option<&DynamicField> {
+        self.field(self.schema.field_by_name(name)?.index)
+    }
+
+    fn slot(&self, field: usize) -> Option<usize> {
+        self.schema.slots.get(&field).copied()
+    }
+
+    fn field_type(&self, field: usize) -> FieldType {
+        self.schema.field(field).unwrap().field_type
+    }
+
+    fn map(&self, field: usize) -> &BTreeMap<MapKey, DynamicValue> {
+        match self.slot(field).map(|x| &self.values[x]) {
+            Some(DynamicField::Map(map)) => map,
+            _ => panic!("No map on field {}", field),
+        }
+    }
+
+    fn map_mut(&mut self, field: usize) -> &mut BTreeMap<MapKey, DynamicValue> {
+        match self.slot(field).map(|x| &mut self.values[x]) {
+            Some(DynamicField::Map(map)) => map,
+            _ => panic!("No map on field {}", field),
+        }
+    }
+
+    fn list(&self, field: usize) -> &Vec<DynamicValue> {
+        match self.slot(field).map(|x| &self.values[x]) {
+            Some(DynamicField::List(list)) => list,
+            _ => panic!("No list on field {}", field),
+        }
+    }
+
+    fn list_mut(&mut self, field: usize) -> &mut Vec<DynamicValue> {
+        match self.slot(field).map(|x| &mut self.values[x]) {
+            Some(DynamicField::List(list)) => list,
+            _ => panic!("No list on field {}", field),
+        }
+    }
+}
+
+impl Debug for DynamicModel {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let mut debug = f.debug_struct(self.schema.name);
+        for (field, value) in self.schema.fields.iter().zip(&self.values) {
+            debug.field(field.field_name, value);
+        }
+
+        debug.finish()
+    }
+}
+
+impl Default for DynamicModel {
+    fn default() -> Self {
+        DynamicModels::create_model(DynamicModel::model_type()).0
+    }
+}
+
+impl Model for DynamicModel {
+    fn model_type() -> usize {
+        DynamicSchema::active().map_or(UNKNOWN_MODEL.model_type, |x| x.root)
+    }
+
+    fn model_name() -> &'static str {
+        DynamicSchema::active()
+            .and_then(|x| x.model(x.root))
+            .map_or(UNKNOWN_MODEL.name, |x| x.name)
+    }
+}
+
+impl ModelDescription for DynamicModel {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn get_parent(&self) -> Option<&dyn ModelDescription> {
+        // Inherited fields are flattened into the model itself
+        None
+    }
+
+    fn get_model_type(&self) -> usize {
+        self.model_type
+    }
+
+    fn get_model_name(&self) -> &'static str {
+        self.schema.name
+    }
+
+    fn get_fields(&self) -> Vec<FieldDescription> {
+        self.schema.fields.clone()
+    }
+
+    fn get_field_description(&self, field: usize) -> Option<&'static FieldDescription> {
+        let schema = self.schema;
+        schema.field(field)
+    }
+
+    fn get_field(&self, field: usize) -> Option<&dyn Any> {
+        Some(match &self.values[self.slot(field)?] {
+            DynamicField::Value(value) => value.as_any(),
+            DynamicField::List(list) => list,
+            DynamicField::Map(map) => map,
+        })
+    }
+
+    fn get_field_mut(&mut self, field: usize) -> Option<&mut dyn Any> {
+        let slot = self.slot(field)?;
+        Some(match &mut self.values[slot] {
+            DynamicField::Value(value) => value.as_any_mut(),
+            DynamicField::List(list) => list,
+            DynamicField::Map(map) => map,
+        })
+    }
+
+    fn get_model_ref(&self, field: usize) -> Option<&Ref> {
+        match &self.values[self.slot(field)?] {
+            DynamicField::Value(value) => value.as_ref(),
+            _ => None,
+        }
+    }
+
+    fn get_model_ref_mut(&mut self, field: usize) -> Option<&mut Ref> {
+        let slot = self.slot(field)?;
+        match &mut self.values[slot] {
+            DynamicField::Value(value) => value.as_ref_mut(),
+            _ => None,
+        }
+    }
+
+    fn get_map_keys(&self, field: usize) -> Vec<MapKey> {
+        self.map(field).keys().cloned().collect()
+    }
+
+    fn create_map_field(&mut self, field: usize, key: &MapKey) -> Option<&mut dyn Any> {
+        let field_type = self.field_type(field);
+        Some(
+            self.map_mut(field)
+                .entry(key.clone())
+                .or_insert_with(|| DynamicValue::new(&field_type))
+                .as_any_mut(),
+        )
+    }
+
+    fn create_map_field_ref(&mut self, field: usize, key: &MapKey) -> Option<&mut Ref> {
+        let field_type = self.field_type(field);
+        self.map_mut(field)
+            .entry(key.clone())
+            .or_insert_with(|| DynamicValue::new(&field_type))
+            .as_ref_mut()
+    }
+
+    fn get_map_field_ref(&self, field: usize, key: &MapKey) -> Option<&Ref> {
+        self.map(field).get(key)?.as_ref()
+    }
+
+    fn get_map_field_ref_mut(&mut self, field: usize, key: &MapKey) -> Option<&mut Ref> {
+        self.map_mut(field).get_mut(key)?.as_ref_mut()
+    }
+
+    fn get_map_field(&self, field: usize, key: &MapKey) -> Option<&dyn Any> {
+        Some(self.map(field).get(key)?.as_any())
+    }
+
+    fn get_map_field_mut(&mut self, field: usize, key: &MapKey) -> Option<&mut dyn Any> {
+        Some(self.map_mut(field).get_mut(key)?.as_any_mut())
+    }
+
+    fn remove_map_field(&mut self, field: usize, key: &MapKey) -> Option<Ref> {
+        self.map_mut(field).remove(key)?.into_ref()
+    }
+
+    fn swap_map_field(&mut self, field: usize, lhs: &MapKey, rhs: &MapKey) -> anyhow::Result<()> {
+        let map = self.map_mut(field);
+        if !map.contains_key(rhs) {
+            bail!("No key {} in map on field {}", rhs, field);
+        }
+        let mut old = map
+            .remove(lhs)
+            .with_context(|| format!("No key {} in map on field {}", lhs, field))?;
+        if let Some(other) = map.get_mut(rhs) {
+            std::mem::swap(&mut old, other);
+        }
+        map.insert(lhs.clone(), old);
+        Ok(())
+    }
+
+    fn clear_field(&mut self, field: usize) {
+        // Schemas have no optional fields
+        panic!("No optional value on field {}", field)
+    }
+
+    fn get_list_len(&self, field: usize) -> usize {
+        self.list(field).len()
+    }
+
+    fn resize_list_field(&mut self, field: usize, new_len: usize) {
+        let field_type = self.field_type(field);
+        self.list_mut(field)
+            .resize_with(new_len, || DynamicValue::new(&field_type));
+    }
+
+    fn remove_list_field(&mut self, field: usize, index: usize) -> Option<Ref> {
+        self.list_mut(field).remove(index).into_ref()
+    }
+
+    fn swap_list_field(&mut self, field: usize, lhs: usize, rhs: usize) {
+        self.list_mut(field).swap(lhs, rhs)
+    }
+
+    fn get_list_field(&self, field: usize, index: usize) -> &dyn Any {
+        self.list(field)[index].as_any()
+    }
+
+    fn get_list_field_mut(&mut self, field: usize, index: usize) -> &mut dyn Any {
+        self.list_mut(field)[index].as_any_mut()
+    }
+
+    fn insert_list_field(&mut self, field: usize, index: usize) -> &mut dyn Any {
+        let field_type = self.field_type(field);
+        let list = self.list_mut(field);
+        list.insert(index, DynamicValue::new(&field_type));
+        list[index].as_any_mut()
+    }
+
+    fn get_list_field_ref(&self, field: usize, index: usize) -> &Ref {
+        self.list(field)[index]
+            .as_ref()
+            .unwrap_or_else(|| panic!("No model list on field {}", field))
+    }
+
+    fn get_list_field_ref_mut(&mut self, field: usize, index: usize) -> &mut Ref {
+        self.list_mut(field)[index]
+            .as_ref_mut()
+            .unwrap_or_else(|| panic!("No model list on field {}", field))
+    }
+
+    fn insert_list_field_ref(&mut self, field: usize, index: usize) -> &mut Ref {
+        let field_type = self.field_type(field);
+        let list = self.list_mut(field);
+        list.insert(index, DynamicValue::new(&field_type));
+        list[index]
+            .as_ref_mut()
+            .unwrap_or_else(|| panic!("No model list on field {}", field))
+    }
+}
+
+#[derive(Debug)]
+pub struct DynamicModels(DynamicModel);
+
+impl DynamicModels {
+    pub fn into_inner(self) -> DynamicModel {
+        self.0
+    }
+}
+
+impl ModelCollection for DynamicModels {
+    fn create_model(id: usize) -> Self {
+        // The patcher checks has_model first, so unknown models only come from documents created
+        // without an active schema
+        DynamicModels(
+            DynamicSchema::active()
+                .and_then(|x| x.create_model(id))
+                .unwrap_or_else(|| DynamicModel::unknown(id)),
+        )
+    }
+
+    fn has_model(id: usize) -> bool {
+        DynamicSchema::active()
+            .map(|x| x.models.contains_key(&id))
+            .unwrap_or(false)
+    }
+
+    fn model_types() -> Vec<usize> {
+        let mut types = DynamicSchema::active()
+            .map(|x| x.models.keys().copied().collect::<Vec<_>>())
+            .unwrap_or_default();
+        types.sort();
+        types
+    }
+
+    fn boxed(self) -> Box<dyn ModelDescription> {
+        Box::new(self.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::patcher::Patcher;
+    use crate::schema::{DynamicField, DynamicModel, DynamicModels, DynamicValue, Schema};
+    use crate::{Document, ModelWithDocument};
+    use bytes::{BufMut, BytesMut};
+
+    const SCHEMA: &str = r#"{
+        "root": "Root",
+        "models": [
+            { "name": "Root", "type": 0, "fields": [
+                { "index": 0, "name": "players", "type": "Player", "container": "map" }
+            ] },
+            { "name": "Base", "type": 1, "fields": [
+                { "index": 0, "name": "id", "type": "i32" }
+            ] },
+            { "name": "Player", "type": 2, "extends": "Base", "fields": [
+                { "index": 1, "name": "name", "type": "string" },
+                { "index": 2, "name": "scores", "type": "f32", "container": "list" }
+            ] }
+        ]
+    }"#;
+
+    #[test]
+    pub fn test_dynamic_schema_patching() {
+        let schema = Schema::from_json(SCHEMA).unwrap().compile().unwrap();
+        let _guard = schema.enter();
+
+        let mut patcher: Patcher<DynamicModel, DynamicModels> = Patcher::new(Document::new());
+        let mut patch = BytesMut::new();
+        // push, create and assign key: players[3] = Player
+        patch.put_slice(&[8, 0, 2]);
+        patch.put_i32_le(3);
+        // assign field: id = 3
+        patch.put_slice(&[2, 0]);
+        patch.put_i32_le(3);
+        // assign field: name = "Gamer"
+        patch.put_slice(&[2, 1]);
+        patch.put_i32_le(5);
+        patch.put_slice(b"Gamer");
+        // resize: scores to 2 entries
+        patch.put_slice(&[14, 2]);
+        patch.put_i32_le(2);
+        // assign key: scores[1] = 1.5
+        patch.put_slice(&[6, 2]);
+        patch.put_i32_le(1);
+        patch.put_f32_le(1.5);
+        // pop
+        patch.put_u8(1);
+        patcher.apply_patch(patch.freeze()).unwrap();
+
+        let document = patcher.document();
+        let id = document.root().map_get_model_id(0, &3.into()).unwrap();
+        let player = document.by_id(id).unwrap();
+        let player = player.cast_ref::<DynamicModel>().unwrap();
+        assert_eq!(player.schema().name(), "Player");
+        assert!(matches!(
+            player.field_by_name("name"),
+            Some(DynamicField::Value(DynamicValue::String(x))) if x == "Gamer"
+        ));
+
+        assert_eq!(
+            document.to_json(id),
+            serde_json::json!({
+                "model_type": 2,
+                "model_name": "Player",
+                "id": 3,
+                "name": "Gamer",
+                "scores": [0.0, 1.5],
+            })
+        );
+    }
+
+    #[test]
+    pub fn test_schema_validation() {
+        let schema = SCHEMA.replace("\"Base\", \"type\": 1", "\"Base\", \"type\": 0");
+        assert!(Schema::from_json(&schema).unwrap().compile().is_err());
+
+        let schema = SCHEMA.replace("\"type\": \"string\"", "\"type\": \"Unknown\"");
+        assert!(Schema::from_json(&schema).unwrap().compile().is_err());
+    }
+
+    #[test]
+    pub fn test_compiled_schemas_are_shared_and_optional() {
+        let schema = Schema::from_json(SCHEMA).unwrap();
+        let compiled = schema.compile().unwrap();
+        assert!(std::ptr::eq(compiled, schema.compile().unwrap()));
+
+        let renamed = SCHEMA.replace("\"scores\"", "\"points\"");
+        let renamed = Schema::from_json(&renamed).unwrap().compile().unwrap();
+        assert!(!std::ptr::eq(compiled, renamed));
+        assert!(std::ptr::eq(
+            compiled.model(1).unwrap().name(),
+            renamed.model(1).unwrap().name()
+        ));
+
+        // Without an active schema the root has no fields, so the patch fails instead of panicking
+        let mut patcher: Patcher<DynamicModel, DynamicModels> = Patcher::new(Document::new());
+        assert!(patcher
+            .document()
+            .root()
+            .cast_ref::<DynamicModel>()
+            .unwrap()
+            .is_unknown());
+        let mut patch = BytesMut::new();
+        patch.put_slice(&[2, 0]);
+        patch.put_i32_le(3);
+        assert!(patcher.apply_patch(patch.freeze()).is_err());
+    }
+}
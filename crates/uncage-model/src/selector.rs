@@ -1,10 +1,17 @@
-use crate::{FieldType, Fields, Model, Path, PathAction};
+use crate::{FieldType, Fields, MapKey, Model, Path, PathAction, PathSubSegment};
+use std::collections::HashMap;
 
 #[derive(Debug, Clone)]
 pub struct Selector {
     chain: Vec<Filter>,
 }
 
+impl Default for Selector {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Selector {
     pub fn new() -> Selector {
         Self {
@@ -32,6 +39,11 @@ impl Selector {
         })
     }
 
+    /// Only matches the map entry with the given key
+    pub fn key<K: Into<MapKey>>(self, key: K) -> Selector {
+        self.add_filter(Filter::Key(key.into()))
+    }
+
     pub fn model<M: Model>(self) -> Selector {
         self.add_filter(Filter::Model(M::model_type()))
     }
@@ -73,6 +85,7 @@ pub enum Filter {
     Root,
     Tail,
     Field { model: usize, field: usize },
+    Key(MapKey),
     Model(usize),
     Action(ActionFilter),
 }
@@ -86,19 +99,15 @@ impl Filter {
                 let fd = path.items[idx].field;
                 (fd.index == *field && fd.model_type == *model, idx)
             }
-            Filter::Model(m) => (
-                path.items[idx].field.field_type == FieldType::TypeModel(*m),
+            Filter::Key(key) => (
+                matches!(&path.items[idx].sub, PathSubSegment::Key(x) if x == key),
                 idx,
             ),
-            Filter::Action(a) => (
-                matches!(
-                    (a, path.items[idx].action),
-                    (ActionFilter::Created, PathAction::Created)
-                        | (ActionFilter::Mutated, PathAction::Mutated)
-                        | (ActionFilter::Removed, PathAction::Removed)
-                ),
+            Filter::Model(m) => (
+                path.items[idx].field.field_type == FieldType::TypeModel(*m),
                 idx,
             ),
+            Filter::Action(a) => (a.matches(path.items[idx].action), idx),
         }
     }
 }
@@ -110,28 +119,277 @@ pub enum ActionFilter {
     Removed,
 }
 
-#[derive(Debug, Default, Clone)]
+impl ActionFilter {
+    const ALL: [ActionFilter; 3] = [
+        ActionFilter::Created,
+        ActionFilter::Mutated,
+        ActionFilter::Removed,
+    ];
+
+    pub fn matches(&self, action: PathAction) -> bool {
+        matches!(
+            (self, action),
+            (ActionFilter::Created, PathAction::Created)
+                | (ActionFilter::Mutated, PathAction::Mutated)
+                | (ActionFilter::Removed, PathAction::Removed)
+        )
+    }
+}
+
+// Selectors are compiled into a trie of filters, so selectors sharing a prefix
+// (e.g. everything below `World.entities`) only test that prefix once per path.
+#[derive(Debug, Clone)]
 pub struct SelectorCollection {
-    selectors: Vec<(Selector, usize)>,
+    nodes: Vec<SelectorNode>,
+    len: usize,
+}
+
+#[derive(Debug, Default, Clone)]
+struct SelectorNode {
+    keys: Vec<(usize, usize)>,
+    root: Option<usize>,
+    tail: Option<usize>,
+    fields: HashMap<(usize, usize), usize>,
+    map_keys: HashMap<MapKey, usize>,
+    models: HashMap<usize, usize>,
+    actions: [Option<usize>; 3],
+}
+
+impl Default for SelectorCollection {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl SelectorCollection {
     pub fn new() -> SelectorCollection {
-        Self { selectors: vec![] }
+        Self {
+            nodes: vec![SelectorNode::default()],
+            len: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
     }
 
     pub fn add_selector(&mut self, key: usize, selector: Selector) {
-        self.selectors.push((selector, key));
+        let mut node = 0;
+        for filter in selector.chain {
+            let next = self.nodes.len();
+            let child = match filter {
+                Filter::Root => self.nodes[node].root.get_or_insert(next),
+                Filter::Tail => self.nodes[node].tail.get_or_insert(next),
+                Filter::Field { model, field } => self.nodes[node]
+                    .fields
+                    .entry((model, field))
+                    .or_insert(next),
+                Filter::Key(key) => self.nodes[node].map_keys.entry(key).or_insert(next),
+                Filter::Model(model) => self.nodes[node].models.entry(model).or_insert(next),
+                Filter::Action(action) => {
+                    self.nodes[node].actions[action as usize].get_or_insert(next)
+                }
+            };
+
+            node = *child;
+            if node == next {
+                self.nodes.push(SelectorNode::default());
+            }
+        }
+
+        self.nodes[node].keys.push((self.len, key));
+        self.len += 1;
     }
 
-    pub fn matches(&mut self, path: &Path) -> Vec<usize> {
+    pub fn matches(&self, path: &Path) -> Vec<usize> {
         let mut keys = vec![];
-        for (selector, key) in &self.selectors {
-            if selector.matches(path) {
-                keys.push(*key)
+        self.matches_into(path, &mut keys);
+        keys
+    }
+
+    // Keys are reported in the order their selectors were added
+    pub fn matches_into(&self, path: &Path, keys: &mut Vec<usize>) {
+        keys.clear();
+        if self.len == 0 {
+            return;
+        }
+
+        let mut found = vec![];
+        self.visit(0, 0, path, &mut found);
+        found.sort_unstable();
+        keys.extend(found.into_iter().map(|(_, key)| key));
+    }
+
+    // Every filter is matched against the first segment at or after `idx` it
+    // applies to, the same way `Selector::matches` walks its chain.
+    fn visit(&self, node: usize, idx: usize, path: &Path, found: &mut Vec<(usize, usize)>) {
+        let node = &self.nodes[node];
+        found.extend_from_slice(&node.keys);
+
+        let items = &path.items;
+        if idx >= items.len() {
+            return;
+        }
+
+        if let (Some(child), 0) = (node.root, idx) {
+            self.visit(child, idx, path, found);
+        }
+
+        if let Some(child) = node.tail {
+            self.visit(child, items.len() - 1, path, found);
+        }
+
+        for (action, child) in node.actions.iter().enumerate() {
+            if let Some(child) = child {
+                let position = items[idx..]
+                    .iter()
+                    .position(|x| ActionFilter::ALL[action].matches(x.action));
+                if let Some(position) = position {
+                    self.visit(*child, idx + position, path, found);
+                }
             }
         }
 
-        keys
+        if !node.models.is_empty() {
+            let mut visited = vec![];
+            for (i, segment) in items.iter().enumerate().skip(idx) {
+                if let FieldType::TypeModel(model) = segment.field.field_type {
+                    if let Some(child) = node.models.get(&model) {
+                        if !visited.contains(child) {
+                            visited.push(*child);
+                            self.visit(*child, i, path, found);
+                        }
+                    }
+                }
+            }
+        }
+
+        if !node.map_keys.is_empty() {
+            let mut visited = vec![];
+            for (i, segment) in items.iter().enumerate().skip(idx) {
+                if let PathSubSegment::Key(key) = &segment.sub {
+                    if let Some(child) = node.map_keys.get(key) {
+                        if !visited.contains(child) {
+                            visited.push(*child);
+                            self.visit(*child, i, path, found);
+                        }
+                    }
+                }
+            }
+        }
+
+        if !node.fields.is_empty() {
+            let mut visited = vec![];
+            for (i, segment) in items.iter().enumerate().skip(idx) {
+                let key = (segment.field.model_type, segment.field.index);
+                if let Some(child) = node.fields.get(&key) {
+                    if !visited.contains(child) {
+                        visited.push(*child);
+                        self.visit(*child, i, path, found);
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::selector::{Filter, SelectorCollection};
+    use crate::{
+        FieldDescription, FieldMetadata, FieldType, Path, PathAction, Selector, ValueType,
+    };
+
+    const fn field(model_type: usize, index: usize, field_type: FieldType) -> FieldDescription {
+        FieldDescription {
+            value_type: ValueType::Value,
+            field_type,
+            index,
+            model_type,
+            model_name: "Test",
+            field_name: "test",
+            autofill: false,
+            enumeration: None,
+            optional: false,
+            nested: false,
+            metadata: FieldMetadata::NONE,
+        }
+    }
+
+    static WORLD: FieldDescription = field(0, 0, FieldType::TypeModel(1));
+    static ENTITIES: FieldDescription = field(1, 1, FieldType::Model);
+    static HP: FieldDescription = field(9, 12, FieldType::Float);
+
+    #[test]
+    pub fn test_collection_matches_like_selectors() {
+        let selectors = [
+            Selector::new(),
+            Selector::new().root(),
+            Selector::new().add_filter(Filter::Field {
+                model: 9,
+                field: 12,
+            }),
+            Selector::new()
+                .created()
+                .add_filter(Filter::Field { model: 1, field: 1 }),
+            Selector::new()
+                .add_filter(Filter::Field { model: 1, field: 1 })
+                .mutated()
+                .tail(),
+            Selector::new()
+                .root()
+                .add_filter(Filter::Model(1))
+                .removed(),
+            Selector::new()
+                .add_filter(Filter::Field { model: 1, field: 1 })
+                .root(),
+            Selector::new().add_filter(Filter::Field {
+                model: 9,
+                field: 12,
+            }),
+            Selector::new()
+                .add_filter(Filter::Field { model: 1, field: 1 })
+                .key(5),
+            Selector::new().key(6),
+            Selector::new().key("5"),
+        ];
+
+        let mut collection = SelectorCollection::new();
+        for (key, selector) in selectors.iter().enumerate() {
+            collection.add_selector(key, selector.clone());
+        }
+
+        let mut paths = vec![];
+        for action in [
+            PathAction::Created,
+            PathAction::Mutated,
+            PathAction::Removed,
+        ] {
+            let mut path = Path::new();
+            path.goto_field(0, &WORLD);
+            paths.push(path.clone());
+            path.set_action(action);
+            paths.push(path.clone());
+            path.goto_map_field(1, &ENTITIES, 5.into());
+            path.set_action(action);
+            paths.push(path.clone());
+            path.goto_field(9, &HP);
+            path.set_action(action);
+            paths.push(path.clone());
+        }
+
+        for path in &paths {
+            let expected = selectors
+                .iter()
+                .enumerate()
+                .filter(|(_, selector)| selector.matches(path))
+                .map(|(key, _)| key)
+                .collect::<Vec<_>>();
+            assert_eq!(collection.matches(path), expected, "{:?}", path);
+        }
     }
 }
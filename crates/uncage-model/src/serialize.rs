@@ -0,0 +1,419 @@
+use crate::{
+    Document, FieldDescription, FieldType, MapKey, Model, ModelCollection, ModelDescription,
+    ModelEnum, ModelRef, ModelWithDocument, Ref, Reference, ValueType,
+};
+use serde::de::{Error, MapAccess, Visitor};
+use serde::ser::{SerializeMap, SerializeSeq};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::any::Any;
+use std::fmt::Formatter;
+use std::marker::PhantomData;
+
+/// Implemented by `#[derive(Model)]` when the `serde` feature is enabled, models are written as
+/// maps of field name to value with the fields of the parent first
+pub trait SerdeModel: Model {
+    const FIELD_COUNT: usize;
+
+    fn serialize_fields<M: SerializeMap>(&self, map: &mut M) -> Result<(), M::Error>;
+
+    /// Returns false when the key isn't a field of the model
+    fn deserialize_field<'de, A: MapAccess<'de>>(
+        &mut self,
+        key: &str,
+        map: &mut A,
+    ) -> Result<bool, A::Error>;
+}
+
+pub fn serialize_model<T: SerdeModel, S: Serializer>(
+    model: &T,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    let mut map = serializer.serialize_map(Some(T::FIELD_COUNT))?;
+    model.serialize_fields(&mut map)?;
+    map.end()
+}
+
+pub fn deserialize_model<'de, T: SerdeModel, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<T, D::Error> {
+    deserializer.deserialize_map(ModelVisitor(PhantomData))
+}
+
+struct ModelVisitor<T>(PhantomData<T>);
+
+impl<'de, T: SerdeModel> Visitor<'de> for ModelVisitor<T> {
+    type Value = T;
+
+    fn expecting(&self, formatter: &mut Formatter) -> std::fmt::Result {
+        write!(formatter, "a {} model", T::model_name())
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<T, A::Error> {
+        let mut model = T::default();
+        while let Some(key) = map.next_key::<String>()? {
+            if !model.deserialize_field(&key, &mut map)? {
+                map.next_value::<serde::de::IgnoredAny>()?;
+            }
+        }
+
+        Ok(model)
+    }
+}
+
+/// Enums are written by name for human readable formats and as their value otherwise, unknown
+/// values are always written as numbers
+pub fn serialize_enum<T: ModelEnum, S: Serializer>(
+    value: &T,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    match value.name() {
+        Some(name) if serializer.is_human_readable() => serializer.serialize_str(name),
+        _ => serializer.serialize_i64(value.value()),
+    }
+}
+
+pub fn deserialize_enum<'de, T: ModelEnum, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<T, D::Error> {
+    if deserializer.is_human_readable() {
+        deserializer.deserialize_any(EnumVisitor(PhantomData))
+    } else {
+        deserializer.deserialize_i64(EnumVisitor(PhantomData))
+    }
+}
+
+struct EnumVisitor<T>(PhantomData<T>);
+
+impl<'de, T: ModelEnum> Visitor<'de> for EnumVisitor<T> {
+    type Value = T;
+
+    fn expecting(&self, formatter: &mut Formatter) -> std::fmt::Result {
+        write!(formatter, "a {} name or value", T::NAME)
+    }
+
+    fn visit_i64<E: Error>(self, value: i64) -> Result<T, E> {
+        Ok(T::from_value(value))
+    }
+
+    fn visit_u64<E: Error>(self, value: u64) -> Result<T, E> {
+        i64::try_from(value)
+            .map(T::from_value)
+            .map_err(|_| E::custom(format!("{} is out of range for {}", value, T::NAME)))
+    }
+
+    fn visit_str<E: Error>(self, value: &str) -> Result<T, E> {
+        T::VARIANTS
+            .iter()
+            .find(|(_, name)| *name == value)
+            .map(|(x, _)| T::from_value(*x))
+            .ok_or_else(|| E::unknown_variant(value, &[]))
+    }
+}
+
+impl Serialize for Ref {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.get().serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Ref {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Ok(Option::<usize>::deserialize(deserializer)?.map_or_else(Ref::empty, Ref::from_id))
+    }
+}
+
+impl<T: Model> Serialize for ModelRef<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.as_ref().serialize(serializer)
+    }
+}
+
+impl<'de, T: Model> Deserialize<'de> for ModelRef<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Ok(Option::<usize>::deserialize(deserializer)?
+            .map_or_else(ModelRef::empty, ModelRef::from_id))
+    }
+}
+
+impl<R: Model, C: ModelCollection> Document<R, C> {
+    /// Serializes the model with the given id with every referenced model inlined instead of
+    /// written as an id
+    pub fn inline(&self, id: usize) -> InlineModel<'_, R, C> {
+        InlineModel { document: self, id }
+    }
+}
+
+pub struct InlineModel<'a, R: Model, C: ModelCollection> {
+    document: &'a Document<R, C>,
+    id: usize,
+}
+
+impl<'a, R: Model, C: ModelCollection> InlineModel<'a, R, C> {
+    fn child(&self, reference: Option<&Ref>) -> Option<InlineModel<'a, R, C>> {
+        reference
+            .and_then(|x| x.get())
+            .map(|id| self.document.inline(id))
+    }
+}
+
+impl<'a, R: Model, C: ModelCollection> Serialize for InlineModel<'a, R, C> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let item = match self.document.by_id(self.id) {
+            Some(item) => item,
+            None => return serializer.serialize_none(),
+        };
+
+        let model = item.model().get();
+        let fields = model.get_fields();
+        let mut map = serializer.serialize_map(Some(fields.len() + 2))?;
+        map.serialize_entry("model_type", &model.get_model_type())?;
+        map.serialize_entry("model_name", model.get_model_name())?;
+
+        for field in &fields {
+            let is_model = matches!(field.field_type, FieldType::Model | FieldType::TypeModel(_));
+            match field.value_type {
+                ValueType::Value if is_model => {
+                    map.serialize_entry(
+                        field.field_name,
+                        &self.child(model.get_model_ref(field.index)),
+                    )?;
+                }
+                ValueType::Value => {
+                    map.serialize_entry(
+                        field.field_name,
+                        &model
+                            .get_field(field.index)
+                            .map(|value| Value { field, value }),
+                    )?;
+                }
+                ValueType::List | ValueType::Array { .. } => {
+                    map.serialize_entry(
+                        field.field_name,
+                        &List {
+                            inline: self,
+                            model,
+                            field,
+                        },
+                    )?;
+                }
+                ValueType::Map { .. } => {
+                    map.serialize_entry(
+                        field.field_name,
+                        &Map {
+                            inline: self,
+                            model,
+                            field,
+                        },
+                    )?;
+                }
+            }
+        }
+
+        map.end()
+    }
+}
+
+struct List<'s, 'a, R: Model, C: ModelCollection> {
+    inline: &'s InlineModel<'a, R, C>,
+    model: &'s dyn ModelDescription,
+    field: &'s FieldDescription,
+}
+
+impl<'s, 'a, R: Model, C: ModelCollection> Serialize for List<'s, 'a, R, C> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let index = self.field.index;
+        let len = self.model.get_list_len(index);
+        let mut seq = serializer.serialize_seq(Some(len))?;
+        for i in 0..len {
+            match self.field.field_type {
+                FieldType::Model | FieldType::TypeModel(_) => seq.serialize_element(
+                    &self
+                        .inline
+                        .child(Some(self.model.get_list_field_ref(index, i))),
+                )?,
+                _ => seq.serialize_element(&Value {
+                    field: self.field,
+                    value: self.model.get_list_field(index, i),
+                })?,
+            }
+        }
+
+        seq.end()
+    }
+}
+
+impl Serialize for MapKey {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            MapKey::Int(x) => serializer.serialize_i64(*x),
+            MapKey::String(x) => serializer.serialize_str(x),
+        }
+    }
+}
+
+struct Map<'s, 'a, R: Model, C: ModelCollection> {
+    inline: &'s InlineModel<'a, R, C>,
+    model: &'s dyn ModelDescription,
+    field: &'s FieldDescription,
+}
+
+impl<'s, 'a, R: Model, C: ModelCollection> Serialize for Map<'s, 'a, R, C> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let index = self.field.index;
+        let keys = self.model.get_map_keys(index);
+        let mut map = serializer.serialize_map(Some(keys.len()))?;
+        for key in keys {
+            match self.field.field_type {
+                FieldType::Model | FieldType::TypeModel(_) => map.serialize_entry(
+                    &key,
+                    &self.inline.child(self.model.get_map_field_ref(index, &key)),
+                )?,
+                _ => map.serialize_entry(
+                    &key,
+                    &self.model.get_map_field(index, &key).map(|value| Value {
+                        field: self.field,
+                        value,
+                    }),
+                )?,
+            }
+        }
+
+        map.end()
+    }
+}
+
+struct Value<'s> {
+    field: &'s FieldDescription,
+    value: &'s dyn Any,
+}
+
+impl<'s> Serialize for Value<'s> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if let Some(enumeration) = &self.field.enumeration {
+            let value = enumeration
+                .get(self.value)
+                .ok_or_else(|| serde::ser::Error::custom("Field has the wrong enum type"))?;
+
+            return match enumeration.name_of(value) {
+                Some(name) if serializer.is_human_readable() => serializer.serialize_str(name),
+                _ => serializer.serialize_i64(value),
+            };
+        }
+
+        fn value<T: Serialize + 'static, S: Serializer>(
+            nested: bool,
+            value: &dyn Any,
+            serializer: S,
+        ) -> Result<S::Ok, S::Error> {
+            let result = match nested {
+                true => value
+                    .downcast_ref::<Vec<T>>()
+                    .map(|x| x.serialize(serializer)),
+                false => value.downcast_ref::<T>().map(|x| x.serialize(serializer)),
+            };
+
+            result.unwrap_or_else(|| Err(serde::ser::Error::custom("Field has the wrong type")))
+        }
+
+        let nested = self.field.nested;
+        match self.field.field_type {
+            FieldType::Int8 => value::<i8, S>(nested, self.value, serializer),
+            FieldType::UInt8 => value::<u8, S>(nested, self.value, serializer),
+            FieldType::Int16 => value::<i16, S>(nested, self.value, serializer),
+            FieldType::UInt16 => value::<u16, S>(nested, self.value, serializer),
+            FieldType::Int32 => value::<i32, S>(nested, self.value, serializer),
+            FieldType::UInt32 => value::<u32, S>(nested, self.value, serializer),
+            FieldType::Int64 => value::<i64, S>(nested, self.value, serializer),
+            FieldType::UInt64 => value::<u64, S>(nested, self.value, serializer),
+            FieldType::Int128 => value::<i128, S>(nested, self.value, serializer),
+            FieldType::UInt128 => value::<u128, S>(nested, self.value, serializer),
+            FieldType::Float => value::<f32, S>(nested, self.value, serializer),
+            FieldType::Double => value::<f64, S>(nested, self.value, serializer),
+            FieldType::String => value::<String, S>(nested, self.value, serializer),
+            FieldType::Boolean => value::<bool, S>(nested, self.value, serializer),
+            FieldType::Model | FieldType::TypeModel(_) => Err(serde::ser::Error::custom(
+                "Models are serialized by the parent",
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::patcher::{PatchAction, Patcher};
+    use crate::{Document, Model, ModelCollection, ModelRef, ModelVec, Reference};
+    use bytes::{BufMut, BytesMut};
+    use serde_json::json;
+    use std::collections::BTreeMap;
+
+    #[derive(Debug, Default, Model)]
+    #[uncage(type = 0)]
+    struct Root {
+        #[uncage(index = 0)]
+        units: ModelVec<Unit>,
+        #[uncage(index = 1)]
+        selected: ModelRef<Unit>,
+    }
+
+    #[derive(Debug, Default, Model)]
+    #[uncage(type = 1)]
+    struct Entity {
+        #[uncage(index = 0)]
+        name: String,
+    }
+
+    #[derive(Debug, Default, Model)]
+    #[uncage(type = 2)]
+    struct Unit {
+        #[uncage(extends)]
+        parent: Entity,
+        #[uncage(index = 1)]
+        hit_points: BTreeMap<i32, f32>,
+    }
+
+    #[derive(Debug, ModelCollection)]
+    enum Models {
+        Root(Root),
+        Entity(Entity),
+        Unit(Unit),
+    }
+
+    #[test]
+    pub fn test_serde_models() {
+        let unit = Unit {
+            parent: Entity {
+                name: "Scout".to_string(),
+            },
+            hit_points: BTreeMap::from([(1, 45.0)]),
+        };
+        let value = serde_json::to_value(&unit).unwrap();
+        assert_eq!(
+            value,
+            json!({ "name": "Scout", "hit_points": { "1": 45.0 } })
+        );
+        let unit: Unit = serde_json::from_value(value).unwrap();
+        assert_eq!(unit.parent.name, "Scout");
+
+        let root: Root =
+            serde_json::from_value(json!({ "units": [3, null], "selected": 3 })).unwrap();
+        assert_eq!(root.units[0].get(), Some(3));
+        assert!(!root.units[1].is_set());
+
+        let mut patcher: Patcher<Root, Models> = Patcher::new(Document::new());
+        let mut patch = BytesMut::new();
+        patch.put_u8(PatchAction::PushCreateAndAssignField as u8);
+        patch.put_u8(1);
+        patch.put_u8(2);
+        patch.put_u8(PatchAction::AssignField as u8);
+        patch.put_u8(0);
+        patch.put_i32_le(4);
+        patch.put_slice(b"Monk");
+        patch.put_u8(PatchAction::Pop as u8);
+        patcher.apply_patch(patch.freeze()).unwrap();
+
+        let inline = serde_json::to_value(patcher.document().inline(0)).unwrap();
+        assert_eq!(inline["units"], json!([]));
+        assert_eq!(inline["selected"]["model_name"], "Unit");
+        assert_eq!(inline["selected"]["name"], "Monk");
+    }
+}
@@ -0,0 +1,12 @@
+use uncage_model::Model;
+
+#[derive(Debug, Default, Model)]
+#[uncage(type = 0)]
+struct Root {
+    #[uncage(index = 0)]
+    time: i32,
+    #[uncage(index = 0)]
+    speed: f32,
+}
+
+fn main() {}
@@ -0,0 +1,23 @@
+use uncage_model::{Model, ModelCollection};
+
+#[derive(Debug, Default, Model)]
+#[uncage(type = 0)]
+pub struct Root {
+    #[uncage(index = 0)]
+    time: i32,
+}
+
+#[derive(Debug, Default, Model)]
+#[uncage(type = 0)]
+pub struct Player {
+    #[uncage(index = 0)]
+    name: String,
+}
+
+#[derive(Debug, ModelCollection)]
+pub enum Models {
+    Root(Root),
+    Player(Player),
+}
+
+fn main() {}
@@ -0,0 +1,19 @@
+use uncage_model::Model;
+
+#[derive(Debug, Default, Model)]
+#[uncage(type = 0)]
+pub struct Entity {
+    #[uncage(index = 0)]
+    hp: f32,
+}
+
+#[derive(Debug, Default, Model)]
+#[uncage(type = 1)]
+pub struct Unit {
+    #[uncage(extends)]
+    parent: Entity,
+    #[uncage(index = 0)]
+    speed: f32,
+}
+
+fn main() {}
@@ -0,0 +1,11 @@
+use std::rc::Rc;
+use uncage_model::Model;
+
+#[derive(Debug, Default, Model)]
+#[uncage(type = 0)]
+struct Root {
+    #[uncage(index = 0)]
+    shared: Rc<i32>,
+}
+
+fn main() {}
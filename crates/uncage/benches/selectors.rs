@@ -0,0 +1,179 @@
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
+use std::rc::Rc;
+use uncage::model::{Models, Root};
+use uncage_client::pb::FrameSequence;
+use uncage_client::prost::bytes::Bytes;
+use uncage_client::prost::Message;
+use uncage_model::patcher::Patcher;
+use uncage_model::selector::{Filter, SelectorCollection};
+use uncage_model::{Document, FieldDescription, ModelCollection, Path, PathAction, Selector};
+
+// Recording as dumped by uncage-client: length delimited `FrameSequence`s
+const RECORDING_ENV: &str = "UNCAGE_BENCH_RECORDING";
+const DEFAULT_RECORDING: &str = "blobs/output.bin";
+
+// The patches of the recording, `None` if there is no recording to read
+fn load_patches() -> Option<Vec<Bytes>> {
+    let file = std::env::var(RECORDING_ENV).unwrap_or_else(|_| DEFAULT_RECORDING.to_string());
+    let mut data = match std::fs::read(&file) {
+        Ok(data) => Bytes::from(data),
+        Err(err) => {
+            eprintln!(
+                "Failed to read recording {} (set {} to a recording), only synthetic paths are benchmarked: {}",
+                file, RECORDING_ENV, err
+            );
+            return None;
+        }
+    };
+
+    let mut patches = vec![];
+    while !data.is_empty() {
+        let seq =
+            FrameSequence::decode_length_delimited(&mut data).expect("Failed to decode frames");
+        patches.extend(seq.frame.into_iter().map(|frame| Bytes::from(frame.patch)));
+    }
+
+    Some(patches)
+}
+
+// Every field of every model, so the workload grows with the models
+fn model_fields() -> Vec<(usize, &'static FieldDescription)> {
+    let mut fields = vec![];
+    for model_type in Models::model_types() {
+        let model = Models::create_model(model_type).boxed();
+        for field in model.get_fields() {
+            if let Some(field) = model.get_field_description(field.index) {
+                fields.push((model_type, field));
+            }
+        }
+    }
+
+    fields
+}
+
+// Roughly what a stats backend registers: a handful of selectors per field
+fn create_selectors() -> Vec<Selector> {
+    let mut selectors = vec![];
+    for (model, field) in model_fields() {
+        let filter = Filter::Field {
+            model,
+            field: field.index,
+        };
+        selectors.push(Selector::new().add_filter(filter.clone()));
+        selectors.push(Selector::new().created().add_filter(filter.clone()));
+        selectors.push(Selector::new().add_filter(filter).removed());
+    }
+
+    selectors
+}
+
+fn collect_paths(patches: &[Bytes]) -> Vec<Rc<Path>> {
+    let mut patcher: Patcher<Root, Models> = Patcher::new(Document::new());
+    patcher.add_selector(0, Selector::new());
+
+    let mut paths = vec![];
+    for patch in patches {
+        let matches = patcher
+            .apply_patch(patch.clone())
+            .expect("Failed to apply patch");
+        paths.extend(matches.into_iter().map(|x| x.path));
+    }
+
+    paths
+}
+
+// Without a recording: every field of every model created, mutated and removed
+fn synthetic_paths() -> Vec<Rc<Path>> {
+    let mut paths = vec![];
+    for (model, field) in model_fields() {
+        for action in [
+            PathAction::Created,
+            PathAction::Mutated,
+            PathAction::Removed,
+        ] {
+            let mut path = Path::new();
+            path.goto_field(model, field);
+            path.set_action(action);
+            paths.push(Rc::new(path));
+        }
+    }
+
+    paths
+}
+
+fn bench_selectors(c: &mut Criterion) {
+    let patches = load_patches();
+    let selectors = create_selectors();
+    let paths = match &patches {
+        Some(patches) => collect_paths(patches),
+        None => synthetic_paths(),
+    };
+    eprintln!("{} selectors, {} paths", selectors.len(), paths.len());
+
+    let mut collection = SelectorCollection::new();
+    for (key, selector) in selectors.iter().enumerate() {
+        collection.add_selector(key, selector.clone());
+    }
+
+    let mut group = c.benchmark_group("selector_matching");
+    group.bench_function("linear", |b| {
+        b.iter(|| {
+            let mut count = 0;
+            for path in &paths {
+                for selector in &selectors {
+                    if selector.matches(path) {
+                        count += 1;
+                    }
+                }
+            }
+
+            count
+        })
+    });
+
+    group.bench_function("compiled", |b| {
+        let mut keys = vec![];
+        b.iter(|| {
+            let mut count = 0;
+            for path in &paths {
+                collection.matches_into(path, &mut keys);
+                count += keys.len();
+            }
+
+            count
+        })
+    });
+    group.finish();
+
+    let Some(patches) = patches else {
+        return;
+    };
+
+    let mut group = c.benchmark_group("replay");
+    group.sample_size(10);
+    group.bench_function("patcher", |b| {
+        b.iter_batched(
+            || {
+                let mut patcher: Patcher<Root, Models> = Patcher::new(Document::new());
+                for (key, selector) in selectors.iter().enumerate() {
+                    patcher.add_selector(key, selector.clone());
+                }
+
+                patcher
+            },
+            |mut patcher| {
+                let mut count = 0;
+                for patch in &patches {
+                    count += patcher.apply_patch(patch.clone()).unwrap().len();
+                }
+
+                count
+            },
+            BatchSize::LargeInput,
+        )
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_selectors);
+criterion_main!(benches);
@@ -0,0 +1,214 @@
+use uncage_model::ModelEnum;
+
+/// `World.victory_type`, stored as `i8`. The codes are the victory conditions of the game setup,
+/// in the order of the `VictoryEnum` of the aoc-mgz recorded game parser
+#[derive(ModelEnum, Debug, Copy, Clone, Eq, PartialEq)]
+pub enum VictoryType {
+    #[uncage(value = 0)]
+    Standard,
+    #[uncage(value = 1)]
+    Conquest,
+    #[uncage(value = 2)]
+    Exploration,
+    #[uncage(value = 3)]
+    Ruins,
+    #[uncage(value = 4)]
+    Artifacts,
+    #[uncage(value = 5)]
+    Discoveries,
+    #[uncage(value = 6)]
+    Gold,
+    #[uncage(value = 7)]
+    TimeLimit,
+    #[uncage(value = 8)]
+    Score,
+    Unknown(i8),
+}
+
+/// `GameOptions.victory_type`, the same codes as [VictoryType] but stored as `u8`
+#[derive(ModelEnum, Debug, Copy, Clone, Eq, PartialEq)]
+pub enum VictorySetting {
+    #[uncage(value = 0)]
+    Standard,
+    #[uncage(value = 1)]
+    Conquest,
+    #[uncage(value = 2)]
+    Exploration,
+    #[uncage(value = 3)]
+    Ruins,
+    #[uncage(value = 4)]
+    Artifacts,
+    #[uncage(value = 5)]
+    Discoveries,
+    #[uncage(value = 6)]
+    Gold,
+    #[uncage(value = 7)]
+    TimeLimit,
+    #[uncage(value = 8)]
+    Score,
+    Unknown(u8),
+}
+
+/// `Entity.r#type`, the unit type of the game data (`Unit::Type` in genieutils, "Type" in
+/// Advanced Genie Editor)
+#[derive(ModelEnum, Debug, Copy, Clone, Eq, PartialEq)]
+pub enum EntityType {
+    #[uncage(value = 10)]
+    Static,
+    #[uncage(value = 15)]
+    Tree,
+    #[uncage(value = 20)]
+    Animated,
+    #[uncage(value = 25)]
+    Doppelganger,
+    #[uncage(value = 30)]
+    Moving,
+    #[uncage(value = 40)]
+    Action,
+    #[uncage(value = 50)]
+    Combat,
+    #[uncage(value = 60)]
+    Missile,
+    #[uncage(value = 70)]
+    Creatable,
+    #[uncage(value = 80)]
+    Building,
+    Unknown(i8),
+}
+
+/// `Action.r#type`, the task action types of the game data (`Task::ActionType` in genieutils)
+#[derive(ModelEnum, Debug, Copy, Clone, Eq, PartialEq)]
+pub enum ActionType {
+    #[uncage(value = 1)]
+    Move,
+    #[uncage(value = 2)]
+    Follow,
+    #[uncage(value = 3)]
+    Garrison,
+    #[uncage(value = 4)]
+    Explore,
+    #[uncage(value = 5)]
+    Gather,
+    #[uncage(value = 6)]
+    Graze,
+    #[uncage(value = 7)]
+    Attack,
+    #[uncage(value = 8)]
+    Missile,
+    #[uncage(value = 10)]
+    Fly,
+    #[uncage(value = 12)]
+    Unload,
+    #[uncage(value = 13)]
+    Guard,
+    #[uncage(value = 21)]
+    Make,
+    #[uncage(value = 101)]
+    Build,
+    #[uncage(value = 102)]
+    MakeObject,
+    #[uncage(value = 103)]
+    MakeTech,
+    #[uncage(value = 104)]
+    Convert,
+    #[uncage(value = 105)]
+    Heal,
+    #[uncage(value = 106)]
+    Repair,
+    #[uncage(value = 107)]
+    AutoConvert,
+    #[uncage(value = 108)]
+    Artifact,
+    #[uncage(value = 110)]
+    Hunt,
+    #[uncage(value = 111)]
+    Trade,
+    #[uncage(value = 120)]
+    Wonder,
+    #[uncage(value = 121)]
+    Farm,
+    #[uncage(value = 122)]
+    Loot,
+    #[uncage(value = 123)]
+    Housing,
+    #[uncage(value = 124)]
+    Pack,
+    #[uncage(value = 125)]
+    Unpack,
+    Unknown(i16),
+}
+
+/// `AttributeValue.r#type`, the first entries of the resource table of the game data
+#[derive(ModelEnum, Debug, Copy, Clone, Eq, PartialEq)]
+pub enum ResourceType {
+    #[uncage(value = 0)]
+    Food,
+    #[uncage(value = 1)]
+    Wood,
+    #[uncage(value = 2)]
+    Stone,
+    #[uncage(value = 3)]
+    Gold,
+    Unknown(i16),
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::enums::{ActionType, EntityType, VictorySetting, VictoryType};
+    use crate::model::{Action, ActionFields, Entity, EntityFields};
+    use crate::model::{GameOptions, GameOptionsFields, World, WorldFields};
+    use uncage_model::{Fields, ModelDescription};
+
+    /// Assigns a field from its bytes on the wire and writes it back
+    fn decode<M: ModelDescription>(model: &mut M, field: usize, wire: &[u8]) {
+        let description = model.get_field_description(field).unwrap();
+        description
+            .assign_value(model.get_field_mut(field).unwrap(), &mut &wire[..])
+            .unwrap();
+
+        let mut written = vec![];
+        description
+            .write_value(model.get_field(field).unwrap(), &mut written)
+            .unwrap();
+        assert_eq!(written, wire);
+    }
+
+    #[test]
+    pub fn test_codes_decode_with_their_storage_width() {
+        let mut world = World::default();
+        decode(&mut world, WorldFields::VictoryType.field(), &[1]);
+        assert_eq!(world.victory_type, VictoryType::Conquest);
+        decode(&mut world, WorldFields::VictoryType.field(), &[0xFF]);
+        assert_eq!(world.victory_type, VictoryType::Unknown(-1));
+
+        let mut options = GameOptions::default();
+        decode(&mut options, GameOptionsFields::VictoryType.field(), &[8]);
+        assert_eq!(options.victory_type, VictorySetting::Score);
+        decode(
+            &mut options,
+            GameOptionsFields::VictoryType.field(),
+            &[0xFF],
+        );
+        assert_eq!(options.victory_type, VictorySetting::Unknown(255));
+
+        let mut entity = Entity::default();
+        decode(&mut entity, EntityFields::Type.field(), &[80]);
+        assert_eq!(entity.r#type, EntityType::Building);
+        decode(&mut entity, EntityFields::Type.field(), &[0xFE]);
+        assert_eq!(entity.r#type, EntityType::Unknown(-2));
+
+        let mut action = Action::default();
+        decode(
+            &mut action,
+            ActionFields::Type.field(),
+            &101i16.to_le_bytes(),
+        );
+        assert_eq!(action.r#type, ActionType::Build);
+        decode(
+            &mut action,
+            ActionFields::Type.field(),
+            &(-7i16).to_le_bytes(),
+        );
+        assert_eq!(action.r#type, ActionType::Unknown(-7));
+    }
+}
@@ -1 +1,37 @@
+pub mod enums;
 pub mod model;
+
+/// Schema registry definition of the game builds, its base schema is the one of `model::Models`.
+/// Builds that change the schema are added as versions on top of it.
+pub const REGISTRY: &str = include_str!("../schemas/registry.json");
+
+#[cfg(test)]
+mod tests {
+    use crate::model::Models;
+    use crate::REGISTRY;
+    use uncage_model::registry::SchemaRegistry;
+    use uncage_model::ModelCollection;
+
+    #[test]
+    pub fn test_registry_matches_models() {
+        let registry = SchemaRegistry::from_json(REGISTRY).unwrap();
+        let schema = registry.resolve(u32::MAX).unwrap();
+
+        for model_type in Models::model_types() {
+            let model = Models::create_model(model_type).boxed();
+            let dynamic = schema.model(model_type).unwrap();
+            assert_eq!(dynamic.name(), model.get_model_name());
+
+            for field in model.get_fields() {
+                assert!(dynamic.field(field.index).is_some());
+            }
+
+            for actual in dynamic.fields() {
+                let expected = model.get_field_description(actual.index).unwrap();
+                assert_eq!(actual.field_name, expected.field_name);
+                assert_eq!(actual.value_type, expected.value_type);
+                assert_eq!(actual.field_type, expected.field_type);
+            }
+        }
+    }
+}
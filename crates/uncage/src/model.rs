@@ -1,7 +1,9 @@
+use crate::enums::{ActionType, EntityType, ResourceType, VictorySetting, VictoryType};
 use std::collections::BTreeMap;
 use uncage_model::{Model, ModelBTreeMap, ModelCollection, ModelHashMap, ModelRef, ModelVec, Ref};
 
 #[derive(ModelCollection, Debug)]
+#[allow(clippy::large_enum_variant)]
 pub enum Models {
     Root(Root),
     World(World),
@@ -61,7 +63,7 @@ pub struct Root {
 #[derive(Model, Debug, Default)]
 #[uncage(type = 1)]
 pub struct World {
-    #[uncage(index = 0)]
+    #[uncage(index = 0, unit = "milliseconds", interpolatable)]
     pub time: u32,
     #[uncage(index = 1)]
     pub entities: BTreeMap<i32, Ref>,
@@ -95,7 +97,7 @@ pub struct World {
     pub game_end_condition: u8,
     #[uncage(index = 16)]
     pub current_player_id: u8,
-    #[uncage(index = 17)]
+    #[uncage(index = 17, hidden_info)]
     pub unified_visible_map: Vec<u32>,
     #[uncage(index = 18)]
     pub last_damage_zone: i32,
@@ -113,8 +115,8 @@ pub struct World {
     pub countdown_clock: f32,
     #[uncage(index = 25)]
     pub countdown_victory: bool,
-    #[uncage(index = 26)]
-    pub victory_type: i8,
+    #[uncage(index = 26, enum = "i8")]
+    pub victory_type: VictoryType,
 }
 
 #[derive(Model, Debug, Default)]
@@ -280,13 +282,13 @@ pub struct VictoryPointEntry {
 #[derive(Model, Debug, Default)]
 #[uncage(type = 8)]
 pub struct PlayerAttributes {
-    #[uncage(index = 0)]
+    #[uncage(index = 0, hidden_info)]
     pub food: f32,
-    #[uncage(index = 1)]
+    #[uncage(index = 1, hidden_info)]
     pub wood: f32,
-    #[uncage(index = 2)]
+    #[uncage(index = 2, hidden_info)]
     pub stone: f32,
-    #[uncage(index = 3)]
+    #[uncage(index = 3, hidden_info)]
     pub gold: f32,
     #[uncage(index = 4)]
     pub max_pop: f32,
@@ -677,11 +679,11 @@ pub struct Entity {
     pub master_id: i16,
     #[uncage(index = 2)]
     pub owner_id: i8,
-    #[uncage(index = 3)]
+    #[uncage(index = 3, unit = "tiles", interpolatable)]
     pub world_x: f32,
-    #[uncage(index = 4)]
+    #[uncage(index = 4, unit = "tiles", interpolatable)]
     pub world_y: f32,
-    #[uncage(index = 5)]
+    #[uncage(index = 5, unit = "tiles", interpolatable)]
     pub world_z: f32,
     #[uncage(index = 6)]
     pub held_attribute_amount: f32,
@@ -693,9 +695,9 @@ pub struct Entity {
     pub sleep_flag: bool,
     #[uncage(index = 10)]
     pub dopple_flag: bool,
-    #[uncage(index = 11)]
-    pub r#type: i8,
-    #[uncage(index = 12)]
+    #[uncage(index = 11, enum = "i8")]
+    pub r#type: EntityType,
+    #[uncage(index = 12, interpolatable)]
     pub hp: f32,
     #[uncage(index = 13)]
     pub is_under_attack: bool,
@@ -996,8 +998,8 @@ pub struct MasterBuildingEntity {
 #[derive(Model, Debug, Default)]
 #[uncage(type = 22)]
 pub struct Action {
-    #[uncage(index = 0)]
-    pub r#type: i16,
+    #[uncage(index = 0, enum = "i16")]
+    pub r#type: ActionType,
     #[uncage(index = 1)]
     pub state: u8,
     #[uncage(index = 2)]
@@ -1205,8 +1207,8 @@ pub struct TechnologyPrerequisite {
 #[derive(Model, Debug, Default)]
 #[uncage(type = 33)]
 pub struct AttributeValue {
-    #[uncage(index = 0)]
-    pub r#type: i16,
+    #[uncage(index = 0, enum = "i16")]
+    pub r#type: ResourceType,
     #[uncage(index = 1)]
     pub amount: f32,
     #[uncage(index = 2)]
@@ -1265,8 +1267,8 @@ pub struct GameOptions {
     pub chosen_map_type: i16,
     #[uncage(index = 12)]
     pub visibility: u8,
-    #[uncage(index = 46)]
-    pub victory_type: u8,
+    #[uncage(index = 46, enum = "u8")]
+    pub victory_type: VictorySetting,
     #[uncage(index = 47)]
     pub victory_amount: i32,
     #[uncage(index = 13)]
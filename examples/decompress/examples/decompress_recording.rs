@@ -1,4 +1,3 @@
-use brotli;
 use std::fs::File;
 
 pub fn main() {